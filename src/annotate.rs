@@ -0,0 +1,122 @@
+use ab_glyph::{Font, FontRef, ScaleFont};
+
+use crate::lattice;
+
+/// Narrowest an annotated image is, so the text fits beside a small lattice
+const MIN_WIDTH: usize = 512;
+/// Font size of the text in the margin, in pixels
+const TEXT_SIZE: f32 = 15.0;
+const LINE_HEIGHT: usize = 20;
+const PADDING: usize = 8;
+/// Side of the coloured squares in the key
+const SWATCH_SIZE: usize = 12;
+
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const TEXT_COLOUR: [u8; 3] = [20, 20, 20];
+const SWATCH_BORDER: [u8; 3] = [128, 128, 128];
+
+/// What's written in the margin below an annotated image
+pub struct Annotation {
+    /// Colours in the image, each with what it stands for
+    pub key: Vec<([u8; 3], String)>,
+    /// Lines about the lattice, below the key
+    pub lines: Vec<String>,
+}
+
+/// RGB image of `width` × `height` pixels blown up by an integer factor to about MIN_WIDTH across,
+/// with a white margin below holding the key and lines of the annotation. Returns the pixels with
+/// the new width and height.
+pub fn annotate(rgb: &[u8], width: usize, height: usize, annotation: &Annotation) -> (Vec<u8>, usize, usize) {
+    let scale = (MIN_WIDTH / width.max(height)).max(1);
+    let scaled = lattice::scale_rgb(rgb, width, scale);
+    let (image_width, image_height) = (width * scale, height * scale);
+
+    let lines = annotation.lines.len() + usize::from(!annotation.key.is_empty());
+    let mut canvas = Canvas {
+        width: image_width.max(MIN_WIDTH),
+        height: image_height + 2 * PADDING + lines * LINE_HEIGHT,
+        rgb: Vec::new(),
+    };
+    canvas.rgb = BACKGROUND.repeat(canvas.width * canvas.height);
+    for (y, row) in scaled.chunks_exact(3 * image_width).enumerate() {
+        canvas.rgb[3 * y * canvas.width..3 * (y * canvas.width + image_width)].copy_from_slice(row);
+    }
+
+    // egui's own font, so there's nothing extra to ship
+    let fonts = egui::FontDefinitions::default();
+    let Some(Ok(font)) = fonts.font_data.get("Ubuntu-Light").map(|data| FontRef::try_from_slice(&data.font)) else {
+        return (canvas.rgb, canvas.width, canvas.height);
+    };
+
+    let mut top = image_height + PADDING;
+    if !annotation.key.is_empty() {
+        let mut x = PADDING;
+        for (colour, label) in &annotation.key {
+            let y = top + (LINE_HEIGHT - SWATCH_SIZE) / 2;
+            canvas.fill(x, y, SWATCH_SIZE, SWATCH_SIZE, SWATCH_BORDER);
+            canvas.fill(x + 1, y + 1, SWATCH_SIZE - 2, SWATCH_SIZE - 2, *colour);
+            x = canvas.text(&font, x + SWATCH_SIZE + 4, top, label) + 16;
+        }
+        top += LINE_HEIGHT;
+    }
+    for line in &annotation.lines {
+        canvas.text(&font, PADDING, top, line);
+        top += LINE_HEIGHT;
+    }
+
+    (canvas.rgb, canvas.width, canvas.height)
+}
+
+struct Canvas {
+    rgb: Vec<u8>,
+    width: usize,
+    height: usize,
+}
+
+impl Canvas {
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, colour: [u8; 3]) {
+        for y in y..(y + height).min(self.height) {
+            for x in x..(x + width).min(self.width) {
+                self.rgb[3 * (x + y * self.width)..3 * (x + y * self.width) + 3].copy_from_slice(&colour);
+            }
+        }
+    }
+
+    /// Write a line of text with the top of the line at `top`, clipped to the canvas, returns where
+    /// it ends
+    fn text(&mut self, font: &FontRef, x: usize, top: usize, text: &str) -> usize {
+        let scaled = font.as_scaled(TEXT_SIZE);
+        let baseline = top as f32 + 0.5 * (LINE_HEIGHT as f32 + scaled.ascent() + scaled.descent());
+
+        let mut caret = x as f32;
+        let mut previous = None;
+        for character in text.chars() {
+            let id = scaled.glyph_id(character);
+            if let Some(previous) = previous {
+                caret += scaled.kern(previous, id);
+            }
+            previous = Some(id);
+
+            let glyph = id.with_scale_and_position(TEXT_SIZE, ab_glyph::point(caret, baseline));
+            caret += scaled.h_advance(id);
+
+            let Some(outline) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|glyph_x, glyph_y, coverage| {
+                let (x, y) = (bounds.min.x as i64 + glyph_x as i64, bounds.min.y as i64 + glyph_y as i64);
+                if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+                    return;
+                }
+
+                let pixel = 3 * (x as usize + y as usize * self.width);
+                for (channel, text) in self.rgb[pixel..pixel + 3].iter_mut().zip(TEXT_COLOUR) {
+                    *channel = (*channel as f32 + coverage.clamp(0.0, 1.0) * (text as f32 - *channel as f32)) as u8;
+                }
+            });
+        }
+
+        caret as usize
+    }
+}
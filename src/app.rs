@@ -1,17 +1,318 @@
-use crate::{lattice::{Lattice, LatticeType, LatticeInitialState}, spin::Spin};
+use std::{collections::VecDeque, sync::Arc};
+
+use crate::{spin::Spin, lattice::{self, Lattice, ImageBuffer, SiteColouring, Palette, LatticeType, LatticeInitialState, LatticeSettings, SweepOrder, Boundary, LongRange, Dipolar, RandomField, FieldProfile, FieldMask}, worker::{Simulation, Command}, rng::{RngAlgorithm, LatticeRng}, measurement::{Measurements, Histogram, Estimate}, model::{self, Model}, geometry::Geometry, graph::{Graph, RandomGraph}, couplings::Couplings, fit, sweep::{self, TemperatureSweep, TemperatureScan, HistogramRun, MultiHistogram, SizeSweep, SweepObservable, CriticalExponent, DataCollapse, FieldSweep}, video::{VideoFormat, VideoRecorder}, annotate::{self, Annotation}};
 
 
 pub struct IsingApp {
-    size: usize,
+    // used for the next regenerated lattice
+    lattice_settings: LatticeSettings,
     fps: f32,
-    last_epoch: std::time::Instant,
-    lattice_type: LatticeType,
-    initial_state: LatticeInitialState,
+    sweeps_per_frame: usize,
+    // sweeps between samples
+    measure_interval: u64,
+    temperature: f32,
+    temperature_gradient: f32,
+    magnetic_field: f32,
+    transverse_field: f32,
+    field_profile: FieldProfile,
+    // last mask loaded, so the profile can be switched back to it
+    field_mask: Option<Arc<FieldMask>>,
+    sweep_order: SweepOrder,
+    simulation: Simulation,
+    // latest snapshot from the simulation thread
     lattice: Lattice,
+    // second lattice drawn beside the first, if running
+    comparison: Option<Comparison>,
+    sweeps: u64,
+    // acceptance rate of the latest batch
+    acceptance_rate: Option<f32>,
+    measurements: Measurements,
+    pause_when_equilibrated: bool,
+    // report an Ising lattice as a lattice gas, density and chemical potential instead of
+    // magnetisation and field
+    lattice_gas: bool,
+    // C(r) of the lattice as it was at correlation_sweep, recomputed every correlation_interval
+    // sweeps while its panel is open
+    correlation: Vec<f32>,
+    correlation_sweep: u64,
+    correlation_interval: u64,
+    // sizes of the domains as they were at cluster_sweep, as for the correlation function
+    cluster_sizes: Vec<usize>,
+    cluster_sweep: u64,
+    cluster_interval: u64,
+    // sweeps since the parameters changed and domain wall length of the samples since then, the
+    // walls of a quenched lattice shrink as t^-1/2
+    domain_wall_history: VecDeque<(u64, usize)>,
+    domain_wall_generation: u64,
+    domain_wall_log_log: bool,
+    // temperature the lattice is quenched to from a random state
+    quench_temperature: f32,
+    // sample number, magnetisation and energy per spin of the latest samples, plotted below the
+    // lattice
+    show_time_series: bool,
+    time_series: VecDeque<(u64, f32, f32)>,
+    time_series_count: u64,
+    // magnetisation of every sample since the histogram was last reset
+    magnetisation_histogram: Histogram,
+    // whether a replica runs alongside the lattice, the overlap of the latest sample and the
+    // distribution P(q) of every sample since the histogram was last reset
+    replica_overlap: bool,
+    overlap: Option<f32>,
+    overlap_histogram: Histogram,
+    temperature_sweep: TemperatureSweep,
+    // runs the sweep curves are extrapolated from by histogram reweighting, with every point of
+    // a sweep added if reweight_sweep
+    reweighting: MultiHistogram,
+    reweight_sweep: bool,
+    // ⟨|m|⟩ at every temperature visited, by the slider or a sweep
+    temperature_scan: TemperatureScan,
+    // the temperature sweep repeated at each side length of sizes_text, if several_sizes
+    size_sweep: SizeSweep,
+    several_sizes: bool,
+    sizes_text: String,
+    sweep_observable: SweepObservable,
+    field_sweep: FieldSweep,
+    // Tc, window of |T - Tc| / Tc and the exponent plotted by the critical exponent fits
+    fit_critical_temperature: f32,
+    fit_window: [f32; 2],
+    fit_exponent: CriticalExponent,
+    // axes the curves of a size sweep are rescaled by
+    data_collapse: DataCollapse,
+    // created once and updated in place when stale
     lattice_texture: Option<egui::TextureHandle>,
+    texture_stale: bool,
+    // pixels the lattice, comparison and structure factor textures are drawn into, kept between
+    // frames so that refreshing a texture doesn't allocate another image
+    texture_pixels: Vec<egui::Color32>,
+    site_colouring: SiteColouring,
+    // structure factor of the layer shown, drawn beside the lattice and rebuilt with its texture
+    show_structure_factor: bool,
+    // colours of up and down spins
+    palette: Palette,
+    // mark the sites which flipped in the latest sweep
+    show_last_flips: bool,
+    // side of the blocks averaged over in the coarse grained overlay, if shown
+    coarse_grain: Option<usize>,
+    structure_factor_texture: Option<egui::TextureHandle>,
+    // z slice shown for 3D lattices
+    view_layer: usize,
+    chain_view: ChainView,
+    // rows of the space-time diagram of a chain, oldest first
+    chain_history: VecDeque<Vec<u8>>,
+    // dragging over the lattice paints spins of paint_spin, within brush_radius sites of the
+    // pointer
+    paint_spins: bool,
+    paint_spin: Spin,
+    brush_shape: BrushShape,
+    brush_radius: usize,
+    // draw the bonds over the lattice, without editing them
+    show_bonds: bool,
+    // draw lines between cells, once they're large enough to be told apart
+    show_grid: bool,
+    // draw an arrow for each vector spin, once the cells are large enough
+    show_arrows: bool,
+    // magnification of the lattice image, and the point of it in the middle of the view as a
+    // fraction of its width and height
+    zoom: f32,
+    view_centre: egui::Pos2,
+    // clicking the lattice sets the couplings of bonds to paint_sign times their strength
+    edit_bonds: bool,
+    paint_sign: f32,
     paused: bool,
+    // sweeps done by the step-N button
+    step_count: usize,
     file_save_handle: Option<std::thread::JoinHandle<Option<std::path::PathBuf>>>,
+    graph_open_handle: Option<std::thread::JoinHandle<Option<std::path::PathBuf>>>,
+    couplings_save_handle: Option<std::thread::JoinHandle<Option<std::path::PathBuf>>>,
+    sweep_save_handle: Option<std::thread::JoinHandle<Option<std::path::PathBuf>>>,
+    svg_save_handle: Option<std::thread::JoinHandle<Option<std::path::PathBuf>>>,
+    // options of the export dialogue, open while choosing them: saved images get export_scale
+    // pixels per site and a margin with the key and parameters if annotate_images
+    export_open: bool,
+    export_format: ExportFormat,
+    export_scale: usize,
+    annotate_images: bool,
+    // captures the lattice after every gif_interval sweeps until there are gif_frames (fewer of
+    // a large image, see MAX_GIF_BYTES), then moves to gif_finished while the save dialogue is
+    // open, to be encoded on another thread
+    gif_recording: Option<GifRecording>,
+    gif_finished: Option<GifRecording>,
+    gif_frames: usize,
+    gif_interval: u64,
+    gif_save_handle: Option<std::thread::JoinHandle<Option<std::path::PathBuf>>>,
+    gif_encode_handle: Option<std::thread::JoinHandle<Result<(), image::ImageError>>>,
+    // video being recorded, with a frame taken every video_interval sweeps, and the sweeps when
+    // the latest one was
+    video: Option<VideoRecorder>,
+    video_last_sweeps: u64,
+    video_format: VideoFormat,
+    video_size: usize,
+    video_fps: u32,
+    video_interval: u64,
+    video_save_handle: Option<std::thread::JoinHandle<Option<std::path::PathBuf>>>,
+    video_encode_handle: Option<std::thread::JoinHandle<std::io::Result<()>>>,
+    couplings_open_handle: Option<std::thread::JoinHandle<Option<std::path::PathBuf>>>,
+    field_mask_open_handle: Option<std::thread::JoinHandle<Option<std::path::PathBuf>>>,
+    // model used by the generate graph button
+    random_graph: RandomGraph,
     alert: Option<Alert>,
+    #[cfg(feature = "gpu")]
+    use_gpu: bool,
+}
+
+/// Largest image, in sites, whose bonds can be edited
+const MAX_EDITABLE_SIZE: usize = 64;
+
+/// Smallest cell, in points, with grid lines drawn between cells
+const MIN_GRID_CELL: f32 = 8.0;
+
+/// Smallest cell, in points, with an arrow drawn for a vector spin
+const MIN_ARROW_CELL: f32 = 12.0;
+
+/// Furthest the lattice image can be zoomed in
+const MAX_ZOOM: f32 = 64.0;
+
+/// Longer side of the minimap shown while zoomed in, in points
+const MINIMAP_SIZE: f32 = 160.0;
+
+/// Fewest sweeps between samples while the GPU runs them, as each sample copies the spins back
+/// and analyses them on the CPU
+#[cfg(feature = "gpu")]
+const GPU_MEASURE_INTERVAL: u64 = 50;
+
+/// Most frames of an animated GIF
+const MAX_GIF_FRAMES: usize = 1000;
+
+/// Most memory the frames of an animated GIF can take up, as they're all kept until it's saved
+const MAX_GIF_BYTES: usize = 1 << 30;
+
+/// Size the frames of an animated GIF are scaled up towards, in pixels
+const GIF_SIZE: usize = 512;
+
+/// Filtering of the lattice textures, which are one texel per site: sharp edges between sites
+/// blown up on screen, and smoothed when there are more sites than pixels
+const LATTICE_TEXTURE_OPTIONS: egui::TextureOptions = egui::TextureOptions {
+    magnification: egui::TextureFilter::Nearest,
+    minification: egui::TextureFilter::Linear,
+};
+
+/// Longest side of a saved image, in pixels, which limits the pixels per site of large lattices
+const MAX_EXPORT_SIZE: usize = 16384;
+
+/// Longer side of a recorded video, in pixels
+const VIDEO_SIZES: [usize; 4] = [480, 720, 1080, 2160];
+
+/// Most points of the domain wall length kept for its plot
+const MAX_DOMAIN_WALL_HISTORY: usize = 10_000;
+
+/// Most couplings of a site listed in its tooltip
+const MAX_TOOLTIP_COUPLINGS: usize = 8;
+
+/// Fewer independent samples than this and the averages are flagged as untrustworthy
+const MIN_INDEPENDENT_SAMPLES: u64 = 20;
+
+/// Most samples kept for the time series plots
+const MAX_TIME_SERIES: usize = 2000;
+/// Height of each time series plot below the lattice
+const TIME_SERIES_HEIGHT: f32 = 100.0;
+
+/// Furthest distance the correlation function is plotted out to, in lattice spacings
+const MAX_CORRELATION_DISTANCE: usize = 64;
+
+/// Shape of the brush spins are painted with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BrushShape {
+    Circle,
+    Square,
+}
+
+/// Second lattice simulated alongside the first and drawn beside it. Its lattice settings follow
+/// the first lattice's, the temperature, field, sweep order and seed can be its own.
+struct Comparison {
+    simulation: Simulation,
+    // latest snapshot from its simulation thread
+    lattice: Lattice,
+    sweeps: u64,
+    texture: Option<egui::TextureHandle>,
+    texture_stale: bool,
+    // its own values, None to follow the first lattice
+    temperature: Option<f32>,
+    magnetic_field: Option<f32>,
+    sweep_order: Option<SweepOrder>,
+    // added to the first lattice's seed, 0 for the same initial state and couplings
+    seed_offset: u64,
+    // parameters last sent to its simulation thread
+    sent: ComparisonParameters,
+}
+
+/// Everything the second lattice's simulation thread is kept in step with
+#[derive(Debug, Clone, PartialEq)]
+struct ComparisonParameters {
+    temperature: f32,
+    temperature_gradient: f32,
+    magnetic_field: f32,
+    transverse_field: f32,
+    field_profile: FieldProfile,
+    sweep_order: SweepOrder,
+    track_activity: bool,
+    fps: f32,
+    sweeps_per_frame: usize,
+    paused: bool,
+}
+
+/// File formats the lattice image can be saved in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    Tiff,
+}
+
+impl ExportFormat {
+    const ALL: [ExportFormat; 4] = [ExportFormat::Png, ExportFormat::Jpeg, ExportFormat::Bmp, ExportFormat::Tiff];
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Jpeg => "jpg",
+            ExportFormat::Bmp => "bmp",
+            ExportFormat::Tiff => "tiff",
+        }
+    }
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::Png => f.write_str("PNG"),
+            ExportFormat::Jpeg => f.write_str("JPEG"),
+            ExportFormat::Bmp => f.write_str("BMP"),
+            ExportFormat::Tiff => f.write_str("TIFF"),
+        }
+    }
+}
+
+/// Images of the lattice captured for an animated GIF, one pixel per site
+struct GifRecording {
+    frames: Vec<Vec<u8>>,
+    width: usize,
+    height: usize,
+    // frames wanted, fewer if that many of this size would take up more than MAX_GIF_BYTES
+    max_frames: usize,
+    // time each frame is shown for, in milliseconds
+    frame_time: u32,
+    // sweeps when the latest frame was captured
+    last_sweeps: u64,
+}
+
+/// How 1D chains are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChainView {
+    /// Just the current state
+    Strip,
+    /// One row per frame, time running downward
+    SpaceTime,
 }
 
 enum Alert {
@@ -21,17 +322,136 @@ enum Alert {
 
 impl Default for IsingApp {
     fn default() -> Self {
+        let lattice_settings = LatticeSettings {
+            model: Model::Ising,
+            geometry: Geometry::Square,
+            width: 32,
+            height: 32,
+            depth: 32,
+            exchange: 1.0,
+            coupling_strengths: [1.0; 3],
+            next_nearest_coupling: 0.0,
+            axial_kappa: 0.0,
+            plaquette_coupling: 0.0,
+            long_range: None,
+            dipolar: None,
+            boundaries: [Boundary::Periodic; 3],
+            lattice_type: LatticeType::Ferromagnetic,
+            random_field: None,
+            p_vacancy: 0.0,
+            p_bond_dilution: 0.0,
+            initial_state: LatticeInitialState::Random,
+            seed: rand::random::<u32>() as u64,
+            rng_algorithm: RngAlgorithm::Xoshiro256PlusPlus,
+            graph: None,
+            trotter_slices: 1,
+        };
+        let lattice = Lattice::new(&lattice_settings, 1.0, 0.0);
+
         Self {
-            size: 32,
+            lattice_settings,
             fps: 10.0,
-            last_epoch: std::time::Instant::now(),
-            initial_state: LatticeInitialState::Random,
-            lattice_type: LatticeType::Ferromagnetic,
-            lattice: Lattice::new_random(32, 1.0, 0.0, LatticeType::Ferromagnetic),
+            sweeps_per_frame: 1,
+            measure_interval: 1,
+            temperature: 1.0,
+            temperature_gradient: 0.0,
+            magnetic_field: 0.0,
+            transverse_field: 1.0,
+            field_profile: FieldProfile::Uniform,
+            field_mask: None,
+            sweep_order: SweepOrder::Random,
+            simulation: Simulation::spawn(lattice.clone(), 10.0, 1),
+            lattice,
+            comparison: None,
+            sweeps: 0,
+            acceptance_rate: None,
+            measurements: Measurements::new(100, 100, 1000),
+            pause_when_equilibrated: false,
+            lattice_gas: false,
+            correlation: Vec::new(),
+            correlation_sweep: 0,
+            correlation_interval: 10,
+            cluster_sizes: Vec::new(),
+            cluster_sweep: 0,
+            cluster_interval: 10,
+            domain_wall_history: VecDeque::new(),
+            domain_wall_generation: 0,
+            domain_wall_log_log: false,
+            quench_temperature: 1.0,
+            show_time_series: false,
+            time_series: VecDeque::new(),
+            time_series_count: 0,
+            magnetisation_histogram: Histogram::new(-1.0, 1.0, 101),
+            replica_overlap: false,
+            overlap: None,
+            overlap_histogram: Histogram::new(-1.0, 1.0, 101),
+            temperature_sweep: TemperatureSweep::new(1.5, 3.5, 21, 1000),
+            temperature_scan: TemperatureScan::new(),
+            reweighting: MultiHistogram::new(),
+            reweight_sweep: false,
+            size_sweep: SizeSweep::new(Vec::new()),
+            several_sizes: false,
+            sizes_text: "16, 32, 64".into(),
+            sweep_observable: SweepObservable::Susceptibility,
+            field_sweep: FieldSweep::new(1.0, 40, 20),
+            fit_critical_temperature: 2.269,
+            fit_window: [0.02, 0.3],
+            fit_exponent: CriticalExponent::Beta,
+            data_collapse: DataCollapse { critical_temperature: 2.269, nu: 1.0, exponent: 1.75 },
             lattice_texture: None,
+            texture_pixels: Vec::new(),
+            texture_stale: true,
+            site_colouring: SiteColouring::State,
+            show_structure_factor: false,
+            coarse_grain: None,
+            show_last_flips: false,
+            palette: Palette::CLASSIC,
+            structure_factor_texture: None,
+            view_layer: 0,
+            chain_view: ChainView::SpaceTime,
+            chain_history: VecDeque::new(),
+            edit_bonds: false,
+            show_bonds: false,
+            show_grid: true,
+            show_arrows: true,
+            zoom: 1.0,
+            view_centre: egui::pos2(0.5, 0.5),
+            paint_spins: false,
+            paint_spin: Spin::Up,
+            brush_shape: BrushShape::Circle,
+            brush_radius: 4,
+            paint_sign: -1.0,
             paused: false,
+            step_count: 10,
             file_save_handle: None,
+            graph_open_handle: None,
+            couplings_save_handle: None,
+            sweep_save_handle: None,
+            svg_save_handle: None,
+            export_open: false,
+            export_format: ExportFormat::Png,
+            export_scale: 1,
+            annotate_images: false,
+            gif_recording: None,
+            gif_finished: None,
+            gif_frames: 100,
+            gif_interval: 1,
+            gif_save_handle: None,
+            gif_encode_handle: None,
+            video: None,
+            video_last_sweeps: 0,
+            video_format: VideoFormat::Mp4,
+            video_size: 720,
+            video_fps: 30,
+            video_interval: 1,
+            video_save_handle: None,
+            video_encode_handle: None,
+            couplings_open_handle: None,
+            field_mask_open_handle: None,
+            random_graph: RandomGraph::WattsStrogatz { nodes: 1024, neighbours: 4, rewiring: 0.1 },
             alert: None,
+            #[cfg(feature = "gpu")]
+            use_gpu: false,
         }
     }
 }
@@ -44,104 +464,2348 @@ impl IsingApp {
             ..Default::default()
         });
 
-        let mut fonts = egui::FontDefinitions::default();
-        fonts.font_data.insert("font_awesome".into(), egui::FontData::from_static(include_bytes!("../fonts/font_awesome_solid.otf")));
-        fonts.families
-            .entry(egui::FontFamily::Name("icons".into())).or_default()
-            .insert(0, "font_awesome".into());
+        let mut fonts = egui::FontDefinitions::default();
+        fonts.font_data.insert("font_awesome".into(), egui::FontData::from_static(include_bytes!("../fonts/font_awesome_solid.otf")));
+        fonts.families
+            .entry(egui::FontFamily::Name("icons".into())).or_default()
+            .insert(0, "font_awesome".into());
+
+        cc.egui_ctx.set_fonts(fonts);
+
+        Default::default()
+    }
+
+    /// Whether the lattice is being read as a lattice gas
+    fn is_lattice_gas(&self) -> bool {
+        self.lattice_gas && self.lattice.settings().model == Model::Ising
+    }
+
+    /// Whether the lattice is a chain drawn as a single row, rather than with its Trotter slices
+    fn is_strip(&self) -> bool {
+        self.lattice.settings().geometry == Geometry::Chain && self.lattice.image_size().1 == 1
+    }
+
+    /// Image of what's shown in the central panel, one pixel per site, returns the pixels with the
+    /// width and height
+    fn view_image(&self) -> (Vec<u8>, usize, usize) {
+        let mut rgb = Vec::new();
+        let (width, height) = self.view_image_into(&mut rgb);
+        (rgb, width, height)
+    }
+
+    /// view_image() into any image buffer, such as the pixels of a texture, returns the width and
+    /// height
+    fn view_image_into(&self, image: &mut impl ImageBuffer) -> (usize, usize) {
+        if self.is_strip() && self.chain_view == ChainView::SpaceTime && !self.chain_history.is_empty() {
+            let width = self.chain_history[0].len() / 3;
+            image.reset(width * self.chain_history.len());
+            for row in &self.chain_history {
+                for colour in row.chunks_exact(3) {
+                    image.push([colour[0], colour[1], colour[2]]);
+                }
+            }
+            (width, self.chain_history.len())
+        } else {
+            let (width, height) = self.lattice.image_into(image, self.view_layer, self.site_colouring, self.palette);
+            if let Some(block) = self.coarse_grain {
+                self.lattice.overlay_coarse_grained(image, self.view_layer, block);
+            }
+            if self.show_last_flips {
+                self.lattice.overlay_last_flips(image, self.view_layer);
+            }
+            (width, height)
+        }
+    }
+
+    /// Whether the simulation needs to keep track of which sites flip, for the activity colouring
+    /// or the overlay of the latest flips
+    fn track_activity(&self) -> bool {
+        self.site_colouring == SiteColouring::Activity || self.show_last_flips
+    }
+
+    /// Parameters the second lattice should be running with, its own where it has them and the
+    /// first lattice's otherwise
+    fn comparison_parameters(&self, comparison: &Comparison) -> ComparisonParameters {
+        ComparisonParameters {
+            temperature: comparison.temperature.unwrap_or(self.temperature),
+            temperature_gradient: self.temperature_gradient,
+            magnetic_field: comparison.magnetic_field.unwrap_or(self.magnetic_field),
+            transverse_field: self.transverse_field,
+            field_profile: self.field_profile.clone(),
+            sweep_order: comparison.sweep_order.unwrap_or(self.sweep_order),
+            track_activity: self.track_activity(),
+            fps: self.fps,
+            sweeps_per_frame: self.sweeps_per_frame,
+            paused: self.paused,
+        }
+    }
+
+    /// Fresh second lattice from the lattice settings and its parameters
+    fn comparison_lattice(&self, parameters: &ComparisonParameters, seed_offset: u64) -> Lattice {
+        let mut settings = self.lattice_settings.clone();
+        settings.seed = settings.seed.wrapping_add(seed_offset);
+
+        let mut lattice = Lattice::new(&settings, parameters.temperature, parameters.magnetic_field);
+        lattice.set_field_profile(&parameters.field_profile);
+        lattice.temperature_gradient = parameters.temperature_gradient;
+        lattice.transverse_field = parameters.transverse_field;
+        lattice.sweep_order = parameters.sweep_order;
+        lattice
+    }
+
+    /// Start running a second lattice beside the first
+    fn start_comparison(&mut self) {
+        let mut comparison = Comparison {
+            simulation: Simulation::spawn(self.lattice.clone(), self.fps, self.sweeps_per_frame),
+            lattice: self.lattice.clone(),
+            sweeps: 0,
+            texture: None,
+            texture_stale: true,
+            temperature: None,
+            magnetic_field: None,
+            sweep_order: None,
+            seed_offset: 0,
+            sent: ComparisonParameters {
+                temperature: 0.0,
+                temperature_gradient: 0.0,
+                magnetic_field: 0.0,
+                transverse_field: 0.0,
+                field_profile: FieldProfile::Uniform,
+                sweep_order: SweepOrder::Random,
+                track_activity: false,
+                fps: self.fps,
+                sweeps_per_frame: self.sweeps_per_frame,
+                paused: false,
+            },
+        };
+        let parameters = self.comparison_parameters(&comparison);
+        comparison.lattice = self.comparison_lattice(&parameters, comparison.seed_offset);
+        comparison.simulation.send(Command::Replace(Box::new(comparison.lattice.clone())));
+        self.comparison = Some(comparison);
+        self.sync_comparison();
+    }
+
+    /// Replace the second lattice with a new one, after the lattice settings or its seed change
+    fn regenerate_comparison(&mut self) {
+        let Some(comparison) = &self.comparison else {
+            return;
+        };
+        let parameters = self.comparison_parameters(comparison);
+        let lattice = self.comparison_lattice(&parameters, comparison.seed_offset);
+
+        let comparison = self.comparison.as_mut().expect("comparison was just checked");
+        comparison.simulation.send(Command::Replace(Box::new(lattice.clone())));
+        comparison.lattice = lattice;
+        comparison.sweeps = 0;
+        comparison.texture_stale = true;
+    }
+
+    /// Send the second lattice's simulation thread whichever parameters have changed
+    fn sync_comparison(&mut self) {
+        let Some(comparison) = &self.comparison else {
+            return;
+        };
+        let parameters = self.comparison_parameters(comparison);
+
+        let comparison = self.comparison.as_mut().expect("comparison was just checked");
+        let sent = &comparison.sent;
+        let simulation = &comparison.simulation;
+        if parameters.temperature != sent.temperature {
+            simulation.send(Command::Temperature(parameters.temperature));
+        }
+        if parameters.temperature_gradient != sent.temperature_gradient {
+            simulation.send(Command::TemperatureGradient(parameters.temperature_gradient));
+        }
+        if parameters.magnetic_field != sent.magnetic_field {
+            simulation.send(Command::MagneticField(parameters.magnetic_field));
+        }
+        if parameters.transverse_field != sent.transverse_field {
+            simulation.send(Command::TransverseField(parameters.transverse_field));
+        }
+        if parameters.field_profile != sent.field_profile {
+            simulation.send(Command::FieldProfile(parameters.field_profile.clone()));
+        }
+        if parameters.sweep_order != sent.sweep_order {
+            simulation.send(Command::SweepOrder(parameters.sweep_order));
+        }
+        if parameters.track_activity != sent.track_activity {
+            simulation.send(Command::TrackActivity(parameters.track_activity));
+        }
+        if (parameters.fps, parameters.sweeps_per_frame) != (sent.fps, sent.sweeps_per_frame) {
+            simulation.send(Command::Rate { fps: parameters.fps, sweeps_per_frame: parameters.sweeps_per_frame });
+        }
+        if parameters.paused != sent.paused {
+            simulation.send(Command::Paused(parameters.paused));
+        }
+        comparison.sent = parameters;
+    }
+
+    /// Image of the second lattice, in the same layer and colouring as the first, into any image
+    /// buffer. Returns the width and height.
+    fn comparison_image_into(&self, comparison: &Comparison, image: &mut impl ImageBuffer) -> (usize, usize) {
+        let layer = self.view_layer.min(comparison.lattice.layers() - 1);
+        let (width, height) = comparison.lattice.image_into(image, layer, self.site_colouring, self.palette);
+        if let Some(block) = self.coarse_grain {
+            comparison.lattice.overlay_coarse_grained(image, layer, block);
+        }
+        if self.show_last_flips {
+            comparison.lattice.overlay_last_flips(image, layer);
+        }
+        (width, height)
+    }
+
+    /// Settings needed to reproduce a saved image, as key/value pairs
+    fn image_metadata(&self) -> Vec<(&'static str, String)> {
+        let settings = self.lattice.settings();
+        vec![
+            ("Model", settings.model.to_string()),
+            ("Geometry", settings.geometry.to_string()),
+            ("Trotter Slices", self.lattice.trotter_slices().to_string()),
+            ("Boundary", settings.boundaries[..settings.geometry.dimensions()]
+                .iter()
+                .map(|boundary| boundary.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")),
+            ("Seed", self.lattice.seed().to_string()),
+            ("RNG", self.lattice.rng_algorithm().to_string()),
+            ("Sweeps", self.sweeps.to_string()),
+        ]
+    }
+
+    /// Image saved by the export dialogue, at its scale and annotated if asked for
+    fn export_image(&self) -> (Vec<u8>, usize, usize) {
+        let (data, width, height) = self.view_image();
+        let scale = self.export_scale.max(1);
+        let (data, width, height) = (lattice::scale_rgb(&data, width, scale), width * scale, height * scale);
+
+        if self.annotate_images {
+            annotate::annotate(&data, width, height, &self.image_annotation())
+        } else {
+            (data, width, height)
+        }
+    }
+
+    /// Format, scale and annotation of a saved image, then where to save it
+    fn export_ui(&mut self, ctx: &egui::Context) {
+        let mut open = self.export_open;
+        let mut save = false;
+
+        egui::Window::new("Save Image").open(&mut open).collapsible(false).resizable(false).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Format");
+                for format in ExportFormat::ALL {
+                    ui.radio_value(&mut self.export_format, format, format.to_string());
+                }
+            });
+            if self.export_format != ExportFormat::Png {
+                ui.weak("Only PNGs keep the seed and settings in the file");
+            }
+
+            let (width, height) = self.lattice.image_size();
+            let most = (MAX_EXPORT_SIZE / width.max(height)).clamp(1, 64);
+            self.export_scale = self.export_scale.min(most);
+            ui.horizontal(|ui| {
+                ui.label("Pixels per Site");
+                ui.add(egui::DragValue::new(&mut self.export_scale).clamp_range(1..=most));
+            });
+            ui.checkbox(&mut self.annotate_images, "Annotate")
+                .on_hover_text("Add a margin with the colour key, lattice, temperature, field and sweeps");
+
+            ui.weak(format!("{} × {} pixels before annotation", width * self.export_scale, height * self.export_scale));
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                save = ui.button("Save…").clicked();
+            });
+        });
+
+        self.export_open = open && !save;
+        if save {
+            let format = self.export_format;
+            self.file_save_handle = Some(std::thread::spawn(move || {
+                rfd::FileDialog::new()
+                    .add_filter(&format.to_string(), &[format.extension()])
+                    .set_file_name(&format!("lattice.{}", format.extension()))
+                    .set_title("Save Lattice Image")
+                    .save_file()
+            }));
+            self.set_paused(true);
+        }
+    }
+
+    /// Key and parameters written below a saved image
+    fn image_annotation(&self) -> Annotation {
+        let settings = self.lattice.settings();
+        let model = settings.model;
+
+        let mut key = match self.site_colouring {
+            SiteColouring::State if model == Model::Ising => vec![
+                (self.palette.down, "Spin Down".to_string()),
+                (self.palette.up, "Spin Up".to_string()),
+            ],
+            SiteColouring::State if !model.is_vector() => (0..model.state_count())
+                .map(|state| (model.colour(state as u8), format!("State {}", state)))
+                .collect(),
+            SiteColouring::Activity => vec![([0, 0, 0], "Frozen".to_string()), ([255, 255, 255], "Flipping Every Sweep".to_string())],
+            SiteColouring::Energy => vec![([0, 0, 0], "Low Energy".to_string()), ([255, 255, 255], "High Energy".to_string())],
+            _ => Vec::new(),
+        };
+        if settings.p_vacancy > 0.0 {
+            key.push(([200, 200, 200], "Vacancy".to_string()));
+        }
+        if self.show_last_flips {
+            key.push(([0, 255, 0], "Flipped in the Latest Sweep".to_string()));
+        }
+
+        let dimensions = settings.geometry.dimensions();
+        let size = [settings.width, settings.height, settings.depth][..dimensions]
+            .iter()
+            .map(|side| side.to_string())
+            .collect::<Vec<_>>()
+            .join(" × ");
+        let mut lattice = format!("{} model, {} {}, {}", model, settings.geometry, size, settings.lattice_type);
+        if self.lattice.trotter_slices() > 1 {
+            lattice += &format!(", {} Trotter slices", self.lattice.trotter_slices());
+        }
+
+        let mut environment = format!("T = {:.4}, B = {:.4}", self.lattice.temperature, self.lattice.magnetic_field);
+        if self.lattice.temperature_gradient != 0.0 {
+            environment += &format!(", ΔT = {:.4}", self.lattice.temperature_gradient);
+        }
+        if self.lattice.trotter_slices() > 1 {
+            environment += &format!(", Γ = {:.4}", self.lattice.transverse_field);
+        }
+
+        let mut view = format!("Coloured by {}", self.site_colouring.to_string().to_lowercase());
+        if self.lattice.layers() > 1 {
+            view += &format!(", layer {} of {}", self.view_layer + 1, self.lattice.layers());
+        }
+        if self.is_strip() && self.chain_view == ChainView::SpaceTime {
+            view += ", space-time diagram with time running downward";
+        }
+        if let Some(block) = self.coarse_grain {
+            view += &format!(", coarse grained over {} × {} blocks", block, block);
+        }
+
+        Annotation {
+            key,
+            lines: vec![
+                lattice,
+                environment,
+                format!("{} sweeps, seed {} ({})", self.sweeps, self.lattice.seed(), self.lattice.rng_algorithm()),
+                view,
+            ],
+        }
+    }
+
+    /// Start capturing frames of an animated GIF, shown at the current frame rate
+    fn start_gif(&mut self) {
+        let (frame, width, height) = self.view_image();
+        self.gif_recording = Some(GifRecording {
+            max_frames: self.gif_frames.min(MAX_GIF_BYTES / frame.len().max(1)).max(2),
+            frames: vec![frame],
+            width,
+            height,
+            frame_time: (1000.0 / self.fps) as u32,
+            last_sweeps: self.sweeps,
+        });
+    }
+
+    /// Capture the lattice if it's been at least gif_interval sweeps since the latest frame,
+    /// stopping once there are enough frames or the image changes size
+    fn record_gif_frame(&mut self) {
+        let Some(recording) = &self.gif_recording else {
+            return;
+        };
+        // the sweeps start again from 0 for a new lattice
+        let due = self.sweeps < recording.last_sweeps || self.sweeps >= recording.last_sweeps + self.gif_interval;
+        if !due {
+            return;
+        }
+
+        let (frame, width, height) = self.view_image();
+        let recording = self.gif_recording.as_mut().expect("recording was just checked");
+        if (width, height) != (recording.width, recording.height) {
+            self.finish_gif();
+            return;
+        }
+        recording.frames.push(frame);
+        recording.last_sweeps = self.sweeps;
+
+        if recording.frames.len() >= recording.max_frames {
+            self.finish_gif();
+        }
+    }
+
+    /// Stop recording and ask where to save the GIF
+    fn finish_gif(&mut self) {
+        self.gif_finished = self.gif_recording.take();
+        self.gif_save_handle = Some(std::thread::spawn(|| {
+            rfd::FileDialog::new()
+                .add_filter("GIF", &["gif"])
+                .set_file_name("lattice.gif")
+                .set_title("Save Animated GIF")
+                .save_file()
+        }));
+    }
+
+    /// Capture a video frame if it's been at least video_interval sweeps since the latest one,
+    /// finishing the video if the image changes size
+    fn record_video_frame(&mut self) {
+        if self.video.is_none() {
+            return;
+        }
+        // the sweeps start again from 0 for a new lattice
+        let due = self.sweeps < self.video_last_sweeps || self.sweeps >= self.video_last_sweeps + self.video_interval;
+        if !due {
+            return;
+        }
+
+        let (frame, width, height) = self.view_image();
+        let video = self.video.as_mut().expect("video was just checked");
+        if video.push(frame, width, height) {
+            self.video_last_sweeps = self.sweeps;
+        } else {
+            self.finish_video();
+        }
+    }
+
+    /// Stop recording, leaving the video to be finished on another thread
+    fn finish_video(&mut self) {
+        if let Some(video) = self.video.take() {
+            self.video_encode_handle = Some(video.finish());
+        }
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.simulation.send(Command::Paused(paused));
+    }
+
+    /// Approximate bytes held by the samples kept for the averages and plots
+    fn sample_memory_usage(&self) -> usize {
+        self.measurements.memory_usage()
+            + self.time_series.len() * std::mem::size_of::<(u64, f32, f32)>()
+            + self.domain_wall_history.len() * std::mem::size_of::<(u64, usize)>()
+    }
+
+    /// Scrolling plots of the magnetisation and energy of the latest samples, if shown
+    fn time_series_ui(&self, ui: &mut egui::Ui) {
+        if !self.show_time_series {
+            return;
+        }
+
+        let series = |value: fn(&(u64, f32, f32)) -> f32| -> egui::plot::PlotPoints {
+            self.time_series.iter().map(|sample| [sample.0 as f64, value(sample) as f64]).collect()
+        };
+
+        ui.add_space(8.0);
+        for (id, name, value) in [
+            ("magnetisation_series", "m", (|sample| sample.1) as fn(&(u64, f32, f32)) -> f32),
+            ("energy_series", "E", |sample| sample.2),
+        ] {
+            egui::plot::Plot::new(id)
+                .height(TIME_SERIES_HEIGHT)
+                .allow_drag(false)
+                .allow_zoom(false)
+                .allow_scroll(false)
+                .legend(egui::plot::Legend::default())
+                .show(ui, |plot_ui| plot_ui.line(egui::plot::Line::new(series(value)).name(name)));
+        }
+    }
+
+    /// Part of the lattice image in view, in texture coordinates
+    fn view_uv(&self) -> egui::Rect {
+        egui::Rect::from_center_size(self.view_centre, egui::Vec2::splat(1.0 / self.zoom))
+    }
+
+    /// Where the whole of the lattice image would be drawn for it to show in `rect` as zoomed, most
+    /// of it outside `rect` when zoomed in
+    fn image_rect(&self, rect: egui::Rect) -> egui::Rect {
+        let uv = self.view_uv();
+        let size = rect.size() / uv.size();
+        egui::Rect::from_min_size(rect.min - uv.min.to_vec2() * size, size)
+    }
+
+    /// Centre the view on a point of the image, as far as it can be without going past an edge
+    fn set_view_centre(&mut self, centre: egui::Pos2) {
+        let half = 0.5 / self.zoom;
+        self.view_centre = centre.clamp(egui::pos2(half, half), egui::pos2(1.0 - half, 1.0 - half));
+    }
+
+    /// Zoom around the pointer by scrolling over the lattice image, and pan by dragging it unless
+    /// dragging paints spins or bonds
+    fn zoom_ui(&mut self, ui: &egui::Ui, response: &egui::Response) {
+        let rect = response.rect;
+
+        if let Some(pointer) = response.hover_pos() {
+            let scroll = ui.input(|i| i.scroll_delta.y);
+            if scroll != 0.0 {
+                // keep the point under the pointer where it is
+                let fraction = (pointer - rect.min) / rect.size();
+                let uv = self.view_uv();
+                let under_pointer = uv.min + fraction * uv.size();
+
+                let zoom = (self.zoom * (0.002 * scroll).exp()).clamp(1.0, MAX_ZOOM);
+                self.zoom = zoom;
+                self.set_view_centre(under_pointer + (egui::Vec2::splat(0.5) - fraction) / zoom);
+            }
+        }
+
+        if self.zoom > 1.0 && !self.paint_spins && !self.edit_bonds && response.dragged() {
+            let uv = self.view_uv();
+            self.set_view_centre(self.view_centre - response.drag_delta() / rect.size() * uv.size());
+        }
+    }
+
+    /// Small copy of the whole lattice image in the bottom right corner while zoomed in, with the
+    /// part in view outlined. Clicking or dragging on it moves the view there.
+    fn minimap_ui(&mut self, ui: &mut egui::Ui, response: &egui::Response, texture: egui::TextureId, texture_size: [usize; 2]) {
+        if self.zoom <= 1.0 {
+            return;
+        }
+
+        let [width, height] = texture_size.map(|side| side as f32);
+        let size = egui::vec2(width, height) * MINIMAP_SIZE / width.max(height);
+        let map = egui::Rect::from_min_size(response.rect.max - size - egui::Vec2::splat(8.0), size);
+
+        let map_response = ui.put(map, egui::Image::new(texture, size).sense(egui::Sense::click_and_drag()))
+            .on_hover_text("Click or drag to move the view");
+        if let (true, Some(pointer)) = (map_response.clicked() || map_response.dragged(), map_response.interact_pointer_pos()) {
+            self.set_view_centre(((pointer - map.min) / size).to_pos2());
+        }
+
+        let uv = self.view_uv();
+        let viewport = egui::Rect::from_min_size(map.min + uv.min.to_vec2() * size, uv.size() * size);
+        let painter = ui.painter_at(map.expand(1.0));
+        painter.rect_stroke(map, 0.0, egui::Stroke::new(1.0, egui::Color32::from_gray(160)));
+        painter.rect_stroke(viewport, 0.0, egui::Stroke::new(2.0, egui::Color32::YELLOW));
+    }
+
+    /// Faint lines between the cells of the lattice image, if they're at least MIN_GRID_CELL across
+    fn grid_ui(&self, ui: &egui::Ui, response: &egui::Response, image_width: usize, image_height: usize) {
+        let rect = self.image_rect(response.rect);
+        let cell = egui::vec2(rect.width() / image_width as f32, rect.height() / image_height as f32);
+        if cell.x.min(cell.y) < MIN_GRID_CELL {
+            return;
+        }
+
+        let painter = ui.painter_at(response.rect);
+        let stroke = egui::Stroke::new(1.0, egui::Color32::from_black_alpha(60));
+
+        for x in 1..image_width {
+            let x = rect.min.x + x as f32 * cell.x;
+            painter.vline(x, rect.y_range(), stroke);
+        }
+        for y in 1..image_height {
+            let y = rect.min.y + y as f32 * cell.y;
+            painter.hline(rect.x_range(), y, stroke);
+        }
+    }
+
+    /// Arrow along the in-plane part of each vector spin in view, if the cells are at least
+    /// MIN_ARROW_CELL across. Heisenberg spins pointing out of the plane get short arrows.
+    fn arrows_ui(&self, ui: &egui::Ui, response: &egui::Response, image_width: usize, image_height: usize) {
+        let rect = self.image_rect(response.rect);
+        let cell = egui::vec2(rect.width() / image_width as f32, rect.height() / image_height as f32);
+        if cell.x.min(cell.y) < MIN_ARROW_CELL {
+            return;
+        }
+
+        let painter = ui.painter_at(response.rect);
+        let stroke = egui::Stroke::new(1.5, egui::Color32::from_black_alpha(200));
+
+        // only the sites in view
+        let uv = self.view_uv();
+        let columns = (uv.min.x * image_width as f32).floor() as usize..((uv.max.x * image_width as f32).ceil() as usize).min(image_width);
+        let rows = (uv.min.y * image_height as f32).floor() as usize..((uv.max.y * image_height as f32).ceil() as usize).min(image_height);
+
+        for y in rows {
+            for x in columns.clone() {
+                let Some([sx, sy, _]) = self.lattice.site_at(x, y, self.view_layer).and_then(|site| self.lattice.vector(site)) else {
+                    continue;
+                };
+
+                // y runs down the screen
+                let arrow = 0.8 * egui::vec2(sx * cell.x, -sy * cell.y);
+                let centre = rect.min + egui::vec2((x as f32 + 0.5) * cell.x, (y as f32 + 0.5) * cell.y);
+                painter.arrow(centre - 0.5 * arrow, arrow, stroke);
+            }
+        }
+    }
+
+    /// Draw the bonds over the lattice image, green for ferromagnetic and amber for
+    /// antiferromagnetic ones, and while editing paint the one nearest the pointer while the mouse
+    /// button is held
+    fn bonds_ui(&mut self, ui: &mut egui::Ui, response: &egui::Response, image_width: usize, image_height: usize) {
+        let rect = self.image_rect(response.rect);
+        let cell = egui::vec2(rect.width() / image_width as f32, rect.height() / image_height as f32);
+        let centre = |(x, y): (usize, usize)| rect.min + egui::vec2((x as f32 + 0.5) * cell.x, (y as f32 + 0.5) * cell.y);
+
+        let bonds = self.lattice.drawn_bonds(self.view_layer);
+        let painter = ui.painter_at(response.rect);
+
+        for bond in &bonds {
+            let [r, g, b] = bond_colour(bond.coupling);
+            let colour = egui::Color32::from_rgb(r, g, b);
+            let width = (cell.x.min(cell.y) * bond_width(bond.coupling)).max(1.0);
+            painter.line_segment([centre(bond.from), centre(bond.to)], egui::Stroke::new(width, colour));
+        }
+
+        if !self.edit_bonds || !response.is_pointer_button_down_on() {
+            return;
+        }
+        let Some(pointer) = response.interact_pointer_pos() else {
+            return;
+        };
+
+        // closest bond midpoint, within half a site
+        let nearest = bonds.iter()
+            .map(|bond| (bond, (centre(bond.from) + (centre(bond.to) - centre(bond.from)) * 0.5).distance(pointer)))
+            .filter(|(_, distance)| *distance < 0.5 * cell.x.min(cell.y))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        if let Some((bond, _)) = nearest {
+            let coupling = self.paint_sign * bond.strength;
+            if bond.coupling != coupling {
+                self.lattice.set_coupling(bond.a, bond.b, coupling);
+                self.simulation.send(Command::Replace(Box::new(self.lattice.clone())));
+            }
+        }
+    }
+
+    /// Paint the sites under the brush while the mouse button is held over the lattice image
+    fn paint_ui(&mut self, response: &egui::Response, image_width: usize, image_height: usize) {
+        if !response.is_pointer_button_down_on() {
+            return;
+        }
+        let Some(pointer) = response.interact_pointer_pos() else {
+            return;
+        };
+        let rect = self.image_rect(response.rect);
+        let centre = [
+            (pointer.x - rect.min.x) / rect.width() * image_width as f32,
+            (pointer.y - rect.min.y) / rect.height() * image_height as f32,
+        ];
+
+        let radius = self.brush_radius as f32;
+        let [low_x, low_y] = centre.map(|coordinate| (coordinate - radius).floor().max(0.0) as usize);
+        let (high_x, high_y) = (((centre[0] + radius) as usize).min(image_width - 1), ((centre[1] + radius) as usize).min(image_height - 1));
+
+        let mut sites = Vec::new();
+        for y in low_y..=high_y {
+            for x in low_x..=high_x {
+                let (dx, dy) = (x as f32 + 0.5 - centre[0], y as f32 + 0.5 - centre[1]);
+                let inside = match self.brush_shape {
+                    BrushShape::Circle => dx * dx + dy * dy <= radius * radius,
+                    BrushShape::Square => dx.abs() <= radius && dy.abs() <= radius,
+                };
+                if let (true, Some(site)) = (inside, self.lattice.site_at(x, y, self.view_layer)) {
+                    sites.push(site);
+                }
+            }
+        }
+
+        if !sites.is_empty() {
+            self.lattice.paint(&sites, self.paint_spin);
+            self.simulation.send(Command::Replace(Box::new(self.lattice.clone())));
+            self.texture_stale = true;
+        }
+    }
+
+    /// Site of the layer shown under the pointer, if it's over the lattice image
+    fn site_under_pointer(&self, response: &egui::Response, image_width: usize, image_height: usize) -> Option<usize> {
+        let pointer = response.hover_pos()?;
+        let rect = self.image_rect(response.rect);
+        let x = ((pointer.x - rect.min.x) / rect.width() * image_width as f32).floor();
+        let y = ((pointer.y - rect.min.y) / rect.height() * image_height as f32).floor();
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+
+        self.lattice.site_at(x as usize, y as usize, self.view_layer)
+    }
+
+    /// Tooltip about the site under the pointer, while it's over the lattice image
+    fn site_tooltip(&self, response: &egui::Response, image_width: usize, image_height: usize) {
+        let Some(site) = self.site_under_pointer(response, image_width, image_height) else {
+            return;
+        };
+
+        let info = self.lattice.site_info(site);
+        response.clone().on_hover_ui_at_pointer(|ui| {
+            let (x, y, layer) = info.position;
+            if self.lattice.layers() > 1 {
+                ui.label(format!("Site {} at ({}, {}, {})", site, x, y, layer));
+            } else {
+                ui.label(format!("Site {} at ({}, {})", site, x, y));
+            }
+            ui.label(format!("State: {}", info.state));
+            if self.lattice.settings().model == Model::Ising {
+                ui.label(format!("Local field: {:.3}", info.field));
+            } else {
+                ui.label(format!("Field: {:.3}", info.field));
+            }
+            ui.label(format!("Energy: {:.3}", info.energy));
+            if self.paused && !self.edit_bonds && !self.paint_spins {
+                ui.weak("Click to flip");
+            }
+
+            let couplings: Vec<String> = info.couplings.iter()
+                .take(MAX_TOOLTIP_COUPLINGS)
+                .map(|(neighbour, coupling)| format!("{}: {}", neighbour, coupling))
+                .collect();
+            let more = info.couplings.len().saturating_sub(MAX_TOOLTIP_COUPLINGS);
+            if more > 0 {
+                ui.label(format!("Couplings: {} and {} more", couplings.join(", "), more));
+            } else if !couplings.is_empty() {
+                ui.label(format!("Couplings: {}", couplings.join(", ")));
+            }
+        });
+    }
+
+    /// Replace the simulated lattice with a new one built from the lattice settings
+    fn regenerate(&mut self, _frame: &eframe::Frame) {
+        self.lattice = Lattice::new(&self.lattice_settings, self.temperature, self.magnetic_field);
+        self.lattice.set_field_profile(&self.field_profile);
+        self.lattice.temperature_gradient = self.temperature_gradient;
+        self.lattice.transverse_field = self.transverse_field;
+        self.lattice.sweep_order = self.sweep_order;
+        self.view_layer = self.view_layer.min(self.lattice.layers() - 1);
+        self.chain_history.clear();
+        self.correlation.clear();
+        self.magnetisation_histogram.clear();
+        self.time_series.clear();
+        self.overlap_histogram.clear();
+        self.cluster_sizes.clear();
+        self.sweeps = 0;
+        self.acceptance_rate = None;
+        self.texture_stale = true;
+
+        self.simulation.send(Command::Replace(Box::new(self.lattice.clone())));
+        self.regenerate_comparison();
+
+        #[cfg(feature = "gpu")]
+        self.update_gpu(_frame);
+    }
+
+    /// Regenerate with every side of the lattice `size` long
+    fn regenerate_at_size(&mut self, size: usize, frame: &eframe::Frame) {
+        self.lattice_settings.width = size;
+        self.lattice_settings.height = size;
+        self.lattice_settings.depth = size;
+        self.regenerate(frame);
+    }
+
+    /// Hand the simulation thread a GPU backend for the current lattice, or take it away.
+    #[cfg(feature = "gpu")]
+    fn update_gpu(&mut self, frame: &eframe::Frame) {
+        let gpu = match frame.wgpu_render_state() {
+            Some(render_state) if self.use_gpu && self.lattice.uniform_coupling().is_some() => {
+                Some(crate::gpu::GpuBackend::new(render_state, &self.lattice))
+            },
+            _ => None,
+        };
+
+        if gpu.is_some() && self.measure_interval < GPU_MEASURE_INTERVAL {
+            self.measure_interval = GPU_MEASURE_INTERVAL;
+            self.simulation.send(Command::MeasureInterval(self.measure_interval));
+        }
+        self.simulation.send(Command::Gpu(gpu));
+    }
+}
+
+impl eframe::App for IsingApp {
+    fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
+        if let Some(snapshot) = self.simulation.latest() {
+            self.lattice = snapshot.lattice;
+            self.sweeps = snapshot.sweeps;
+            self.acceptance_rate = snapshot.acceptance_rate;
+
+            if self.is_strip() {
+                let (mut row, width, _) = self.lattice.as_image_raw(0, self.site_colouring, self.palette);
+                if self.show_last_flips {
+                    self.lattice.overlay_last_flips(&mut row, 0);
+                }
+
+                self.chain_history.push_back(row);
+                // keep the diagram square
+                while self.chain_history.len() > width {
+                    self.chain_history.pop_front();
+                }
+            }
+
+            self.record_gif_frame();
+            self.record_video_frame();
+
+            let was_equilibrated = self.measurements.equilibrated_at().is_some();
+
+            for sample in &snapshot.samples {
+                self.measurements.record(sample);
+                if let Some(magnetic_field) = self.field_sweep.record(sample) {
+                    self.magnetic_field = magnetic_field;
+                    self.simulation.send(Command::MagneticField(magnetic_field));
+                }
+                self.magnetisation_histogram.add(sample.magnetisation);
+
+                self.time_series.push_back((self.time_series_count, sample.magnetisation, sample.energy));
+                self.time_series_count += 1;
+                if self.time_series.len() > MAX_TIME_SERIES {
+                    self.time_series.pop_front();
+                }
+                if let Some(overlap) = sample.overlap {
+                    self.overlap_histogram.add(overlap);
+                }
+                self.overlap = sample.overlap;
+
+                if sample.generation != self.domain_wall_generation {
+                    self.domain_wall_generation = sample.generation;
+                    self.domain_wall_history.clear();
+                }
+                self.domain_wall_history.push_back((sample.sweeps_since_change, sample.domain_wall_length));
+                if self.domain_wall_history.len() > MAX_DOMAIN_WALL_HISTORY {
+                    self.domain_wall_history.pop_front();
+                }
+            }
+
+            let was_sweeping = self.temperature_sweep.current().is_some();
+            let swept_points = self.temperature_sweep.points().len();
+            if let Some(temperature) = self.temperature_sweep.update(&self.measurements, self.lattice.spin_count(), self.lattice.image_size().0) {
+                self.temperature = temperature;
+                self.simulation.send(Command::Temperature(temperature));
+            }
+            // the measurements still hold the point just finished
+            if let (true, Some(point)) = (self.reweight_sweep, self.temperature_sweep.points().get(swept_points)) {
+                if let Some(run) = HistogramRun::new(&self.measurements, self.lattice.spin_count(), point.temperature) {
+                    self.reweighting.add(run);
+                }
+            }
+            // move on to the next size once the sweep at this one is done
+            if was_sweeping && self.temperature_sweep.current().is_none() {
+                if let Some(size) = self.size_sweep.finish(self.temperature_sweep.points()) {
+                    self.temperature = self.temperature_sweep.begin(&self.measurements);
+                    self.regenerate_at_size(size, _frame);
+                }
+            }
+            self.temperature_scan.update(&self.measurements, self.temperature);
+
+            if self.pause_when_equilibrated && !was_equilibrated && self.measurements.equilibrated_at().is_some() {
+                self.set_paused(true);
+            }
+            // force redraw
+            self.texture_stale = true;
+        }
+
+        if let Some(comparison) = &mut self.comparison {
+            if let Some(snapshot) = comparison.simulation.latest() {
+                comparison.lattice = snapshot.lattice;
+                comparison.sweeps = snapshot.sweeps;
+                comparison.texture_stale = true;
+            }
+        }
+        self.sync_comparison();
+
+        // save image
+        if self.file_save_handle.is_some() && self.file_save_handle.as_ref().expect("").is_finished() {
+            match self.file_save_handle.take().expect("").join() {
+                Ok(path) => if let Some(path) = path {
+                    let (data, width, height) = self.export_image();
+
+                    let saved = match self.export_format {
+                        ExportFormat::Png => save_png(&path, &data, width as u32, height as u32, &self.image_metadata()).map_err(|err| err.to_string()),
+                        format => save_image(&path, &data, width as u32, height as u32, format).map_err(|err| err.to_string()),
+                    };
+                    self.alert = match saved {
+                        Ok(_) => Some(Alert::Success("Image saved succesfully.".into())),
+                        Err(err) => Some(Alert::Error(format!("Failed to save image: {}", err))),
+                    };
+                },
+                Err(_) => {
+                    self.alert = Some(Alert::Error("Failed to open file save dialogue.".into()));
+                },
+            }
+        }
+
+        // save SVG
+        if self.svg_save_handle.is_some() && self.svg_save_handle.as_ref().expect("").is_finished() {
+            match self.svg_save_handle.take().expect("").join() {
+                Ok(path) => if let Some(path) = path {
+                    let (data, width, height) = self.view_image();
+                    let space_time = self.is_strip() && self.chain_view == ChainView::SpaceTime;
+                    let bonds = if self.show_bonds && !space_time { self.lattice.drawn_bonds(self.view_layer) } else { Vec::new() };
+
+                    self.alert = match save_svg(&path, &data, width, height, &bonds, &self.image_metadata()) {
+                        Ok(_) => Some(Alert::Success("SVG saved succesfully.".into())),
+                        Err(err) => Some(Alert::Error(format!("Failed to save SVG: {}", err))),
+                    };
+                },
+                Err(_) => {
+                    self.alert = Some(Alert::Error("Failed to open file save dialogue.".into()));
+                },
+            }
+        }
+
+        // encode GIF, once there's somewhere to save it
+        if self.gif_save_handle.is_some() && self.gif_save_handle.as_ref().expect("").is_finished() {
+            match self.gif_save_handle.take().expect("").join() {
+                Ok(path) => if let (Some(path), Some(recording)) = (path, self.gif_finished.take()) {
+                    self.gif_encode_handle = Some(std::thread::spawn(move || save_gif(&path, &recording)));
+                },
+                Err(_) => {
+                    self.alert = Some(Alert::Error("Failed to open file save dialogue.".into()));
+                },
+            }
+            self.gif_finished = None;
+        }
+        if self.gif_encode_handle.is_some() && self.gif_encode_handle.as_ref().expect("").is_finished() {
+            self.alert = match self.gif_encode_handle.take().expect("").join() {
+                Ok(Ok(_)) => Some(Alert::Success("GIF saved succesfully.".into())),
+                Ok(Err(err)) => Some(Alert::Error(format!("Failed to save GIF: {}", err))),
+                Err(_) => Some(Alert::Error("Failed to encode GIF.".into())),
+            };
+        }
+
+        // start a video, once there's somewhere to save it
+        if self.video_save_handle.is_some() && self.video_save_handle.as_ref().expect("").is_finished() {
+            match self.video_save_handle.take().expect("").join() {
+                Ok(path) => if let Some(path) = path {
+                    let (frame, width, height) = self.view_image();
+                    match VideoRecorder::start(&path, self.video_format, width, height, self.video_size, self.video_fps) {
+                        Ok(mut video) => {
+                            video.push(frame, width, height);
+                            self.video = Some(video);
+                            self.video_last_sweeps = self.sweeps;
+                        },
+                        Err(err) => self.alert = Some(Alert::Error(format!("Failed to start video: {}", err))),
+                    }
+                },
+                Err(_) => {
+                    self.alert = Some(Alert::Error("Failed to open file save dialogue.".into()));
+                },
+            }
+        }
+        if self.video_encode_handle.is_some() && self.video_encode_handle.as_ref().expect("").is_finished() {
+            self.alert = match self.video_encode_handle.take().expect("").join() {
+                Ok(Ok(_)) => Some(Alert::Success("Video saved succesfully.".into())),
+                Ok(Err(err)) => Some(Alert::Error(format!("Failed to save video: {}", err))),
+                Err(_) => Some(Alert::Error("Failed to encode video.".into())),
+            };
+        }
+
+        // save sweep
+        if self.sweep_save_handle.is_some() && self.sweep_save_handle.as_ref().expect("").is_finished() {
+            match self.sweep_save_handle.take().expect("").join() {
+                Ok(path) => if let Some(path) = path {
+                    let curves: Vec<(usize, &[sweep::SweepPoint])> = match self.size_sweep.curves() {
+                        [] => vec![(self.lattice.image_size().0, self.temperature_sweep.points())],
+                        curves => curves.iter().map(|(size, points)| (*size, points.as_slice())).collect(),
+                    };
+
+                    let settings = self.lattice.settings();
+                    let size = [settings.width, settings.height, settings.depth][..settings.geometry.dimensions()]
+                        .iter()
+                        .map(|side| side.to_string())
+                        .collect::<Vec<_>>()
+                        .join("x");
+                    let boundaries = settings.boundaries[..settings.geometry.dimensions()]
+                        .iter()
+                        .map(|boundary| boundary.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let description = format!(
+                        "seed {}, rng {}, geometry {}, size {}\n{} model, {}, boundaries {}, trotter slices {}",
+                        self.lattice.seed(), self.lattice.rng_algorithm(), settings.geometry, size,
+                        settings.model, settings.lattice_type, boundaries, self.lattice.trotter_slices(),
+                    );
+
+                    self.alert = match sweep::save_csv(&path, &description, &curves) {
+                        Ok(_) => Some(Alert::Success("Sweep saved succesfully.".into())),
+                        Err(err) => Some(Alert::Error(format!("Failed to save sweep: {}", err))),
+                    };
+                },
+                Err(_) => {
+                    self.alert = Some(Alert::Error("Failed to open file save dialogue.".into()));
+                },
+            }
+        }
+
+        // load graph
+        if self.graph_open_handle.is_some() && self.graph_open_handle.as_ref().expect("").is_finished() {
+            match self.graph_open_handle.take().expect("").join() {
+                Ok(path) => if let Some(path) = path {
+                    self.alert = match Graph::load(&path) {
+                        Ok(graph) => {
+                            let message = format!("Loaded graph with {} nodes and {} edges.", graph.node_count(), graph.edges.len());
+                            self.lattice_settings.graph = Some(Arc::new(graph));
+                            Some(Alert::Success(message))
+                        },
+                        Err(err) => Some(Alert::Error(format!("Failed to load graph: {}", err))),
+                    };
+                },
+                Err(_) => {
+                    self.alert = Some(Alert::Error("Failed to open file dialogue.".into()));
+                },
+            }
+        }
+
+        // save couplings
+        if self.couplings_save_handle.is_some() && self.couplings_save_handle.as_ref().expect("").is_finished() {
+            match self.couplings_save_handle.take().expect("").join() {
+                Ok(path) => if let Some(path) = path {
+                    let description = format!("{}, seed {}", self.lattice.settings().geometry, self.lattice.seed());
+
+                    self.alert = match self.lattice.couplings().save(&path, &description) {
+                        Ok(_) => Some(Alert::Success("Couplings saved succesfully.".into())),
+                        Err(err) => Some(Alert::Error(format!("Failed to save couplings: {}", err))),
+                    };
+                },
+                Err(_) => {
+                    self.alert = Some(Alert::Error("Failed to open file save dialogue.".into()));
+                },
+            }
+        }
+
+        // load couplings
+        if self.couplings_open_handle.is_some() && self.couplings_open_handle.as_ref().expect("").is_finished() {
+            match self.couplings_open_handle.take().expect("").join() {
+                Ok(path) => if let Some(path) = path {
+                    self.alert = match Couplings::load(&path) {
+                        Ok(couplings) if couplings.sites != self.lattice.spin_count() => Some(Alert::Error(format!(
+                            "Failed to load couplings: they are for {} sites, the lattice has {}.",
+                            couplings.sites,
+                            self.lattice.spin_count(),
+                        ))),
+                        Ok(couplings) => {
+                            self.lattice.load_couplings(&couplings);
+                            self.texture_stale = true;
+                            self.simulation.send(Command::Replace(Box::new(self.lattice.clone())));
+
+                            #[cfg(feature = "gpu")]
+                            self.update_gpu(_frame);
+
+                            Some(Alert::Success(format!("Loaded {} bonds.", couplings.bonds.len())))
+                        },
+                        Err(err) => Some(Alert::Error(format!("Failed to load couplings: {}", err))),
+                    };
+                },
+                Err(_) => {
+                    self.alert = Some(Alert::Error("Failed to open file dialogue.".into()));
+                },
+            }
+        }
+
+        // load field mask
+        if self.field_mask_open_handle.is_some() && self.field_mask_open_handle.as_ref().expect("").is_finished() {
+            match self.field_mask_open_handle.take().expect("").join() {
+                Ok(path) => if let Some(path) = path {
+                    self.alert = match FieldMask::load(&path) {
+                        Ok(mask) => {
+                            let (width, height) = mask.size();
+                            let mask = Arc::new(mask);
+                            self.field_mask = Some(mask.clone());
+                            self.field_profile = FieldProfile::Mask(mask);
+                            self.simulation.send(Command::FieldProfile(self.field_profile.clone()));
+                            Some(Alert::Success(format!("Loaded {} × {} field mask.", width, height)))
+                        },
+                        Err(err) => Some(Alert::Error(format!("Failed to load field mask: {}", err))),
+                    };
+                },
+                Err(_) => {
+                    self.alert = Some(Alert::Error("Failed to open file dialogue.".into()));
+                },
+            }
+        }
+
+        egui::SidePanel::left("side_panel").show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.heading("Ising Model");
+
+                ui.add_space(4.0);
+
+                egui::CollapsingHeader::new("Lattice").default_open(true).show(ui, |ui| {
+                    let settings = &mut self.lattice_settings;
+
+                    ui.label("Model");
+                    ui.horizontal_wrapped(|ui| {
+                        ui.radio_value(&mut settings.model, Model::Ising, "Ising");
+                        if ui.radio(matches!(settings.model, Model::Potts { .. }), "Potts").clicked() && !matches!(settings.model, Model::Potts { .. }) {
+                            settings.model = Model::Potts { q: 3 };
+                        }
+                        if ui.radio(matches!(settings.model, Model::Clock { .. }), "Clock").clicked() && !matches!(settings.model, Model::Clock { .. }) {
+                            settings.model = Model::Clock { q: 6 };
+                        }
+                        ui.radio_value(&mut settings.model, Model::XY, "XY");
+                        ui.radio_value(&mut settings.model, Model::Heisenberg, "Heisenberg");
+                        if ui.radio(matches!(settings.model, Model::BlumeCapel { .. }), "Blume-Capel").clicked() && !matches!(settings.model, Model::BlumeCapel { .. }) {
+                            settings.model = Model::BlumeCapel { crystal_field: 0.0 };
+                        }
+                    });
+                    match &mut settings.model {
+                        Model::Ising | Model::XY | Model::Heisenberg => {},
+                        Model::Potts { q } => {
+                            ui.add(egui::Slider::new(q, 2..=Model::MAX_STATES).text("q"))
+                                .on_hover_text("The transition of the square lattice is continuous up to q = 4 and first order above");
+                        },
+                        Model::Clock { q } => {
+                            ui.add(egui::Slider::new(q, 2..=Model::MAX_ANGLES).text("q"))
+                                .on_hover_text("Ising for q = 2, in two dimensions an intermediate critical phase opens up above q = 4");
+                        },
+                        Model::BlumeCapel { crystal_field } => {
+                            ui.add(egui::Slider::new(crystal_field, -2.0..=4.0).text("Δ"))
+                                .on_hover_text("Crystal field, the square lattice's tricritical point is near Δ = 1.97, T = 0.61");
+                        },
+                    }
+
+                    if settings.model == Model::Ising {
+                        ui.label("Trotter Slices");
+                        ui.add(egui::Slider::new(&mut settings.trotter_slices, 1..=64))
+                            .on_hover_text("Above 1, the transverse-field Ising model is simulated through the Suzuki-Trotter mapping, \
+                                with one copy of the lattice per slice of imaginary time (stacked vertically for a chain, as layers otherwise). \
+                                Not for mean field or graphs, energies are those of the classical model.");
+                    }
+
+                    ui.label("Geometry");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut settings.geometry, Geometry::Chain, "Chain (1D)");
+                        ui.radio_value(&mut settings.geometry, Geometry::Square, "Square");
+                        ui.radio_value(&mut settings.geometry, Geometry::Kagome, "Kagome");
+                        ui.radio_value(&mut settings.geometry, Geometry::Cubic, "Cubic (3D)");
+                        ui.radio_value(&mut settings.geometry, Geometry::MeanField, "Mean Field");
+                        ui.radio_value(&mut settings.geometry, Geometry::Graph, "Graph");
+                    });
+
+                    if settings.geometry == Geometry::Kagome {
+                        ui.label("Size (Unit Cells)");
+                    }
+
+                    if settings.geometry == Geometry::Graph {
+                        ui.horizontal(|ui| {
+                            match &settings.graph {
+                                Some(graph) => ui.label(format!("{} ({} nodes, {} edges)", graph.name, graph.node_count(), graph.edges.len())),
+                                None => ui.weak("No graph loaded"),
+                            };
+
+                            if ui.button("Load Graph").clicked() && self.graph_open_handle.is_none() {
+                                self.graph_open_handle = Some(std::thread::spawn(|| {
+                                    rfd::FileDialog::new()
+                                        .add_filter("Edge List", &["txt", "edges", "csv", "tsv"])
+                                        .add_filter("DOT", &["dot", "gv"])
+                                        .add_filter("JSON", &["json"])
+                                        .set_title("Load Graph")
+                                        .pick_file()
+                                }));
+                            }
+                        });
+
+                        let (nodes, mut neighbours, mut rewiring, mut mean_degree) = match self.random_graph {
+                            RandomGraph::WattsStrogatz { nodes, neighbours, rewiring } => (nodes, neighbours, rewiring, 4.0),
+                            RandomGraph::ErdosRenyi { nodes, mean_degree } => (nodes, 4, 0.1, mean_degree),
+                        };
+                        let mut nodes = nodes;
+
+                        ui.horizontal(|ui| {
+                            let small_world = matches!(self.random_graph, RandomGraph::WattsStrogatz { .. });
+                            if ui.radio(small_world, "Small World").clicked() {
+                                self.random_graph = RandomGraph::WattsStrogatz { nodes, neighbours, rewiring };
+                            }
+                            if ui.radio(!small_world, "Random (Erdos-Renyi)").clicked() {
+                                self.random_graph = RandomGraph::ErdosRenyi { nodes, mean_degree };
+                            }
+                        });
+
+                        ui.add(egui::Slider::new(&mut nodes, 2..=1 << 20).logarithmic(true).text("Nodes"));
+                        self.random_graph = match self.random_graph {
+                            RandomGraph::WattsStrogatz { .. } => {
+                                ui.add(egui::Slider::new(&mut neighbours, 2..=32).text("Neighbours"));
+                                ui.add(egui::Slider::new(&mut rewiring, 0.0..=1.0).logarithmic(true).text("Rewiring p"));
+                                RandomGraph::WattsStrogatz { nodes, neighbours, rewiring }
+                            },
+                            RandomGraph::ErdosRenyi { .. } => {
+                                ui.add(egui::Slider::new(&mut mean_degree, 0.0..=32.0).text("Mean Degree"));
+                                RandomGraph::ErdosRenyi { nodes, mean_degree }
+                            },
+                        };
+
+                        if ui.button("Generate Graph").clicked() {
+                            // from the lattice seed, so generated graphs are reproducible too
+                            let mut rng = LatticeRng::new(settings.rng_algorithm, settings.seed);
+                            settings.graph = Some(Arc::new(self.random_graph.generate(&mut rng)));
+                        }
+                    }
+
+                    let max_size = settings.geometry.max_size();
+                    let dimensions = settings.geometry.dimensions();
+
+                    for (name, side) in [("Width", &mut settings.width), ("Height", &mut settings.height), ("Depth", &mut settings.depth)].into_iter().take(dimensions) {
+                        *side = (*side).min(max_size);
+                        ui.label(if dimensions == 1 { "Length" } else { name });
+                        ui.add(egui::Slider::new(side, 1..=max_size).logarithmic(true));
+                    }
+
+                    if settings.geometry != Geometry::MeanField {
+                        ui.label("Boundary");
+                        for (axis, boundary) in ["x", "y", "z"].into_iter().zip(&mut settings.boundaries).take(dimensions) {
+                            egui::ComboBox::from_label(axis)
+                                .selected_text(boundary.to_string())
+                                .show_ui(ui, |ui| {
+                                    for option in Boundary::ALL {
+                                        ui.selectable_value(boundary, option, option.to_string());
+                                    }
+                                });
+                        }
+                    }
+
+                    {
+                        let p_antiferro = if let LatticeType::SpinGlass { p_antiferro } = &settings.lattice_type {
+                            *p_antiferro
+                        } else {
+                            0.5
+                        };
+
+                        let spin_glass = matches!(settings.lattice_type, LatticeType::SpinGlass { .. } | LatticeType::GaussianSpinGlass { .. });
+
+                        ui.radio_value(&mut settings.lattice_type, LatticeType::Ferromagnetic, "Ferromagnetic");
+                        ui.radio_value(&mut settings.lattice_type, LatticeType::Antiferromagnetic, "Antiferromagnetic");
+                        if ui.radio(spin_glass, "Spin Glass").clicked() && !spin_glass {
+                            settings.lattice_type = LatticeType::SpinGlass { p_antiferro };
+                        }
+
+                        if spin_glass {
+                            ui.horizontal(|ui| {
+                                ui.label("Couplings");
+                                ui.radio_value(&mut settings.lattice_type, LatticeType::SpinGlass { p_antiferro }, "±J");
+                                if ui.radio(matches!(settings.lattice_type, LatticeType::GaussianSpinGlass { .. }), "Gaussian").clicked() {
+                                    if let LatticeType::SpinGlass { .. } = settings.lattice_type {
+                                        settings.lattice_type = LatticeType::GaussianSpinGlass { sigma: 1.0 };
+                                    }
+                                }
+                            });
+                        }
+
+                        match &mut settings.lattice_type {
+                            LatticeType::SpinGlass { p_antiferro } => {
+                                ui.label("p Antiferromagnetic");
+                                ui.add(egui::Slider::new(p_antiferro, 0.0..=1.0));
+                            },
+                            LatticeType::GaussianSpinGlass { sigma } => {
+                                ui.label("σ");
+                                ui.add(egui::Slider::new(sigma, 0.0..=4.0));
+                            },
+                            _ => {},
+                        }
+                    }
+
+                    ui.label("Exchange Constant (J)");
+                    ui.add(egui::Slider::new(&mut settings.exchange, 0.0..=4.0))
+                        .on_hover_text("Multiplies every coupling, 0 leaves a paramagnet");
+
+                    ui.label("Coupling Strengths");
+                    for (name, strength) in settings.geometry.bond_directions().iter().zip(&mut settings.coupling_strengths) {
+                        ui.add(egui::Slider::new(strength, 0.0..=2.0).text(*name));
+                    }
+
+                    if let Some(critical_temperature) = exact_critical_temperature(settings) {
+                        ui.label(format!("Exact Tc: {:.4}", critical_temperature));
+                    }
+
+                    if settings.geometry != Geometry::MeanField {
+                        ui.label("Next-Nearest Coupling (J2)");
+                        ui.add(egui::Slider::new(&mut settings.next_nearest_coupling, -2.0..=2.0));
+
+                        if matches!(settings.geometry, Geometry::Chain | Geometry::Square | Geometry::Cubic) {
+                            ui.label("ANNNI κ");
+                            ui.add(egui::Slider::new(&mut settings.axial_kappa, 0.0..=1.5))
+                                .on_hover_text("Sites two apart along x are coupled by -κ Jx, modulated phases appear above κ = 0.5 at low temperature");
+                        }
+
+                        if settings.model == Model::Ising && matches!(settings.geometry, Geometry::Square | Geometry::Cubic) {
+                            ui.label("Plaquette Coupling (K)");
+                            ui.add(egui::Slider::new(&mut settings.plaquette_coupling, -2.0..=2.0))
+                                .on_hover_text("Adds -K s1 s2 s3 s4 for every elementary square, the dynamics turn glassy without any disorder");
+                        }
+
+                        if settings.geometry != Geometry::Kagome {
+                            let mut long_range = settings.long_range.is_some();
+                            ui.checkbox(&mut long_range, "Long-Range Interactions");
+
+                            settings.long_range = match (long_range, settings.long_range) {
+                                (true, None) => Some(LongRange { exponent: 2.0, cutoff: 4.0 }),
+                                (false, _) => None,
+                                (true, existing) => existing,
+                            };
+
+                            // the number of bonds per site grows as cutoff^dimensions
+                            let max_cutoff = match dimensions {
+                                1 => 64.0,
+                                2 => 8.0,
+                                _ => 4.0,
+                            };
+
+                            if let Some(long_range) = &mut settings.long_range {
+                                long_range.cutoff = long_range.cutoff.min(max_cutoff);
+                                ui.add(egui::Slider::new(&mut long_range.exponent, 0.0..=6.0).text("Exponent α"));
+                                ui.add(egui::Slider::new(&mut long_range.cutoff, 1.5..=max_cutoff).text("Cutoff"));
+                            }
+
+                            if matches!(settings.geometry, Geometry::Chain | Geometry::Square | Geometry::Cubic) {
+                                let mut dipolar = settings.dipolar.is_some();
+                                ui.checkbox(&mut dipolar, "Dipolar Interactions")
+                                    .on_hover_text("-g / r³ between every pair of sites within the cutoff. Against the exchange this makes stripes and bubbles, try g around J/3 at low temperature.");
+
+                                settings.dipolar = match (dipolar, settings.dipolar) {
+                                    (true, None) => Some(Dipolar { strength: 0.3, cutoff: 8.0 }),
+                                    (false, _) => None,
+                                    (true, existing) => existing,
+                                };
+
+                                if let Some(dipolar) = &mut settings.dipolar {
+                                    dipolar.cutoff = dipolar.cutoff.min(max_cutoff);
+                                    ui.add(egui::Slider::new(&mut dipolar.strength, 0.0..=2.0).text("Strength g"));
+                                    ui.add(egui::Slider::new(&mut dipolar.cutoff, 1.5..=max_cutoff).text("Cutoff"));
+                                }
+                            }
+                        }
+                    }
+                    
+                    {
+                        // keep the strength when switching between distributions
+                        let strength = match settings.random_field {
+                            Some(RandomField::Binary { strength }) => strength,
+                            Some(RandomField::Gaussian { width }) => width,
+                            None => 1.0,
+                        };
+
+                        ui.label("Random Field");
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut settings.random_field, None, "None");
+                            ui.radio_value(&mut settings.random_field, Some(RandomField::Binary { strength }), "±h");
+                            ui.radio_value(&mut settings.random_field, Some(RandomField::Gaussian { width: strength }), "Gaussian");
+                        });
+
+                        match &mut settings.random_field {
+                            Some(RandomField::Binary { strength }) => {
+                                ui.add(egui::Slider::new(strength, 0.0..=4.0).text("h"));
+                            },
+                            Some(RandomField::Gaussian { width }) => {
+                                ui.add(egui::Slider::new(width, 0.0..=4.0).text("σ"));
+                            },
+                            None => {},
+                        }
+                    }
+
+                    ui.label("p Vacancy");
+                    ui.add(egui::Slider::new(&mut settings.p_vacancy, 0.0..=1.0));
+
+                    ui.label("p Bond Dilution");
+                    ui.add(egui::Slider::new(&mut settings.p_bond_dilution, 0.0..=1.0));
+
+                    ui.label("Lattice Initial State");
+                    ui.radio_value(&mut settings.initial_state, LatticeInitialState::Random, "Random");
+                    ui.radio_value(&mut settings.initial_state, LatticeInitialState::AllUp, "All Spin Up");
+                    ui.radio_value(&mut settings.initial_state, LatticeInitialState::AllDown, "All Spin Down");
+
+                    ui.label("Seed");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut settings.seed));
+                        if ui.button("Randomise").clicked() {
+                            settings.seed = rand::random::<u32>() as u64;
+                        }
+                    });
+        
+                    let can_generate = settings.geometry != Geometry::Graph || settings.graph.is_some();
+
+                    if ui.add_enabled(can_generate, egui::Button::new("Regenerate Lattice")).clicked() {
+                        self.regenerate(_frame);
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Save Couplings").clicked() && self.couplings_save_handle.is_none() {
+                            self.couplings_save_handle = Some(std::thread::spawn(|| {
+                                rfd::FileDialog::new()
+                                    .add_filter("Couplings", &["txt"])
+                                    .set_file_name("couplings.txt")
+                                    .set_title("Save Couplings")
+                                    .save_file()
+                            }));
+                        }
+                        if ui.button("Load Couplings").clicked() && self.couplings_open_handle.is_none() {
+                            self.couplings_open_handle = Some(std::thread::spawn(|| {
+                                rfd::FileDialog::new()
+                                    .add_filter("Couplings", &["txt"])
+                                    .set_title("Load Couplings")
+                                    .pick_file()
+                            }));
+                        }
+                    }).response.on_hover_text("Bonds and local fields of every site, to reuse one disorder realisation. Loading needs a lattice with the same number of sites.");
+                });
+
+                ui.add_space(4.0);
+                
+                egui::CollapsingHeader::new("Environment").default_open(true).show(ui, |ui| {
+                    ui.label("Temperature");
+                    if ui.add(egui::Slider::new(&mut self.temperature, 0.0..=10.0)).changed() {
+                        self.simulation.send(Command::Temperature(self.temperature));
+                    }
+                    let exchange = self.lattice.settings().exchange;
+                    if exchange != 1.0 && exchange != 0.0 {
+                        ui.weak(format!("k_B T / J = {:.4}", self.temperature / exchange));
+                    }
+
+                    ui.label("Temperature Gradient");
+                    if ui.add(egui::Slider::new(&mut self.temperature_gradient, -10.0..=10.0))
+                        .on_hover_text("Temperature of the right edge minus that of the left, the middle stays at the temperature above")
+                        .changed()
+                    {
+                        self.simulation.send(Command::TemperatureGradient(self.temperature_gradient));
+                    }
+                    
+                    if self.is_lattice_gas() {
+                        ui.label("Chemical Potential (μ)");
+                        let mut chemical_potential = self.lattice.chemical_potential(self.magnetic_field);
+                        if ui.add(egui::Slider::new(&mut chemical_potential, -20.0..=20.0))
+                            .on_hover_text("Sets the magnetic field B = μ/2 + Σ J, half filling is at μ = -2 Σ J")
+                            .changed()
+                        {
+                            self.magnetic_field = self.lattice.field_for_chemical_potential(chemical_potential);
+                            self.simulation.send(Command::MagneticField(self.magnetic_field));
+                        }
+                    } else {
+                        ui.label("Magnetic Field");
+                        if ui.add(egui::Slider::new(&mut self.magnetic_field, -5.0..=5.0)).changed() {
+                            self.simulation.send(Command::MagneticField(self.magnetic_field));
+                        }
+                    }
+
+                    if self.lattice.trotter_slices() > 1 {
+                        ui.label("Transverse Field (Γ)");
+                        if ui.add(egui::Slider::new(&mut self.transverse_field, 0.0..=5.0)).changed() {
+                            self.simulation.send(Command::TransverseField(self.transverse_field));
+                        }
+                    }
+
+                    ui.label("Field Profile");
+                    let mut profile_changed = false;
+                    ui.horizontal_wrapped(|ui| {
+                        let profile = &mut self.field_profile;
+                        if ui.radio(*profile == FieldProfile::Uniform, "Uniform").clicked() && *profile != FieldProfile::Uniform {
+                            *profile = FieldProfile::Uniform;
+                            profile_changed = true;
+                        }
+                        if ui.radio(matches!(profile, FieldProfile::Gradient { .. }), "Gradient").clicked() && !matches!(profile, FieldProfile::Gradient { .. }) {
+                            *profile = FieldProfile::Gradient { angle: 0.0 };
+                            profile_changed = true;
+                        }
+                        if ui.radio(matches!(profile, FieldProfile::Sinusoidal { .. }), "Sinusoidal").clicked() && !matches!(profile, FieldProfile::Sinusoidal { .. }) {
+                            *profile = FieldProfile::Sinusoidal { wavelength: 16.0, angle: 0.0 };
+                            profile_changed = true;
+                        }
+                        if ui.radio(matches!(profile, FieldProfile::Circle { .. }), "Circle").clicked() && !matches!(profile, FieldProfile::Circle { .. }) {
+                            *profile = FieldProfile::Circle { radius: 0.5 };
+                            profile_changed = true;
+                        }
+                        if let Some(mask) = &self.field_mask {
+                            if ui.radio(matches!(profile, FieldProfile::Mask(_)), "Mask").clicked() && !matches!(profile, FieldProfile::Mask(_)) {
+                                *profile = FieldProfile::Mask(mask.clone());
+                                profile_changed = true;
+                            }
+                        }
+                    });
+
+                    match &mut self.field_profile {
+                        FieldProfile::Uniform | FieldProfile::Mask(_) => {},
+                        FieldProfile::Gradient { angle } => {
+                            profile_changed |= ui.drag_angle(angle).changed();
+                        },
+                        FieldProfile::Sinusoidal { wavelength, angle } => {
+                            profile_changed |= ui.add(egui::Slider::new(wavelength, 2.0..=256.0).logarithmic(true).text("Wavelength")).changed();
+                            profile_changed |= ui.drag_angle(angle).changed();
+                        },
+                        FieldProfile::Circle { radius } => {
+                            profile_changed |= ui.add(egui::Slider::new(radius, 0.0..=1.5).text("Radius")).changed();
+                        },
+                    }
+
+                    if ui.button("Load Mask").on_hover_text("Grayscale image stretched over the lattice, black for -B and white for B").clicked()
+                        && self.field_mask_open_handle.is_none()
+                    {
+                        self.field_mask_open_handle = Some(std::thread::spawn(|| {
+                            rfd::FileDialog::new()
+                                .add_filter("Image", &["png", "jpg", "jpeg", "bmp", "gif", "tif", "tiff"])
+                                .set_title("Load Field Mask")
+                                .pick_file()
+                        }));
+                    }
+
+                    if profile_changed {
+                        self.simulation.send(Command::FieldProfile(self.field_profile.clone()));
+                    }
+                });
+
+                ui.add_space(4.0);
+                
+                egui::CollapsingHeader::new("Simulation").default_open(true).show(ui, |ui| {
+                    let mut rate_changed = false;
+
+                    ui.label("Frames per Second");
+                    rate_changed |= ui.add(egui::Slider::new(&mut self.fps, 1.0..=60.0)).changed();
+
+                    ui.label("Sweeps per Frame");
+                    rate_changed |= ui.add(egui::Slider::new(&mut self.sweeps_per_frame, 1..=1000).logarithmic(true)).changed();
+
+                    if rate_changed {
+                        self.simulation.send(Command::Rate { fps: self.fps, sweeps_per_frame: self.sweeps_per_frame });
+                    }
+
+                    ui.label("Sweep Order");
+                    let mut order_changed = false;
+                    order_changed |= ui.radio_value(&mut self.sweep_order, SweepOrder::Random, "Random").changed();
+                    order_changed |= ui.radio_value(&mut self.sweep_order, SweepOrder::Sequential, "Sequential").changed();
+                    order_changed |= ui.radio_value(&mut self.sweep_order, SweepOrder::Checkerboard, "Checkerboard").changed();
+                    if order_changed {
+                        self.simulation.send(Command::SweepOrder(self.sweep_order));
+                    }
+                    if self.lattice.multispin_active() {
+                        ui.weak("Using bit-packed multispin kernel");
+                    }
+
+                    #[cfg(feature = "gpu")]
+                    if _frame.wgpu_render_state().is_some() {
+                        if ui.checkbox(&mut self.use_gpu, "GPU Compute").changed() {
+                            self.update_gpu(_frame);
+                        }
+                        if self.use_gpu && self.lattice.uniform_coupling().is_none() {
+                            ui.weak("Only periodic square lattices with an even side and uniform couplings run on the GPU");
+                        }
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Save Image").clicked() {
+                            self.export_open = true;
+                        }
+
+                        if ui.button("Save SVG")
+                            .on_hover_text("Save the lattice as a vector image, one square per site, with the bonds if they're shown")
+                            .clicked()
+                        {
+                            self.svg_save_handle = Some(std::thread::spawn(|| {
+                                rfd::FileDialog::new()
+                                    .add_filter("SVG", &["svg"])
+                                    .set_file_name("lattice.svg")
+                                    .set_title("Save Lattice SVG")
+                                    .save_file()
+                            }));
+                            self.set_paused(true);
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        if let Some(recording) = &self.gif_recording {
+                            ui.label(format!("Recording {}/{}", recording.frames.len(), recording.max_frames));
+                            if recording.max_frames < self.gif_frames {
+                                ui.weak("fewer frames")
+                                    .on_hover_text(format!("Frames this size would take up more than {} MB, the recording stops once they fill it", MAX_GIF_BYTES >> 20));
+                            }
+                            if ui.button("Stop").clicked() {
+                                self.finish_gif();
+                            }
+                        } else if self.gif_encode_handle.is_some() {
+                            ui.spinner();
+                            ui.label("Encoding GIF");
+                        } else {
+                            let button = ui.add_enabled(self.gif_save_handle.is_none(), egui::Button::new("Record GIF"))
+                                .on_hover_text("Capture the lattice as shown for an animated GIF, played back at the current frame rate");
+                            if button.clicked() {
+                                self.start_gif();
+                            }
+                            ui.add(egui::DragValue::new(&mut self.gif_frames).clamp_range(2..=MAX_GIF_FRAMES).suffix(" frames"));
+                            ui.add(egui::DragValue::new(&mut self.gif_interval).clamp_range(1..=100_000).prefix("every ").suffix(" sweeps"));
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        if let Some(video) = &self.video {
+                            ui.label(format!("Recording {} frames", video.frame_count()));
+                            if video.dropped_count() > 0 {
+                                ui.weak(format!("{} dropped", video.dropped_count()))
+                                    .on_hover_text("ffmpeg is encoding slower than frames are captured, record fewer or smaller frames");
+                            }
+                            if ui.button("Stop").clicked() {
+                                self.finish_video();
+                            }
+                        } else if self.video_encode_handle.is_some() {
+                            ui.spinner();
+                            ui.label("Finishing video");
+                        } else {
+                            let button = ui.add_enabled(self.video_save_handle.is_none(), egui::Button::new("Record Video"))
+                                .on_hover_text("Stream the lattice as shown to ffmpeg, which has to be installed, until stopped. Better than a GIF for long runs.");
+                            if button.clicked() {
+                                let format = self.video_format;
+                                self.video_save_handle = Some(std::thread::spawn(move || {
+                                    rfd::FileDialog::new()
+                                        .add_filter(&format.to_string(), &[format.extension()])
+                                        .set_file_name(&format!("lattice.{}", format.extension()))
+                                        .set_title("Save Video")
+                                        .save_file()
+                                }));
+                            }
+                            for format in VideoFormat::ALL {
+                                ui.radio_value(&mut self.video_format, format, format.to_string());
+                            }
+                        }
+                    });
+                    if self.video.is_none() && self.video_encode_handle.is_none() {
+                        ui.horizontal(|ui| {
+                            ui.label("Longer Side");
+                            for size in VIDEO_SIZES {
+                                ui.radio_value(&mut self.video_size, size, format!("{} px", size));
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add(egui::DragValue::new(&mut self.video_fps).clamp_range(1..=60).suffix(" fps"));
+                            ui.add(egui::DragValue::new(&mut self.video_interval).clamp_range(1..=100_000).prefix("frame every ").suffix(" sweeps"));
+                        });
+                    }
+                });
+
+                ui.add_space(4.0);
+
+                egui::CollapsingHeader::new("Comparison").default_open(false).show(ui, |ui| {
+                    let mut comparing = self.comparison.is_some();
+                    if ui.checkbox(&mut comparing, "Run a Second Lattice")
+                        .on_hover_text("Simulate another lattice with the same settings beside this one, at its own temperature, field or sweep order if given")
+                        .changed()
+                    {
+                        if comparing {
+                            self.start_comparison();
+                        } else {
+                            self.comparison = None;
+                        }
+                    }
+
+                    let mut regenerate = false;
+                    if let Some(comparison) = &mut self.comparison {
+                        let mut own_temperature = comparison.temperature.is_some();
+                        let mut temperature = comparison.temperature.unwrap_or(self.temperature);
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut own_temperature, "Own Temperature");
+                            ui.add_enabled(own_temperature, egui::DragValue::new(&mut temperature).speed(0.01).clamp_range(0.01..=10.0));
+                        });
+                        comparison.temperature = own_temperature.then_some(temperature);
+
+                        let mut own_field = comparison.magnetic_field.is_some();
+                        let mut magnetic_field = comparison.magnetic_field.unwrap_or(self.magnetic_field);
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut own_field, "Own Field");
+                            ui.add_enabled(own_field, egui::DragValue::new(&mut magnetic_field).speed(0.01).clamp_range(-5.0..=5.0));
+                        });
+                        comparison.magnetic_field = own_field.then_some(magnetic_field);
+
+                        let mut own_order = comparison.sweep_order.is_some();
+                        let mut sweep_order = comparison.sweep_order.unwrap_or(self.sweep_order);
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut own_order, "Own Sweep Order");
+                            ui.add_enabled_ui(own_order, |ui| {
+                                ui.radio_value(&mut sweep_order, SweepOrder::Random, "Random");
+                                ui.radio_value(&mut sweep_order, SweepOrder::Sequential, "Sequential");
+                                ui.radio_value(&mut sweep_order, SweepOrder::Checkerboard, "Checkerboard");
+                            });
+                        });
+                        comparison.sweep_order = own_order.then_some(sweep_order);
+
+                        ui.horizontal(|ui| {
+                            ui.label("Seed Offset");
+                            regenerate |= ui.add(egui::DragValue::new(&mut comparison.seed_offset))
+                                .on_hover_text("Added to the seed, 0 starts both lattices from the same state with the same couplings")
+                                .changed();
+                            regenerate |= ui.button("Restart").clicked();
+                        });
+
+                        let lattice = &comparison.lattice;
+                        ui.label(format!("T = {:.3}, B = {:.3}, {} sweeps", lattice.temperature, lattice.magnetic_field, comparison.sweeps));
+                        ui.label(format!("m = {:.4}, E/N = {:.4}", lattice.magnetisation(), lattice.internal_energy() / lattice.spin_count() as f32));
+                    }
+                    if regenerate {
+                        self.regenerate_comparison();
+                    }
+                });
+
+                ui.add_space(4.0);
+
+                egui::CollapsingHeader::new("Measurement").default_open(true).show(ui, |ui| {
+                    ui.label("Measure Every");
+                    ui.horizontal(|ui| {
+                        if ui.add(egui::DragValue::new(&mut self.measure_interval).clamp_range(1..=10_000)).changed() {
+                            self.simulation.send(Command::MeasureInterval(self.measure_interval));
+                        }
+                        ui.label("sweeps");
+                        #[cfg(feature = "gpu")]
+                        if self.use_gpu && self.measure_interval < GPU_MEASURE_INTERVAL {
+                            ui.weak("slows the GPU down, each sample copies the spins back");
+                        }
+                    }).response.on_hover_text("Sweeps between samples. Further apart samples are less correlated, so fewer are needed for the same error, and the autocorrelation time is counted in samples. Changing it restarts the averages.");
+
+                    ui.label("Thermalisation Sweeps");
+                    ui.add(egui::DragValue::new(&mut self.measurements.burn_in).clamp_range(0..=1_000_000))
+                        .on_hover_text("Sweeps after a parameter change which are left out of the averages");
+
+                    ui.label("Equilibration Window");
+                    ui.add(egui::DragValue::new(&mut self.measurements.equilibration_window).clamp_range(2..=100_000))
+                        .on_hover_text("Samples in each of the two windows whose mean energies are compared to detect equilibrium");
+                    ui.label("Fluctuation Window");
+                    ui.add(egui::DragValue::new(&mut self.measurements.fluctuation_window).clamp_range(2..=1_000_000))
+                        .on_hover_text("Latest samples the heat capacity and susceptibility are estimated from");
+                    ui.weak(format!("Samples kept: {:.1} KiB", self.sample_memory_usage() as f32 / 1024.0))
+                        .on_hover_text("Memory held by the fluctuation window, the equilibration windows, the binned averages and the plotted histories");
+                    ui.checkbox(&mut self.pause_when_equilibrated, "Pause when Equilibrated");
+                    ui.checkbox(&mut self.lattice_gas, "Lattice Gas")
+                        .on_hover_text("Read up spins as particles and down spins as empty sites, showing the density and chemical potential. Only for the Ising model.");
+
+                    if ui.button("Reset Averages").clicked() {
+                        self.measurements.clear();
+                    }
+                });
+
+                ui.add_space(4.0);
+
+                egui::CollapsingHeader::new("Temperature Sweep").default_open(false).show(ui, |ui| {
+                    let running = self.temperature_sweep.current().is_some();
+
+                    ui.add_enabled_ui(!running, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("From");
+                            ui.add(egui::DragValue::new(&mut self.temperature_sweep.start).speed(0.01).clamp_range(0.0..=10.0));
+                            ui.label("To");
+                            ui.add(egui::DragValue::new(&mut self.temperature_sweep.end).speed(0.01).clamp_range(0.0..=10.0));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Points");
+                            ui.add(egui::DragValue::new(&mut self.temperature_sweep.steps).clamp_range(2..=200));
+                            ui.label("Samples");
+                            ui.add(egui::DragValue::new(&mut self.temperature_sweep.samples).clamp_range(10..=1_000_000))
+                                .on_hover_text("Samples recorded at each temperature after the thermalisation sweeps, C and χ come from the last fluctuation window of them");
+                        });
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.several_sizes, "Sizes")
+                                .on_hover_text("Repeat the sweep on a regenerated lattice of each side length in turn, for finite-size scaling");
+                            ui.add_enabled(self.several_sizes, egui::TextEdit::singleline(&mut self.sizes_text).desired_width(100.0));
+                        });
+                    });
+
+                    ui.horizontal(|ui| {
+                        if running {
+                            if ui.button("Stop Sweep").clicked() {
+                                self.temperature_sweep.stop();
+                                self.size_sweep.stop();
+                            }
+                        } else if ui.button("Start Sweep").clicked() {
+                            self.size_sweep.clear();
+                            let mut size = None;
+                            if self.several_sizes {
+                                // sizes the sliders couldn't reach either are left out
+                                let max_size = self.lattice_settings.geometry.max_size();
+                                let (sizes, dropped): (Vec<&str>, Vec<&str>) = self.sizes_text.split([',', ' '])
+                                    .map(str::trim)
+                                    .filter(|size| !size.is_empty())
+                                    .partition(|size| size.parse::<usize>().is_ok_and(|size| (2..=max_size).contains(&size)));
+                                if !dropped.is_empty() {
+                                    self.alert = Some(Alert::Error(format!("Left out sizes which aren't from 2 to {}: {}", max_size, dropped.join(", "))));
+                                }
+                                self.size_sweep.sizes = sizes.iter().filter_map(|size| size.parse().ok()).collect();
+                                size = self.size_sweep.begin();
+                            }
+
+                            self.temperature = self.temperature_sweep.begin(&self.measurements);
+                            match size {
+                                Some(size) => self.regenerate_at_size(size, _frame),
+                                None => self.simulation.send(Command::Temperature(self.temperature)),
+                            }
+                        }
+                        if !running && !self.temperature_sweep.points().is_empty()
+                            && ui.button("Save CSV").clicked() && self.sweep_save_handle.is_none()
+                        {
+                            self.sweep_save_handle = Some(std::thread::spawn(|| {
+                                rfd::FileDialog::new()
+                                    .add_filter("CSV", &["csv"])
+                                    .set_file_name("sweep.csv")
+                                    .set_title("Save Sweep")
+                                    .save_file()
+                            }));
+                        }
+                        if let Some(step) = self.temperature_sweep.current() {
+                            match self.size_sweep.current() {
+                                Some(size) => ui.weak(format!("L = {}, point {} of {}", size, step + 1, self.temperature_sweep.steps)),
+                                None => ui.weak(format!("Point {} of {}", step + 1, self.temperature_sweep.steps)),
+                            };
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Reweight This Run")
+                            .on_hover_text("Keep the fluctuation window of the run at this temperature and extrapolate every observable to the temperatures around it by histogram reweighting. Runs at several temperatures are combined.")
+                            .clicked()
+                        {
+                            match HistogramRun::new(&self.measurements, self.lattice.spin_count(), self.temperature) {
+                                Some(run) => self.reweighting.add(run),
+                                None => self.alert = Some(Alert::Error("Reweighting needs at least 100 samples in the fluctuation window.".into())),
+                            }
+                        }
+                        ui.checkbox(&mut self.reweight_sweep, "Every Sweep Point")
+                            .on_hover_text("Add the run at each point of a temperature sweep as it finishes");
+                    });
+                    if let Some((low, high)) = self.reweighting.range() {
+                        ui.horizontal(|ui| {
+                            ui.weak(format!("{} runs, trusted from T = {:.3} to {:.3}", self.reweighting.runs().len(), low, high));
+                            if ui.button("Clear").clicked() {
+                                self.reweighting.clear();
+                            }
+                        });
+                    }
+
+                    ui.horizontal_wrapped(|ui| {
+                        for observable in SweepObservable::ALL {
+                            ui.radio_value(&mut self.sweep_observable, observable, observable.to_string());
+                        }
+                    });
+
+                    if self.sweep_observable == SweepObservable::AutocorrelationTime {
+                        ui.weak("Local updates slow down near Tc, where τ grows as L^z with z ≈ 2.17 for Metropolis in 2D.");
+                    }
+
+                    let observable = self.sweep_observable;
+                    let points = self.temperature_sweep.points();
+                    let curves = self.size_sweep.curves();
+                    // the sweep in progress, or the only one
+                    let show_points = curves.is_empty() || self.size_sweep.current().is_some();
+                    let exact = exact_critical_temperature(self.lattice.settings());
+                    let binder_crossings: Vec<(usize, usize, f32)> = curves.windows(2)
+                        .flat_map(|pair| {
+                            let ((small, a), (large, b)) = (&pair[0], &pair[1]);
+                            sweep::crossings(a, b, SweepObservable::BinderCumulant).into_iter().map(|temperature| (*small, *large, temperature))
+                        })
+                        .collect();
+
+                    egui::plot::Plot::new("temperature_sweep")
+                        .height(160.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .legend(egui::plot::Legend::default())
+                        .show(ui, |plot_ui| {
+                            for (index, (size, points)) in curves.iter().enumerate() {
+                                plot_sweep_curve(plot_ui, points, observable, &format!("L = {}", size), index);
+                            }
+                            if show_points {
+                                let name = match self.size_sweep.current() {
+                                    Some(size) => format!("L = {}", size),
+                                    None => observable.to_string(),
+                                };
+                                plot_sweep_curve(plot_ui, points, observable, &name, curves.len());
+                            }
+                            if observable == SweepObservable::BinderCumulant {
+                                for (_, _, temperature) in &binder_crossings {
+                                    plot_ui.vline(egui::plot::VLine::new(*temperature as f64).name("Binder crossing"));
+                                }
+                            }
+                            if !self.reweighting.runs().is_empty() {
+                                let line: Vec<[f64; 2]> = self.reweighting.curve().iter()
+                                    .filter_map(|point| Some([point.temperature as f64, observable.of(point)?.value as f64]))
+                                    .collect();
+                                plot_ui.line(egui::plot::Line::new(line).style(egui::plot::LineStyle::dashed_dense()).name("Reweighted"));
+                            }
+                            if let Some(exact) = exact {
+                                plot_ui.vline(egui::plot::VLine::new(exact as f64).style(egui::plot::LineStyle::dashed_loose()).name("Exact Tc"));
+                            }
+                        });
+
+                    if show_points {
+                        match sweep::peak_temperature(points, |point| point.susceptibility) {
+                            Some(peak) => ui.label(format!("Tc from χ peak: {:.3}", peak)),
+                            None => ui.label("Tc from χ peak: -"),
+                        }.on_hover_text("Vertex of a parabola through the highest χ and its neighbours, the error propagated from theirs. Shifts towards the exact value as the lattice grows.");
+                        match sweep::peak_temperature(points, |point| point.heat_capacity) {
+                            Some(peak) => ui.label(format!("Tc from C peak: {:.3}", peak)),
+                            None => ui.label("Tc from C peak: -"),
+                        };
+                    }
+                    for (size, points) in curves {
+                        if let Some(peak) = sweep::peak_temperature(points, |point| point.susceptibility) {
+                            ui.label(format!("L = {}: Tc from χ peak {:.3}", size, peak));
+                        }
+                    }
+                    for (small, large, temperature) in &binder_crossings {
+                        ui.label(format!("U4 of L = {} and {} cross at T = {:.3}", small, large, temperature))
+                            .on_hover_text("Binder cumulants of different sizes cross close to Tc, with a much smaller finite-size shift than the peaks");
+                    }
+                    if let Some(exact) = exact {
+                        ui.weak(format!("Exact Tc: {:.4}", exact));
+                    }
+                });
+
+                ui.add_space(4.0);
+
+                egui::CollapsingHeader::new("Magnetisation vs Temperature").default_open(false).show(ui, |ui| {
+                    let points = self.temperature_scan.points();
+                    let highest = points.iter().map(|(temperature, _)| *temperature).fold(0.0, f32::max);
+                    let exact_tc = exact_critical_temperature(self.lattice.settings());
+                    let end = exact_tc.map_or(highest, |tc| highest.max(1.5 * tc)).max(0.1);
+                    let exact: Option<Vec<[f64; 2]>> = (1..=200)
+                        .map(|step| {
+                            let temperature = end * step as f32 / 200.0;
+                            Some([temperature as f64, exact_magnetisation(self.lattice.settings(), temperature)? as f64])
+                        })
+                        .collect();
+
+                    egui::plot::Plot::new("temperature_scan")
+                        .height(160.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .include_y(0.0)
+                        .include_y(1.0)
+                        .legend(egui::plot::Legend::default())
+                        .show(ui, |plot_ui| {
+                            if let Some(exact) = exact {
+                                plot_ui.line(egui::plot::Line::new(exact).style(egui::plot::LineStyle::dashed_loose()).name("Onsager"));
+                            }
+                            let scatter: Vec<[f64; 2]> = points.iter().map(|(temperature, magnetisation)| [*temperature as f64, magnetisation.value as f64]).collect();
+                            plot_ui.points(egui::plot::Points::new(scatter).radius(3.0).name("⟨|m|⟩"));
+                        });
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Clear").clicked() {
+                            self.temperature_scan.clear();
+                        }
+                        ui.weak(format!("{} temperatures", self.temperature_scan.points().len()));
+                    }).response.on_hover_text("A point is recorded at each temperature the simulation is left at, move the temperature slider to add more");
+                });
+
+                ui.add_space(4.0);
+
+                egui::CollapsingHeader::new("Critical Exponents").default_open(false).show(ui, |ui| {
+                    // the sweep in progress or the last one finished, usually the largest size
+                    let points = match self.size_sweep.curves().last() {
+                        Some((_, points)) if self.size_sweep.current().is_none() => points.as_slice(),
+                        _ => self.temperature_sweep.points(),
+                    };
+                    let exact = exact_critical_temperature(self.lattice.settings());
+
+                    ui.horizontal(|ui| {
+                        ui.label("Tc");
+                        ui.add(egui::DragValue::new(&mut self.fit_critical_temperature).speed(0.001).clamp_range(0.01..=10.0));
+                        if let Some(exact) = exact {
+                            if ui.button("Exact").clicked() {
+                                self.fit_critical_temperature = exact;
+                            }
+                        }
+                        if let Some(peak) = sweep::peak_temperature(points, |point| point.susceptibility) {
+                            if ui.button("χ Peak").clicked() {
+                                self.fit_critical_temperature = peak.value;
+                            }
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("|t| from");
+                        ui.add(egui::DragValue::new(&mut self.fit_window[0]).speed(0.001).clamp_range(0.0..=1.0));
+                        ui.label("to");
+                        ui.add(egui::DragValue::new(&mut self.fit_window[1]).speed(0.001).clamp_range(0.0..=1.0));
+                    }).response.on_hover_text("Window of reduced temperature |T - Tc| / Tc fitted, too close to Tc the finite size rounds off the power laws");
+
+                    let ising = self.lattice.settings().model == Model::Ising && exact.is_some();
+                    for exponent in CriticalExponent::ALL {
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut self.fit_exponent, exponent, "");
+                            match exponent.fit(points, self.fit_critical_temperature, self.fit_window) {
+                                Some((_, value)) => ui.label(format!("{} = {:.3}", exponent, value)),
+                                None => ui.label(format!("{}: -", exponent)),
+                            };
+                            if ising {
+                                ui.weak(format!("(exact {})", exponent.ising_2d()));
+                            }
+                        });
+                    }
+
+                    let log_points = self.fit_exponent.log_points(points, self.fit_critical_temperature, self.fit_window);
+                    let fit = self.fit_exponent.fit(points, self.fit_critical_temperature, self.fit_window);
+                    egui::plot::Plot::new("critical_exponent_fit")
+                        .height(120.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .show(ui, |plot_ui| {
+                            plot_ui.points(egui::plot::Points::new(log_points.clone()).radius(2.0).name("Data"));
+                            if let Some((line, _)) = fit {
+                                let xs = log_points.iter().map(|[x, _]| *x);
+                                let (low, high) = (xs.clone().fold(f64::INFINITY, f64::min), xs.fold(f64::NEG_INFINITY, f64::max));
+                                plot_ui.line(egui::plot::Line::new(vec![[low, line.at(low)], [high, line.at(high)]]).name("Fit"));
+                            }
+                        });
+                    ui.weak("ln y against ln |t|");
+                    if let Some((line, _)) = fit {
+                        egui::plot::Plot::new("critical_exponent_residuals")
+                            .height(80.0)
+                            .allow_drag(false)
+                            .allow_zoom(false)
+                            .allow_scroll(false)
+                            .show(ui, |plot_ui| {
+                                plot_ui.points(egui::plot::Points::new(line.residuals(&log_points)).radius(2.0).name("Residual"));
+                                plot_ui.hline(egui::plot::HLine::new(0.0));
+                            });
+                        ui.weak("Residuals, a trend means the window reaches outside the scaling region");
+                    }
+                });
+
+                ui.add_space(4.0);
+
+                egui::CollapsingHeader::new("Data Collapse").default_open(false).show(ui, |ui| {
+                    let curves = self.size_sweep.curves();
+                    if curves.len() < 2 {
+                        ui.weak("Run a temperature sweep at several sizes to collapse their curves");
+                        return;
+                    }
+
+                    ui.horizontal_wrapped(|ui| {
+                        for observable in SweepObservable::ALL {
+                            ui.radio_value(&mut self.sweep_observable, observable, observable.to_string());
+                        }
+                    });
+                    let observable = self.sweep_observable;
+
+                    ui.label("Tc");
+                    ui.add(egui::Slider::new(&mut self.data_collapse.critical_temperature, 0.1..=10.0).logarithmic(true));
+                    ui.label("ν");
+                    ui.add(egui::Slider::new(&mut self.data_collapse.nu, 0.3..=3.0));
+                    ui.label(format!("κ, the power of L {} grows as", observable));
+                    ui.add(egui::Slider::new(&mut self.data_collapse.exponent, -1.0..=3.0));
+
+                    let exact = exact_critical_temperature(self.lattice.settings());
+                    let ising = DataCollapse::ising_2d_exponent(observable);
+                    if ui.add_enabled(ising.is_some(), egui::Button::new("2D Ising Values")).clicked() {
+                        self.data_collapse.nu = 1.0;
+                        self.data_collapse.exponent = ising.unwrap_or_default();
+                        if let Some(exact) = exact {
+                            self.data_collapse.critical_temperature = exact;
+                        }
+                    }
+
+                    let collapse = self.data_collapse;
+                    egui::plot::Plot::new("data_collapse")
+                        .height(200.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .legend(egui::plot::Legend::default())
+                        .show(ui, |plot_ui| {
+                            for (index, (size, points)) in curves.iter().enumerate() {
+                                let [r, g, b] = model::hue(index as f32 * 0.38);
+                                let colour = egui::Color32::from_rgb(r, g, b);
+                                let rescaled = collapse.points(*size, points, observable);
+                                plot_ui.line(egui::plot::Line::new(rescaled.clone()).color(colour).name(format!("L = {}", size)));
+                                plot_ui.points(egui::plot::Points::new(rescaled).radius(2.0).color(colour));
+                            }
+                        });
+                    ui.weak(format!("{} L^-κ against (T - Tc) L^(1/ν)", observable));
+
+                    match collapse.residual(curves, observable) {
+                        Some(residual) => ui.label(format!("Residual: {:.4}", residual)),
+                        None => ui.label("Residual: -"),
+                    }.on_hover_text("Mean squared distance between each curve and the next, relative to the spread of the values. Tune the sliders to make it as small as you can.");
+                });
+
+                ui.add_space(4.0);
+
+                egui::CollapsingHeader::new("Free Energy and Entropy").default_open(false).show(ui, |ui| {
+                    let points = match self.size_sweep.curves().last() {
+                        Some((_, points)) if self.size_sweep.current().is_none() => points.as_slice(),
+                        _ => self.temperature_sweep.points(),
+                    };
+
+                    let Some(infinite_temperature) = self.lattice.infinite_temperature() else {
+                        ui.weak("Only for discrete models without Trotter slices");
+                        return;
+                    };
+                    let thermodynamics = sweep::thermodynamic_integration(points, infinite_temperature);
+
+                    let curve = |value: fn(&sweep::Thermodynamics) -> f32| -> egui::plot::PlotPoints {
+                        thermodynamics.iter().map(|point| [point.temperature as f64, value(point) as f64]).collect()
+                    };
+                    egui::plot::Plot::new("thermodynamic_integration")
+                        .height(160.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .legend(egui::plot::Legend::default())
+                        .show(ui, |plot_ui| {
+                            plot_ui.line(egui::plot::Line::new(curve(|point| point.free_energy)).name("F"));
+                            plot_ui.line(egui::plot::Line::new(curve(|point| point.entropy)).name("S"));
+                        });
+
+                    // the points are in order of 1/T
+                    if let Some(lowest) = thermodynamics.last() {
+                        ui.label(format!("At T = {:.3}: F = {:.4}, S = {:.4}", lowest.temperature, lowest.free_energy, lowest.entropy));
+                    }
+                    ui.weak(format!(
+                        "Per site, integrating the sweep's <E> over 1/T from S = ln {} at infinite temperature. Needs a sweep reaching well above Tc.",
+                        self.lattice.settings().model.state_count(),
+                    ));
+                });
+
+                ui.add_space(4.0);
+
+                egui::CollapsingHeader::new("Hysteresis Loop").default_open(false).show(ui, |ui| {
+                    let running = self.field_sweep.current().is_some();
+
+                    ui.add_enabled_ui(!running, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Max Field");
+                            ui.add(egui::DragValue::new(&mut self.field_sweep.max_field).speed(0.01).clamp_range(0.0..=10.0));
+                            ui.label("Steps");
+                            ui.add(egui::DragValue::new(&mut self.field_sweep.steps).clamp_range(1..=1000))
+                                .on_hover_text("Steps each way between -B and B");
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Sweeps per Step");
+                            ui.add(egui::DragValue::new(&mut self.field_sweep.sweeps).clamp_range(1..=100_000))
+                                .on_hover_text("Fewer sweeps ramp the field faster, widening the loop");
+                        });
+                    });
+
+                    ui.horizontal(|ui| {
+                        if running {
+                            if ui.button("Stop Loop").clicked() {
+                                self.field_sweep.stop();
+                            }
+                        } else if ui.button("Start Loop").clicked() {
+                            self.magnetic_field = self.field_sweep.begin(self.measurements.generation());
+                            self.simulation.send(Command::MagneticField(self.magnetic_field));
+                        }
+                        if let Some(step) = self.field_sweep.current() {
+                            ui.weak(format!("Step {} of {}", step + 1, 2 * self.field_sweep.steps + 1));
+                        }
+                    });
+
+                    let points: Vec<[f64; 2]> = self.field_sweep.points().iter().map(|[b, m]| [*b as f64, *m as f64]).collect();
+                    egui::plot::Plot::new("hysteresis_loop")
+                        .height(160.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .show(ui, |plot_ui| plot_ui.line(egui::plot::Line::new(points).name("m")));
+                    ui.weak("Magnetisation against field");
+
+                    match self.field_sweep.coercive_fields() {
+                        [Some(up), Some(down)] => ui.label(format!("Coercive field: {:.3}", 0.5 * (up - down))),
+                        _ => ui.label("Coercive field: -"),
+                    }.on_hover_text("Half the distance between the fields at which m changes sign on the way up and down");
+                    match self.field_sweep.loop_area() {
+                        Some(area) => ui.label(format!("Loop area: {:.3}", area)),
+                        None => ui.label("Loop area: -"),
+                    }.on_hover_text("∮ m dB, the energy per spin lost as heat each cycle");
+                });
+
+                ui.add_space(4.0);
+
+                egui::CollapsingHeader::new("Correlation Function").default_open(false).show(ui, |ui| {
+                    ui.label("Update Every N Sweeps");
+                    ui.add(egui::DragValue::new(&mut self.correlation_interval).clamp_range(1..=100_000));
+
+                    if self.correlation.is_empty() || self.sweeps >= self.correlation_sweep + self.correlation_interval {
+                        self.correlation = self.lattice.correlation_function(MAX_CORRELATION_DISTANCE);
+                        self.correlation_sweep = self.sweeps;
+                    }
+
+                    if self.correlation.is_empty() {
+                        ui.weak("Only for lattices with distances between sites");
+                        return;
+                    }
+
+                    let points: egui::plot::PlotPoints = self.correlation.iter()
+                        .enumerate()
+                        .map(|(r, correlation)| [r as f64, *correlation as f64])
+                        .collect();
+                    egui::plot::Plot::new("correlation_function")
+                        .height(160.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .show(ui, |plot_ui| plot_ui.line(egui::plot::Line::new(points).name("C(r)")));
+                    ui.weak(format!("Connected <s(0) s(r)> - <s>² at sweep {}, averaged over sites and axes", self.correlation_sweep));
+                });
 
-        cc.egui_ctx.set_fonts(fonts);
+                ui.add_space(4.0);
 
-        Default::default()
-    }
-}
+                egui::CollapsingHeader::new("Clusters").default_open(false).show(ui, |ui| {
+                    ui.label("Update Every N Sweeps");
+                    ui.add(egui::DragValue::new(&mut self.cluster_interval).clamp_range(1..=100_000));
 
-impl eframe::App for IsingApp {
-    fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
-        // save image
-        if self.file_save_handle.is_some() && self.file_save_handle.as_ref().expect("").is_finished() {
-            match self.file_save_handle.take().expect("").join() {
-                Ok(path) => if let Some(path) = path {
-                    let (data, size) = self.lattice.as_image_raw();
-                    let size = size as u32;
-                    
-                    self.alert = match image::save_buffer_with_format(path, &data, size, size, image::ColorType::Rgb8, image::ImageFormat::Png) {
-                        Ok(_) => Some(Alert::Success("Image saved succesfully.".into())),
-                        Err(err) => Some(Alert::Error(format!("Failed to save image: {}", err.to_string()))),
-                    };
-                },
-                Err(_) => {
-                    self.alert = Some(Alert::Error("Failed to open file save dialogue.".into()));
-                },
-            }
-        }
+                    if self.cluster_sizes.is_empty() || self.sweeps >= self.cluster_sweep + self.cluster_interval {
+                        self.cluster_sizes = self.lattice.cluster_sizes();
+                        self.cluster_sweep = self.sweeps;
+                    }
 
-        egui::SidePanel::left("side_panel").show(ctx, |ui| {
-            egui::ScrollArea::vertical().show(ui, |ui| {
-                ui.heading("Ising Model");
+                    let largest = self.cluster_sizes.iter().copied().max().unwrap_or_default();
+                    ui.label(format!(
+                        "{} clusters, the largest has {:.1}% of the sites",
+                        self.cluster_sizes.len(), 100.0 * largest as f32 / self.lattice.spin_count() as f32,
+                    ));
+
+                    // clusters of 1, 2-3, 4-7, ... sites
+                    let mut counts = vec![0; largest.max(1).ilog2() as usize + 1];
+                    for size in &self.cluster_sizes {
+                        counts[size.ilog2() as usize] += 1;
+                    }
+                    let bars: Vec<egui::plot::Bar> = counts.iter()
+                        .enumerate()
+                        .map(|(bin, count)| egui::plot::Bar::new(bin as f64, *count as f64).width(0.9))
+                        .collect();
+                    egui::plot::Plot::new("cluster_sizes")
+                        .height(160.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .show(ui, |plot_ui| plot_ui.bar_chart(egui::plot::BarChart::new(bars).name("Clusters")));
+                    ui.weak(format!("Number of domains with 2^n to 2^(n+1) - 1 sites, at sweep {}", self.cluster_sweep));
+                });
 
                 ui.add_space(4.0);
 
-                egui::CollapsingHeader::new("Lattice").default_open(true).show(ui, |ui| {
-                    ui.label("Size");
-                    ui.add(egui::Slider::new(&mut self.size, 1..=256));
-                    
-                    {
-                        let p_antiferro = if let LatticeType::SpinGlass { p_antiferro } = &self.lattice_type {
-                            *p_antiferro
+                egui::CollapsingHeader::new("Domain Walls").default_open(false).show(ui, |ui| {
+                    match self.domain_wall_history.back() {
+                        Some((_, length)) => ui.label(format!("Length: {} bonds", length)),
+                        None => ui.label("Length: -"),
+                    };
+
+                    let points: egui::plot::PlotPoints = self.domain_wall_history.iter()
+                        .filter(|(sweeps, length)| !self.domain_wall_log_log || (*sweeps > 0 && *length > 0))
+                        .map(|(sweeps, length)| if self.domain_wall_log_log {
+                            [(*sweeps as f64).log10(), (*length as f64).log10()]
                         } else {
-                            0.5
-                        };
+                            [*sweeps as f64, *length as f64]
+                        })
+                        .collect();
+                    egui::plot::Plot::new("domain_walls")
+                        .height(160.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .show(ui, |plot_ui| plot_ui.line(egui::plot::Line::new(points).name("Length")));
+
+                    ui.checkbox(&mut self.domain_wall_log_log, "Log-Log")
+                        .on_hover_text("log₁₀ of both axes, after a quench below Tc the length falls on a line of slope -1/2");
+                    ui.weak("Against sweeps since the parameters last changed");
+                });
 
-                        ui.radio_value(&mut self.lattice_type, LatticeType::Ferromagnetic, "Ferromagnetic");
-                        ui.radio_value(&mut self.lattice_type, LatticeType::Antiferromagnetic, "Antiferromagnetic");
-                        ui.radio_value(&mut self.lattice_type, LatticeType::SpinGlass { p_antiferro }, "Spin Glass");
+                ui.add_space(4.0);
 
-                        if let LatticeType::SpinGlass { p_antiferro } = &mut self.lattice_type {
-                            ui.label("p Antiferromagnetic");
-                            ui.add(egui::Slider::new(p_antiferro, 0.0..=1.0));
+                egui::CollapsingHeader::new("Quench").default_open(false).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("To T");
+                        ui.add(egui::DragValue::new(&mut self.quench_temperature).speed(0.01).clamp_range(0.01..=10.0));
+                        if ui.button("Quench").on_hover_text("Randomise the spins, as at infinite temperature, and drop straight to this temperature").clicked() {
+                            self.temperature = self.quench_temperature;
+                            self.lattice.temperature = self.quench_temperature;
+                            self.lattice.randomise();
+                            self.simulation.send(Command::Replace(Box::new(self.lattice.clone())));
+                            self.texture_stale = true;
                         }
-                    }
-                    
-                    ui.label("Lattice Initial State");
-                    ui.radio_value(&mut self.initial_state, LatticeInitialState::Random, "Random");
-                    ui.radio_value(&mut self.initial_state, LatticeInitialState::AllUp, "All Spin Up");
-                    ui.radio_value(&mut self.initial_state, LatticeInitialState::AllDown, "All Spin Down");
-        
-                    if ui.button("Regenerate Lattice").clicked() {
-                        self.lattice = match self.initial_state {
-                            LatticeInitialState::Random => Lattice::new_random(self.size, self.lattice.temperature, self.lattice.magnetic_field, self.lattice_type),
-                            LatticeInitialState::AllUp => Lattice::new_uniform(self.size, self.lattice.temperature, self.lattice.magnetic_field, Spin::Up, self.lattice_type),
-                            LatticeInitialState::AllDown => Lattice::new_uniform(self.size, self.lattice.temperature, self.lattice.magnetic_field, Spin::Down, self.lattice_type),
-                        };
-                    }
+                    });
+
+                    // domain size as the inverse of the fraction of bonds which are domain walls
+                    let bonds = self.lattice.bond_count() as f64;
+                    let log_points: Vec<[f64; 2]> = self.domain_wall_history.iter()
+                        .filter(|(sweeps, length)| *sweeps > 0 && *length > 0)
+                        .map(|(sweeps, length)| [(*sweeps as f64).log10(), (bonds / *length as f64).log10()])
+                        .collect();
+                    // leave out the early sweeps, before the domains are more than a few sites
+                    let late: Vec<[f64; 2]> = log_points.iter().copied().filter(|[log_sweeps, _]| *log_sweeps >= 1.0).collect();
+                    let line = fit::linear_fit(&late);
+
+                    egui::plot::Plot::new("quench")
+                        .height(160.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .legend(egui::plot::Legend::default())
+                        .show(ui, |plot_ui| {
+                            plot_ui.line(egui::plot::Line::new(log_points.clone()).name("log₁₀ L"));
+                            if let (Some(line), Some(first), Some(last)) = (line, late.first(), late.last()) {
+                                let ends = vec![[first[0], line.at(first[0])], [last[0], line.at(last[0])]];
+                                plot_ui.line(egui::plot::Line::new(ends).style(egui::plot::LineStyle::dashed_loose()).name("Fit"));
+                            }
+                        });
+
+                    match line {
+                        Some(line) => ui.label(format!("Growth exponent: {}", Estimate { value: line.slope as f32, error: line.slope_error.map(|error| error as f32) })),
+                        None => ui.label("Growth exponent: -"),
+                    }.on_hover_text("Slope of log L against log t after the first 10 sweeps. The domains of a non-conserved order parameter coarsen as L ~ t^(1/2) (Allen-Cahn), which the slope creeps up towards over the first few thousand sweeps.");
+                    ui.weak("L = bonds / domain wall length, against sweeps since the quench");
                 });
 
                 ui.add_space(4.0);
-                
-                egui::CollapsingHeader::new("Environment").default_open(true).show(ui, |ui| {
-                    ui.label("Temperature");
-                    ui.add(egui::Slider::new(&mut self.lattice.temperature, 0.0..=10.0));
-                    
-                    ui.label("Magnetic Field");
-                    ui.add(egui::Slider::new(&mut self.lattice.magnetic_field, -5.0..=5.0));
+
+                egui::CollapsingHeader::new("Magnetisation Histogram").default_open(false).show(ui, |ui| {
+                    let width = self.magnetisation_histogram.bin_width() as f64;
+                    let bars: Vec<egui::plot::Bar> = self.magnetisation_histogram.bins()
+                        .map(|(centre, count)| egui::plot::Bar::new(centre as f64, count as f64).width(width))
+                        .collect();
+                    egui::plot::Plot::new("magnetisation_histogram")
+                        .height(160.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .show(ui, |plot_ui| plot_ui.bar_chart(egui::plot::BarChart::new(bars).name("m")));
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Reset Histogram").clicked() {
+                            self.magnetisation_histogram.clear();
+                        }
+                        ui.weak(format!("{} samples", self.magnetisation_histogram.total()));
+                    });
                 });
 
                 ui.add_space(4.0);
-                
-                egui::CollapsingHeader::new("Simulation").default_open(true).show(ui, |ui| {
-                    ui.label("Iterations per Second");
-                    ui.add(egui::Slider::new(&mut self.fps, 1.0..=60.0));
-                    if ui.button("Save Image").clicked() {
-                        self.file_save_handle = Some(std::thread::spawn(|| {
-                            rfd::FileDialog::new()
-                                .add_filter("PNG", &["png"])
-                                .set_file_name("lattice.png")
-                                .set_title("Save Lattice Image")
-                                .save_file()
-                        }));
-                        self.paused = true;
+
+                egui::CollapsingHeader::new("Replica Overlap").default_open(false).show(ui, |ui| {
+                    if ui.checkbox(&mut self.replica_overlap, "Run Replica")
+                        .on_hover_text("Second copy of the lattice with the same couplings, started from a random state")
+                        .changed()
+                    {
+                        self.overlap = None;
+                        self.overlap_histogram.clear();
+                        self.simulation.send(Command::Replica(self.replica_overlap));
+                    }
+
+                    match self.overlap {
+                        Some(overlap) => ui.label(format!("Overlap q: {:.4}", overlap)),
+                        None => ui.label("Overlap q: -"),
+                    };
+
+                    let width = self.overlap_histogram.bin_width() as f64;
+                    let bars: Vec<egui::plot::Bar> = self.overlap_histogram.bins()
+                        .map(|(centre, count)| egui::plot::Bar::new(centre as f64, count as f64).width(width))
+                        .collect();
+                    egui::plot::Plot::new("overlap_histogram")
+                        .height(160.0)
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .show(ui, |plot_ui| plot_ui.bar_chart(egui::plot::BarChart::new(bars).name("P(q)")));
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Reset Histogram").clicked() {
+                            self.overlap_histogram.clear();
+                        }
+                        ui.weak(format!("{} samples", self.overlap_histogram.total()));
+                    });
+                    ui.weak("q = (1/N) Σ s_i s'_i, P(q) spreads out between ±q_EA in a spin glass");
+                });
+
+                ui.add_space(4.0);
+
+                egui::CollapsingHeader::new("Advanced").default_open(false).show(ui, |ui| {
+                    ui.label("Random Number Generator");
+                    for algorithm in RngAlgorithm::ALL {
+                        ui.radio_value(&mut self.lattice_settings.rng_algorithm, algorithm, algorithm.to_string());
                     }
+                    ui.weak("Applies when the lattice is regenerated");
                 });
 
                 ui.add_space(8.0);
@@ -170,56 +2834,648 @@ impl eframe::App for IsingApp {
                 }
             }
 
+            if self.export_open {
+                self.export_ui(ctx);
+            }
+
             ui.with_layout(egui::Layout::left_to_right(egui::Align::Min), |ui| {
-                let (pause_text, play_text) = {
+                let (pause_text, play_text, step_text) = {
                     let font_id = egui::FontId::new(14.0, egui::FontFamily::Name("icons".into()));
-                    (egui::RichText::new("\u{f04c}").font(font_id.clone()), egui::RichText::new("\u{f04b}").font(font_id))
+                    (
+                        egui::RichText::new("\u{f04c}").font(font_id.clone()),
+                        egui::RichText::new("\u{f04b}").font(font_id.clone()),
+                        egui::RichText::new("\u{f051}").font(font_id),
+                    )
                 };
                 
                 if ui.add_enabled(!self.paused, egui::Button::new(pause_text).small()).clicked() {
-                    self.paused = true;
+                    self.set_paused(true);
                 }
                 if ui.add_enabled(self.paused, egui::Button::new(play_text).small()).clicked() {
-                    self.paused = false;
+                    self.set_paused(false);
+                }
+
+                // right arrow steps once, shift + right arrow steps N times
+                let (step_once_key, step_n_key) = ui.input(|i| {
+                    let pressed = i.key_pressed(egui::Key::ArrowRight);
+                    (pressed && !i.modifiers.shift, pressed && i.modifiers.shift)
+                });
+
+                if ui.add_enabled(self.paused, egui::Button::new(step_text).small())
+                    .on_hover_text("Step one sweep (Right Arrow)")
+                    .clicked() || (self.paused && step_once_key)
+                {
+                    self.simulation.send(Command::Step(1));
+                    if let Some(comparison) = &self.comparison {
+                        comparison.simulation.send(Command::Step(1));
+                    }
+                }
+
+                ui.add_space(4.0);
+
+                if ui.add_enabled(self.paused, egui::Button::new(format!("Step {}", self.step_count)).small())
+                    .on_hover_text("Step N sweeps (Shift + Right Arrow)")
+                    .clicked() || (self.paused && step_n_key)
+                {
+                    self.simulation.send(Command::Step(self.step_count));
+                    if let Some(comparison) = &self.comparison {
+                        comparison.simulation.send(Command::Step(self.step_count));
+                    }
                 }
+                ui.add_enabled(self.paused, egui::DragValue::new(&mut self.step_count).clamp_range(1..=100_000));
 
                 ui.add_space(8.0);
 
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                    ui.label(format!("Magnetisation: {:.4}", self.lattice.magnetisation()));
-                    ui.label(format!("Heat capacity: {:.2}", self.lattice.heat_capacity()));
+                    match self.lattice.settings().model {
+                        Model::Ising if self.lattice_gas => ui.label(format!("Density: {:.4}", self.lattice.density())),
+                        Model::Ising if self.lattice.settings().lattice_type == LatticeType::Antiferromagnetic => {
+                            if let Some(staggered) = self.lattice.staggered_magnetisation() {
+                                ui.label(format!("Staggered magnetisation: {:.4}", staggered))
+                                    .on_hover_text("Magnetisation with every other site flipped, the order parameter of an antiferromagnet");
+                            }
+                            if let Some([a, b]) = self.lattice.sublattice_magnetisations() {
+                                ui.label(format!("m_A: {:.4}, m_B: {:.4}", a, b))
+                                    .on_hover_text("Magnetisation of each sublattice on its own, opposite in the ordered phase. In a field both tilt towards it.");
+                            }
+                            ui.label(format!("Magnetisation: {:.4}", self.lattice.magnetisation()))
+                        },
+                        Model::Ising => ui.label(format!("Magnetisation: {:.4}", self.lattice.magnetisation())),
+                        _ => ui.label(format!("Order parameter: {:.4}", self.lattice.magnetisation())),
+                    };
+                    ui.label(format!("Energy: {:.4}", self.lattice.internal_energy() / self.lattice.spin_count() as f32))
+                        .on_hover_text("Energy per spin of the latest sweep");
+                    let window_independent = match self.measurements.effective_window_count() {
+                        Some(count) => format!(", worth about {} independent samples", count),
+                        None => String::new(),
+                    };
+                    match self.measurements.heat_capacity(self.lattice.spin_count(), self.temperature) {
+                        Some(heat_capacity) if self.temperature > 0.0 => ui.label(format!("Heat capacity: {:.3}", heat_capacity)),
+                        _ => ui.label("Heat capacity: -"),
+                    }.on_hover_text(format!("C = N (<e²> - <e>²) / T² over the fluctuation window{}, ± a block bootstrap error", window_independent));
+                    match self.measurements.susceptibility(self.lattice.spin_count(), self.temperature) {
+                        Some(susceptibility) if self.temperature > 0.0 => ui.label(format!("Susceptibility: {:.3}", susceptibility)),
+                        _ => ui.label("Susceptibility: -"),
+                    }.on_hover_text(format!("χ = N (<m²> - <m>²) / T over the fluctuation window{}, ± a block bootstrap error", window_independent));
+                    match self.measurements.autocorrelation_sweeps() {
+                        Some(tau) => ui.label(format!("τ: {:.1} sweeps", tau)),
+                        None => ui.label("τ: -"),
+                    }.on_hover_text("Integrated autocorrelation time of the magnetisation over the fluctuation window, samples about 2τ sweeps apart are independent");
+                    ui.label(format!("Sweep: {}", self.sweeps));
+
+                    if let Some(acceptance_rate) = self.acceptance_rate {
+                        let attempted = self.lattice.attempted_flips().max(1);
+                        let average = self.lattice.accepted_flips() as f32 / attempted as f32;
+
+                        ui.label(format!("Acceptance: {:.3} (avg {:.3})", acceptance_rate, average))
+                            .on_hover_text("Fraction of accepted flips in the latest batch of sweeps, and since the lattice was generated");
+                    }
                 });
             });
 
+            ui.horizontal(|ui| {
+                // the density keeps the sign of the magnetisation, particles and holes differ
+                let (name, order) = if self.is_lattice_gas() {
+                    ("Mean density", self.measurements.mean_density())
+                } else {
+                    ("Mean |m|", self.measurements.mean_abs_magnetisation())
+                };
+                let averages = match (order, self.measurements.mean_energy(), self.measurements.binder_cumulant()) {
+                    (Some(order), Some(energy), Some(binder)) => format!(
+                        "{}: {:.4}   Mean energy: {:.4}   Binder U4: {:.4}   ({} samples)",
+                        name, order, energy, binder, self.measurements.count(),
+                    ),
+                    (Some(order), Some(energy), None) => format!(
+                        "{}: {:.4}   Mean energy: {:.4}   Binder U4: -   ({} samples)",
+                        name, order, energy, self.measurements.count(),
+                    ),
+                    _ => format!("{}: -   Mean energy: -   Binder U4: -", name),
+                };
+
+                if self.measurements.thermalising() {
+                    ui.weak(averages);
+                    ui.weak(format!("Thermalising, {} sweeps left", self.measurements.burn_in_remaining()));
+                } else {
+                    ui.label(averages)
+                        .on_hover_text("± one standard error, from a jackknife over blocks of consecutive samples (shown once there are enough blocks)");
+
+                    if let Some(independent) = self.measurements.effective_sample_count() {
+                        let text = format!("≈ {} independent", independent);
+                        let hover = "Samples recorded divided by 2τ, what the averages are worth. Consecutive sweeps are correlated, so this is far fewer than the samples recorded near Tc.";
+                        if independent < MIN_INDEPENDENT_SAMPLES {
+                            ui.colored_label(egui::Color32::from_rgb(255, 180, 60), text)
+                                .on_hover_text(format!("{} Too few to trust the averages yet.", hover));
+                        } else {
+                            ui.weak(text).on_hover_text(hover);
+                        }
+                    }
+                }
+
+                if let (Some(squared), Some(fourth), Some(kurtosis)) = (
+                    self.measurements.mean_magnetisation_squared(),
+                    self.measurements.mean_magnetisation_fourth(),
+                    self.measurements.magnetisation_kurtosis(),
+                ) {
+                    ui.add_space(8.0);
+                    ui.label(format!("<m²>: {:.4}   <m⁴>: {:.4}", squared, fourth))
+                        .on_hover_text(format!("Raw moments of the magnetisation, saved with a temperature sweep. Kurtosis <m⁴> / <m²>²: {:.3}, 3 for Gaussian fluctuations and 1 when ordered.", kurtosis));
+                }
+
+                if let Some(correlation_length) = self.measurements.correlation_length(self.lattice.image_size().0) {
+                    ui.add_space(8.0);
+                    ui.label(format!("ξ: {:.2}", correlation_length))
+                        .on_hover_text("Second-moment correlation length from the structure factor, in lattice spacings along x. Only meaningful above Tc.");
+                }
+
+                ui.add_space(8.0);
+
+                match self.measurements.equilibrated_at() {
+                    Some(sweeps) => ui.label(format!("Equilibrated after {} sweeps", sweeps)),
+                    None => ui.weak("Equilibrating"),
+                };
+
+                if self.lattice.settings().geometry == Geometry::MeanField && self.lattice.settings().model == Model::Ising && self.field_profile == FieldProfile::Uniform && self.temperature_gradient == 0.0 {
+                    ui.add_space(8.0);
+                    let magnetisation = lattice::mean_field_magnetisation(self.lattice.mean_field_coupling(), self.magnetic_field, self.temperature);
+                    ui.label(format!("Mean-field theory |m|: {:.4}", magnetisation.abs()));
+                }
+            });
+
+            if self.is_strip() {
+                ui.horizontal(|ui| {
+                    let mut view_changed = false;
+                    view_changed |= ui.radio_value(&mut self.chain_view, ChainView::SpaceTime, "Space-Time Diagram").changed();
+                    view_changed |= ui.radio_value(&mut self.chain_view, ChainView::Strip, "Strip").changed();
+                    if view_changed {
+                        self.texture_stale = true;
+                    }
+                });
+            }
+
+            if self.lattice.layers() > 1 {
+                ui.horizontal(|ui| {
+                    ui.label(if self.lattice.trotter_slices() > 1 { "Slice (z, Trotter)" } else { "Slice (z)" });
+                    if ui.add(egui::Slider::new(&mut self.view_layer, 0..=self.lattice.layers() - 1)).changed() {
+                        self.texture_stale = true;
+                    }
+                });
+            }
+
+            // bonds can only be drawn and picked out where there are a few pixels per site, and
+            // not on the space-time diagram
+            let (image_width, image_height) = self.lattice.image_size();
+            let can_edit_bonds = image_width.max(image_height) <= MAX_EDITABLE_SIZE
+                && !(self.is_strip() && self.chain_view == ChainView::SpaceTime);
+
+            ui.horizontal(|ui| {
+                ui.label("Zoom").on_hover_text("Scroll over the lattice to zoom in around the pointer, drag it to pan");
+                let zoom = self.zoom;
+                ui.add(egui::DragValue::new(&mut self.zoom).speed(0.05).clamp_range(1.0..=MAX_ZOOM).suffix("×"));
+                if ui.add_enabled(self.zoom > 1.0, egui::Button::new("Reset").small()).clicked() {
+                    self.zoom = 1.0;
+                }
+                if self.zoom != zoom {
+                    self.set_view_centre(self.view_centre);
+                }
+
+                ui.add_space(8.0);
+
+                ui.checkbox(&mut self.show_grid, "Grid")
+                    .on_hover_text(format!("Draw lines between sites once they're at least {} points across", MIN_GRID_CELL));
+
+                if self.lattice.settings().model.is_vector() {
+                    ui.checkbox(&mut self.show_arrows, "Arrows")
+                        .on_hover_text(format!("Draw the direction of each spin once sites are at least {} points across, zoom in to see them. Vortices of the XY model stand out.", MIN_ARROW_CELL));
+                }
+
+                ui.add_enabled(can_edit_bonds, egui::Checkbox::new(&mut self.show_bonds, "Show Bonds"))
+                    .on_hover_text("Draw every bond over the lattice, to see the couplings of a spin glass or a diluted lattice")
+                    .on_disabled_hover_text(format!("Only for lattices up to {} sites across, in the strip view for chains", MAX_EDITABLE_SIZE));
+
+                let checkbox = ui.add_enabled(can_edit_bonds, egui::Checkbox::new(&mut self.edit_bonds, "Edit Bonds"))
+                    .on_disabled_hover_text(format!("Only for lattices up to {} sites across, in the strip view for chains", MAX_EDITABLE_SIZE));
+                if checkbox.changed() && self.edit_bonds {
+                    self.set_paused(true);
+                }
+
+                if self.edit_bonds && can_edit_bonds {
+                    ui.radio_value(&mut self.paint_sign, 1.0, "Ferromagnetic");
+                    ui.radio_value(&mut self.paint_sign, -1.0, "Antiferromagnetic");
+                    ui.radio_value(&mut self.paint_sign, 0.0, "Zero");
+                }
+
+                ui.add_space(8.0);
+
+                let checkbox = ui.add_enabled(!(self.is_strip() && self.chain_view == ChainView::SpaceTime), egui::Checkbox::new(&mut self.paint_spins, "Paint Spins"))
+                    .on_hover_text("Drag over the lattice to paint spins up or down, then watch the shapes melt or grow at different temperatures");
+                if checkbox.changed() && self.paint_spins {
+                    self.set_paused(true);
+                }
+
+                if self.paint_spins {
+                    ui.radio_value(&mut self.paint_spin, Spin::Up, "Up");
+                    ui.radio_value(&mut self.paint_spin, Spin::Down, "Down");
+                    ui.radio_value(&mut self.brush_shape, BrushShape::Circle, "Circle");
+                    ui.radio_value(&mut self.brush_shape, BrushShape::Square, "Square");
+                    ui.add(egui::DragValue::new(&mut self.brush_radius).clamp_range(0..=100).prefix("radius "));
+                }
+
+                ui.add_space(8.0);
+
+                if ui.checkbox(&mut self.show_structure_factor, "Structure Factor")
+                    .on_hover_text("|FFT|² of the spins shown, with k = 0 in the middle on a log scale. Ferromagnetic order is a central peak, antiferromagnetic order peaks at the corners.")
+                    .changed()
+                {
+                    self.texture_stale = true;
+                }
+
+                let mut coarse_grain = self.coarse_grain.is_some();
+                let mut block = self.coarse_grain.unwrap_or(8);
+                ui.checkbox(&mut coarse_grain, "Coarse Grain")
+                    .on_hover_text("Cover the lattice with the mean spin of each block of sites, black for down and white for up. Near Tc the blocks look much like the spins themselves.");
+                if coarse_grain {
+                    ui.add(egui::DragValue::new(&mut block).clamp_range(2..=64).suffix(" sites"));
+                }
+                if coarse_grain.then_some(block) != self.coarse_grain {
+                    self.coarse_grain = coarse_grain.then_some(block);
+                    self.texture_stale = true;
+                }
+
+                if ui.checkbox(&mut self.show_last_flips, "Latest Flips")
+                    .on_hover_text("Mark the sites which flipped during the latest sweep in green. At low temperature these are the rare activated flips, which usually flip straight back.")
+                    .changed()
+                {
+                    self.simulation.send(Command::TrackActivity(self.track_activity()));
+                    self.texture_stale = true;
+                }
+
+                ui.checkbox(&mut self.show_time_series, "Time Series")
+                    .on_hover_text(format!("Plot the magnetisation and energy of the last {} samples below the lattice", MAX_TIME_SERIES));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Colour by");
+                for colouring in SiteColouring::ALL {
+                    if ui.radio_value(&mut self.site_colouring, colouring, colouring.to_string()).changed() {
+                        self.simulation.send(Command::TrackActivity(self.track_activity()));
+                        self.texture_stale = true;
+                    }
+                }
+
+                ui.add_space(8.0);
+
+                let palette = self.palette;
+                ui.label("Spins").on_hover_text("Colours of up and down Ising spins, in the view and saved images");
+                for (name, preset) in Palette::PRESETS {
+                    ui.radio_value(&mut self.palette, preset, name);
+                }
+                ui.color_edit_button_srgb(&mut self.palette.up).on_hover_text("Up");
+                ui.color_edit_button_srgb(&mut self.palette.down).on_hover_text("Down");
+                if self.palette != palette {
+                    self.texture_stale = true;
+                }
+            });
+
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                ui.label(egui::RichText::new("Spin Down").color(egui::Color32::from_rgb(255, 64, 64)));
-                ui.label(egui::RichText::new("Spin Up").color(egui::Color32::from_rgb(96, 96, 255)));
+                match self.site_colouring {
+                    SiteColouring::State => {
+                        let [up, down] = [self.palette.up, self.palette.down].map(|[r, g, b]| egui::Color32::from_rgb(r, g, b));
+                        ui.label("Spin Down");
+                        ui.label(egui::RichText::new("■").color(down));
+                        ui.label("Spin Up");
+                        ui.label(egui::RichText::new("■").color(up));
+                    },
+                    SiteColouring::Activity => {
+                        ui.label(egui::RichText::new("Flipping Every Sweep").color(egui::Color32::from_rgb(255, 255, 160)));
+                        ui.label(egui::RichText::new("Frozen").color(egui::Color32::from_rgb(120, 120, 120)));
+                    },
+                    SiteColouring::Energy => {
+                        ui.label(egui::RichText::new("High Energy").color(egui::Color32::from_rgb(255, 255, 160)));
+                        ui.label(egui::RichText::new("Low Energy").color(egui::Color32::from_rgb(120, 120, 120)));
+                    },
+                    SiteColouring::Clusters => {
+                        ui.label("A colour for each domain of sites in the same state");
+                    },
+                }
                 ui.label("Key:");
             });
 
             ui.add_space(8.0);
 
-            if !self.paused && std::time::Instant::now() - self.last_epoch > std::time::Duration::from_secs_f32(1.0/self.fps) {
-                let start = std::time::Instant::now();
-                self.lattice.epoch();
-                println!("Epoch time: {:.5}", (std::time::Instant::now() - start).as_secs_f32());
-                // force redraw
-                self.lattice_texture = None;
-                self.last_epoch = std::time::Instant::now();
-            }
-            
-            let available_space = ui.available_size().x.min(ui.available_size().y);
-            
-            let texture: &egui::TextureHandle = self.lattice_texture.get_or_insert_with(|| {
-                let start = std::time::Instant::now();
-                let tex = ui.ctx().load_texture("lattice-texture", self.lattice.as_image(available_space as usize), Default::default());
-                println!("Texture time: {:.5}", (std::time::Instant::now() - start).as_secs_f32());
-                tex
-            });
-            
-            ui.image(texture, egui::Vec2::new(available_space, available_space));
+            // the structure factor and second lattice share the width
+            let images = 1 + self.show_structure_factor as usize + self.comparison.is_some() as usize;
+            let available_width = (ui.available_size().x - 8.0 * (images - 1) as f32) / images as f32;
+            // and the time series some of the height
+            let reserved_height = if self.show_time_series { 2.0 * TIME_SERIES_HEIGHT + 16.0 } else { 0.0 };
+            let available_space = available_width.min(ui.available_size().y - reserved_height).max(1.0);
+
+            // draw straight from the GPU spin buffer when it shows the same as the texture would,
+            // the overlays and interaction below work the same either way
+            #[cfg(feature = "gpu")]
+            let gpu_view = self.use_gpu && _frame.wgpu_render_state().is_some() && self.lattice.uniform_coupling().is_some() && self.site_colouring == SiteColouring::State && self.coarse_grain.is_none() && self.palette == Palette::CLASSIC && self.comparison.is_none() && self.zoom == 1.0 && !self.track_activity();
+            #[cfg(not(feature = "gpu"))]
+            let gpu_view = false;
+
+            if gpu_view {
+                // rebuilt if the texture is needed again
+                self.texture_stale = true;
+            }
+            // taken out while it's drawn into, as drawing borrows the rest of self
+            let mut pixels = std::mem::take(&mut self.texture_pixels);
+            if self.texture_stale || (self.lattice_texture.is_none() && !gpu_view) {
+                self.texture_stale = gpu_view;
+
+                if !gpu_view {
+                    let size = self.view_image_into(&mut pixels);
+                    update_texture(ui.ctx(), &mut self.lattice_texture, "lattice-texture", size, &pixels);
+                }
+
+                if self.show_structure_factor {
+                    let size = self.lattice.structure_factor_image_into(&mut pixels, self.view_layer);
+                    update_texture(ui.ctx(), &mut self.structure_factor_texture, "structure-factor-texture", size, &pixels);
+                } else {
+                    self.structure_factor_texture = None;
+                }
+
+                // in case the colouring changed
+                if let Some(comparison) = &mut self.comparison {
+                    comparison.texture_stale = true;
+                }
+            }
+            if let Some(comparison) = &self.comparison {
+                if comparison.texture_stale || comparison.texture.is_none() {
+                    let size = self.comparison_image_into(comparison, &mut pixels);
+                    let comparison = self.comparison.as_mut().expect("comparison was just checked");
+                    update_texture(ui.ctx(), &mut comparison.texture, "comparison-texture", size, &pixels);
+                    comparison.texture_stale = false;
+                }
+            }
+            self.texture_pixels = pixels;
+
+            // fit the longer side, strips get at least a visible height
+            let fit_size = |[width, height]: [usize; 2]| {
+                let (width, height) = (width as f32, height as f32);
+                let longest = width.max(height);
+                egui::Vec2::new(available_space * width / longest, (available_space * height / longest).max(24.0))
+            };
+            let fit = |texture: &egui::TextureHandle| fit_size(texture.size());
+
+            let response = ui.horizontal_top(|ui| {
+                let response = match &self.lattice_texture {
+                    Some(texture) if !gpu_view => ui.add(egui::Image::new(texture, fit(texture)).uv(self.view_uv()).sense(egui::Sense::click_and_drag())),
+                    _ => {
+                        let (_rect, response) = ui.allocate_exact_size(fit_size([image_width, image_height]), egui::Sense::click_and_drag());
+                        #[cfg(feature = "gpu")]
+                        ui.painter().add(crate::gpu::paint_callback(_rect));
+                        response
+                    },
+                };
+
+                if let Some(comparison) = &self.comparison {
+                    if let Some(texture) = &comparison.texture {
+                        let lattice = &comparison.lattice;
+                        ui.add(egui::Image::new(texture, fit(texture)).uv(self.view_uv()))
+                            .on_hover_text(format!("Second lattice at T = {:.3}, B = {:.3}: m = {:.4}", lattice.temperature, lattice.magnetic_field, lattice.magnetisation()));
+                    }
+                }
+
+                if let Some(structure_factor) = &self.structure_factor_texture {
+                    ui.add(egui::Image::new(structure_factor, fit(structure_factor)));
+                }
+
+                response
+            }).inner;
+
+            self.zoom_ui(ui, &response);
+            if self.show_grid && !(self.is_strip() && self.chain_view == ChainView::SpaceTime) {
+                self.grid_ui(ui, &response, image_width, image_height);
+            }
+            if self.show_arrows && self.lattice.settings().model.is_vector() && !(self.is_strip() && self.chain_view == ChainView::SpaceTime) {
+                self.arrows_ui(ui, &response, image_width, image_height);
+            }
+            if (self.edit_bonds || self.show_bonds) && can_edit_bonds {
+                self.bonds_ui(ui, &response, image_width, image_height);
+            }
+            if !(self.is_strip() && self.chain_view == ChainView::SpaceTime) {
+                self.site_tooltip(&response, image_width, image_height);
+
+                if self.paint_spins {
+                    self.paint_ui(&response, image_width, image_height);
+                }
+
+                // flip the clicked site while paused, to seed a defect by hand
+                if self.paused && !self.edit_bonds && !self.paint_spins && response.clicked() {
+                    if let Some(site) = self.site_under_pointer(&response, image_width, image_height) {
+                        self.lattice.flip_site(site);
+                        self.simulation.send(Command::Replace(Box::new(self.lattice.clone())));
+                        self.texture_stale = true;
+                    }
+                }
+            }
+
+            if let Some(texture) = self.lattice_texture.as_ref().filter(|_| !gpu_view) {
+                let (texture_id, texture_size) = (texture.id(), texture.size());
+                self.minimap_ui(ui, &response, texture_id, texture_size);
+            }
+
+            self.time_series_ui(ui);
         });
 
         ctx.request_repaint_after(std::time::Duration::from_secs_f32(1.0/self.fps));
     }
 }
+
+/// Pixels of a texture, filled without going through RGB bytes
+impl ImageBuffer for Vec<egui::Color32> {
+    fn reset(&mut self, pixels: usize) {
+        self.clear();
+        self.reserve_exact(pixels);
+    }
+
+    fn push(&mut self, [r, g, b]: [u8; 3]) {
+        Vec::push(self, egui::Color32::from_rgb(r, g, b));
+    }
+
+    fn get(&self, index: usize) -> [u8; 3] {
+        let [r, g, b, _] = self[index].to_array();
+        [r, g, b]
+    }
+
+    fn set(&mut self, index: usize, [r, g, b]: [u8; 3]) {
+        self[index] = egui::Color32::from_rgb(r, g, b);
+    }
+}
+
+/// Replace the image of a texture in place, or create it if there isn't one yet. egui keeps its
+/// own copy of the pixels, made here in one go.
+fn update_texture(ctx: &egui::Context, texture: &mut Option<egui::TextureHandle>, name: &str, (width, height): (usize, usize), pixels: &[egui::Color32]) {
+    let image = egui::ColorImage { size: [width, height], pixels: pixels.to_vec() };
+    match texture {
+        Some(texture) => texture.set(image, LATTICE_TEXTURE_OPTIONS),
+        None => *texture = Some(ctx.load_texture(name, image, LATTICE_TEXTURE_OPTIONS)),
+    }
+}
+
+/// Save an RGB image as a PNG, with the given key/value pairs stored as text chunks.
+fn save_png(path: &std::path::Path, data: &[u8], width: u32, height: u32, metadata: &[(&str, String)]) -> Result<(), png::EncodingError> {
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    for (keyword, text) in metadata {
+        encoder.add_text_chunk(keyword.to_string(), text.clone())?;
+    }
+
+    encoder.write_header()?.write_image_data(data)
+}
+
+/// Colour a bond is drawn in, green for ferromagnetic, amber for antiferromagnetic and grey for
+/// a bond with no coupling
+fn bond_colour(coupling: f32) -> [u8; 3] {
+    if coupling > 0.0 {
+        [64, 220, 64]
+    } else if coupling < 0.0 {
+        [255, 190, 0]
+    } else {
+        [90, 90, 90]
+    }
+}
+
+/// Width a bond is drawn with, as a fraction of a site, growing with the size of the coupling
+fn bond_width(coupling: f32) -> f32 {
+    0.15 * coupling.abs().clamp(0.3, 2.0)
+}
+
+/// Save an RGB image as an SVG, one square per pixel, with the bonds drawn over it and the given
+/// key/value pairs in its description
+fn save_svg(path: &std::path::Path, data: &[u8], width: usize, height: usize, bonds: &[lattice::DrawnBond], metadata: &[(&str, String)]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    // 10 px per site by default, scaled freely by whatever it's put in
+    writeln!(file, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#, 10 * width, 10 * height, width, height)?;
+
+    let description = metadata.iter()
+        .map(|(keyword, text)| format!("{}: {}", keyword, text))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    writeln!(file, "<desc>{}</desc>", description)?;
+
+    // crisp edges stop hairline gaps between neighbouring squares
+    writeln!(file, r#"<g shape-rendering="crispEdges">"#)?;
+    for (pixel, colour) in data.chunks_exact(3).enumerate() {
+        let (x, y) = (pixel % width, pixel / width);
+        writeln!(file, r##"<rect x="{}" y="{}" width="1" height="1" fill="#{:02x}{:02x}{:02x}"/>"##, x, y, colour[0], colour[1], colour[2])?;
+    }
+    writeln!(file, "</g>")?;
+
+    if !bonds.is_empty() {
+        writeln!(file, r#"<g stroke-linecap="round">"#)?;
+        for bond in bonds {
+            let [r, g, b] = bond_colour(bond.coupling);
+            writeln!(
+                file,
+                r##"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="#{:02x}{:02x}{:02x}" stroke-width="{}"/>"##,
+                bond.from.0 as f32 + 0.5, bond.from.1 as f32 + 0.5, bond.to.0 as f32 + 0.5, bond.to.1 as f32 + 0.5,
+                r, g, b, bond_width(bond.coupling),
+            )?;
+        }
+        writeln!(file, "</g>")?;
+    }
+
+    writeln!(file, "</svg>")?;
+    file.flush()
+}
+
+/// Save an RGB image in a format other than PNG
+fn save_image(path: &std::path::Path, data: &[u8], width: u32, height: u32, format: ExportFormat) -> Result<(), image::ImageError> {
+    match format {
+        // the default quality of 75 smears the edges between sites
+        ExportFormat::Jpeg => {
+            let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+            image::codecs::jpeg::JpegEncoder::new_with_quality(file, 95).encode(data, width, height, image::ColorType::Rgb8)
+        },
+        ExportFormat::Png => image::save_buffer_with_format(path, data, width, height, image::ColorType::Rgb8, image::ImageFormat::Png),
+        ExportFormat::Bmp => image::save_buffer_with_format(path, data, width, height, image::ColorType::Rgb8, image::ImageFormat::Bmp),
+        ExportFormat::Tiff => image::save_buffer_with_format(path, data, width, height, image::ColorType::Rgb8, image::ImageFormat::Tiff),
+    }
+}
+
+/// Save the frames of a recording as an animated GIF, looping forever, each frame blown up
+/// towards GIF_SIZE pixels across
+fn save_gif(path: &std::path::Path, recording: &GifRecording) -> Result<(), image::ImageError> {
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let scale = (GIF_SIZE / recording.width.max(recording.height)).max(1);
+    let (width, height) = ((recording.width * scale) as u32, (recording.height * scale) as u32);
+    let delay = image::Delay::from_numer_denom_ms(recording.frame_time, 1);
+
+    let mut encoder = image::codecs::gif::GifEncoder::new_with_speed(file, 10);
+    encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+
+    // scaled one at a time, so that only one frame is held in RGBA at once
+    for rgb in &recording.frames {
+        let rgba: Vec<u8> = lattice::scale_rgb(rgb, recording.width, scale)
+            .chunks_exact(3)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+            .collect();
+        let buffer = image::RgbaImage::from_raw(width, height, rgba).expect("frame is the size of the image");
+        encoder.encode_frame(image::Frame::from_parts(buffer, 0, 0, delay))?;
+    }
+    Ok(())
+}
+
+/// Exact critical temperature of the lattice the settings describe, for the ferromagnets on the
+/// square lattice where one is known
+fn exact_critical_temperature(settings: &LatticeSettings) -> Option<f32> {
+    if settings.geometry != Geometry::Square || settings.lattice_type != LatticeType::Ferromagnetic || settings.next_nearest_coupling != 0.0 || settings.axial_kappa != 0.0 || settings.plaquette_coupling != 0.0 || settings.dipolar.is_some() {
+        return None;
+    }
+
+    let [jx, jy, _] = settings.coupling_strengths.map(|strength| settings.exchange * strength);
+    match settings.model {
+        Model::Ising => lattice::critical_temperature(jx, jy),
+        Model::Potts { q } => model::potts_critical_temperature(jx, jy, q),
+        Model::Clock { q: 2 } => lattice::critical_temperature(jx, jy),
+        // two independent Ising models with half the coupling
+        Model::Clock { q: 4 } => lattice::critical_temperature(0.5 * jx, 0.5 * jy),
+        Model::Clock { .. } | Model::XY | Model::Heisenberg | Model::BlumeCapel { .. } => None,
+    }
+}
+
+/// Exact spontaneous magnetisation per spin at the temperature, for the square Ising ferromagnets
+/// in zero field
+fn exact_magnetisation(settings: &LatticeSettings, temperature: f32) -> Option<f32> {
+    if !matches!(settings.model, Model::Ising | Model::Clock { q: 2 }) || settings.random_field.is_some() {
+        return None;
+    }
+    exact_critical_temperature(settings)?;
+
+    let [jx, jy, _] = settings.coupling_strengths.map(|strength| settings.exchange * strength);
+    lattice::spontaneous_magnetisation(jx, jy, temperature)
+}
+
+/// Line through an observable of the points of a temperature sweep, with a bar of ± one error at
+/// each point. Curves get colours in turn by `index`.
+fn plot_sweep_curve(plot_ui: &mut egui::plot::PlotUi, points: &[sweep::SweepPoint], observable: SweepObservable, name: &str, index: usize) {
+    let [r, g, b] = model::hue(index as f32 * 0.38);
+    let colour = egui::Color32::from_rgb(r, g, b);
+
+    let values: Vec<(f64, Estimate)> = points.iter()
+        .filter_map(|point| Some((point.temperature as f64, observable.of(point)?)))
+        .collect();
+    let line: Vec<[f64; 2]> = values.iter().map(|(temperature, value)| [*temperature, value.value as f64]).collect();
+
+    plot_ui.line(egui::plot::Line::new(line.clone()).color(colour).name(name));
+    plot_ui.points(egui::plot::Points::new(line).radius(2.0).color(colour).name(name));
+    for (temperature, value) in &values {
+        if let Some(error) = value.error {
+            let (low, high) = ((value.value - error) as f64, (value.value + error) as f64);
+            plot_ui.line(egui::plot::Line::new(vec![[*temperature, low], [*temperature, high]]).color(colour).name(name));
+        }
+    }
+}
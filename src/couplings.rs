@@ -0,0 +1,119 @@
+use std::{fmt::Display, io::Write, path::Path};
+
+/// Every coupling of a lattice, so one realisation of the disorder can be saved and studied
+/// again later.
+///
+/// Stored as text: a `sites N` line, then one `a b coupling` line per bond, and a
+/// `field site h` line per site with a local field (from a fixed boundary or random field). Lines
+/// starting with # are comments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Couplings {
+    pub sites: usize,
+    pub bonds: Vec<(usize, usize, f32)>,
+    /// Sites with a non-zero local field
+    pub fields: Vec<(usize, f32)>,
+}
+
+#[derive(Debug)]
+pub enum CouplingsError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl Display for CouplingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CouplingsError::Io(err) => write!(f, "{}", err),
+            CouplingsError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl From<std::io::Error> for CouplingsError {
+    fn from(err: std::io::Error) -> Self {
+        CouplingsError::Io(err)
+    }
+}
+
+impl Couplings {
+    /// `description` is written as a comment at the top
+    pub fn save(&self, path: &Path, description: &str) -> Result<(), CouplingsError> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+        writeln!(file, "# ising-gui couplings")?;
+        for line in description.lines() {
+            writeln!(file, "# {}", line)?;
+        }
+        writeln!(file, "sites {}", self.sites)?;
+
+        for (a, b, coupling) in &self.bonds {
+            writeln!(file, "{} {} {}", a, b, coupling)?;
+        }
+        for (site, field) in &self.fields {
+            writeln!(file, "field {} {}", site, field)?;
+        }
+
+        file.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Couplings, CouplingsError> {
+        let text = std::fs::read_to_string(path)?;
+
+        let mut sites: Option<usize> = None;
+        let mut bonds = Vec::new();
+        let mut fields = Vec::new();
+
+        for (line_index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |message: String| CouplingsError::Parse { line: line_index + 1, message };
+            let words: Vec<&str> = line.split_whitespace().collect();
+
+            if let ["sites", count] = words.as_slice() {
+                // the sites of the lines before were checked against the first count
+                if sites.is_some() {
+                    return Err(error("more than one \"sites N\" line".into()));
+                }
+                sites = Some(count.parse().map_err(|_| error("invalid site count".into()))?);
+                continue;
+            }
+
+            let Some(count) = sites else {
+                return Err(error("expected \"sites N\" before any bonds".into()));
+            };
+            let site = |word: &str| match word.parse::<usize>() {
+                Ok(site) if site < count => Ok(site),
+                _ => Err(error(format!("invalid site \"{}\" for {} sites", word, count))),
+            };
+            // NaN and infinity parse as numbers, but would poison every energy
+            let value = |word: &str, what: &str| match word.parse::<f32>() {
+                Ok(value) if value.is_finite() => Ok(value),
+                _ => Err(error(format!("invalid {} \"{}\"", what, word))),
+            };
+
+            match words.as_slice() {
+                ["field", i, field] => {
+                    let field = value(field, "field")?;
+                    fields.push((site(i)?, field));
+                },
+                [a, b, coupling] => {
+                    let coupling = value(coupling, "coupling")?;
+                    let (a, b) = (site(a)?, site(b)?);
+                    // a site can't interact with itself
+                    if a != b {
+                        bonds.push((a, b, coupling));
+                    }
+                },
+                _ => return Err(error("expected \"a b coupling\" or \"field site h\"".into())),
+            }
+        }
+
+        let sites = sites.ok_or(CouplingsError::Parse { line: 1, message: "missing \"sites N\" line".into() })?;
+
+        Ok(Couplings { sites, bonds, fields })
+    }
+}
@@ -0,0 +1,176 @@
+use std::f64::consts::TAU;
+
+/// Complex number as (real, imaginary)
+pub type Complex = [f64; 2];
+
+fn multiply(a: Complex, b: Complex) -> Complex {
+    [a[0] * b[0] - a[1] * b[1], a[0] * b[1] + a[1] * b[0]]
+}
+
+fn conjugate(a: Complex) -> Complex {
+    [a[0], -a[1]]
+}
+
+/// exp(-2πi k / n)
+fn twiddle(k: usize, n: usize) -> Complex {
+    let (sin, cos) = (-TAU * k as f64 / n as f64).sin_cos();
+    [cos, sin]
+}
+
+/// Discrete Fourier transform X_k = Σ_j x_j exp(-2πi jk / n) in place, of any length. Powers of
+/// two use the radix-2 Cooley-Tukey algorithm, other lengths Bluestein's, which turns the
+/// transform into a convolution of twice the length rounded up to a power of two.
+pub fn fft(data: &mut [Complex]) {
+    let n = data.len();
+    if n <= 1 {
+        return;
+    }
+
+    if n.is_power_of_two() {
+        radix_2(data);
+    } else {
+        bluestein(data);
+    }
+}
+
+/// Two-dimensional transform of `width` × `height` values stored row by row
+pub fn fft_2d(data: &mut [Complex], width: usize, height: usize) {
+    for row in data.chunks_exact_mut(width) {
+        fft(row);
+    }
+
+    let mut column = vec![[0.0; 2]; height];
+    for x in 0..width {
+        for (y, value) in column.iter_mut().enumerate() {
+            *value = data[x + y * width];
+        }
+        fft(&mut column);
+        for (y, value) in column.iter().enumerate() {
+            data[x + y * width] = *value;
+        }
+    }
+}
+
+fn radix_2(data: &mut [Complex]) {
+    let n = data.len();
+
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let mut length = 2;
+    while length <= n {
+        let step = twiddle(1, length);
+        for start in (0..n).step_by(length) {
+            let mut factor = [1.0, 0.0];
+            for k in 0..length / 2 {
+                let even = data[start + k];
+                let odd = multiply(factor, data[start + k + length / 2]);
+                data[start + k] = [even[0] + odd[0], even[1] + odd[1]];
+                data[start + k + length / 2] = [even[0] - odd[0], even[1] - odd[1]];
+                factor = multiply(factor, step);
+            }
+        }
+        length <<= 1;
+    }
+}
+
+fn bluestein(data: &mut [Complex]) {
+    let n = data.len();
+    let m = (2 * n - 1).next_power_of_two();
+
+    // chirp exp(-πi k² / n), with k² taken mod 2n to keep the angle accurate
+    let chirp: Vec<Complex> = (0..n).map(|k| twiddle(k * k % (2 * n), 2 * n)).collect();
+
+    let mut a = vec![[0.0; 2]; m];
+    for k in 0..n {
+        a[k] = multiply(data[k], chirp[k]);
+    }
+
+    let mut b = vec![[0.0; 2]; m];
+    b[0] = conjugate(chirp[0]);
+    for k in 1..n {
+        b[k] = conjugate(chirp[k]);
+        b[m - k] = conjugate(chirp[k]);
+    }
+
+    radix_2(&mut a);
+    radix_2(&mut b);
+    for (a, b) in a.iter_mut().zip(&b) {
+        *a = conjugate(multiply(*a, *b));
+    }
+    // the inverse transform, as the conjugate of the forward transform of the conjugate
+    radix_2(&mut a);
+
+    for k in 0..n {
+        data[k] = multiply(conjugate(a[k]), chirp[k]).map(|part| part / m as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Values without any symmetry the transform could hide mistakes behind
+    fn signal(n: usize) -> Vec<Complex> {
+        (0..n).map(|j| [(j as f64 * 0.7).sin() + 0.3 * j as f64, (j as f64 * 1.3).cos() - 0.1]).collect()
+    }
+
+    /// O(n²) transform straight from the definition
+    fn naive_dft(data: &[Complex]) -> Vec<Complex> {
+        let n = data.len();
+        (0..n).map(|k| {
+            data.iter().enumerate().fold([0.0; 2], |sum, (j, value)| {
+                let term = multiply(*value, twiddle(j * k % n, n));
+                [sum[0] + term[0], sum[1] + term[1]]
+            })
+        }).collect()
+    }
+
+    fn assert_close(actual: &[Complex], expected: &[Complex]) {
+        assert_eq!(actual.len(), expected.len());
+        for (index, (actual, expected)) in actual.iter().zip(expected).enumerate() {
+            let error = (actual[0] - expected[0]).hypot(actual[1] - expected[1]);
+            assert!(error < 1e-9 * expected.len().max(1) as f64 * (1.0 + expected[0].hypot(expected[1])), "at {index}: {actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn fft_matches_naive_dft() {
+        for n in [1, 2, 8, 64, 3, 5, 12, 100] {
+            let mut data = signal(n);
+            let expected = naive_dft(&data);
+            fft(&mut data);
+            assert_close(&data, &expected);
+        }
+    }
+
+    #[test]
+    fn fft_2d_matches_naive_dft() {
+        for (width, height) in [(8, 4), (12, 5), (6, 100)] {
+            let mut data = signal(width * height);
+
+            let expected: Vec<Complex> = (0..width * height).map(|k| {
+                let (kx, ky) = (k % width, k / width);
+                data.iter().enumerate().fold([0.0; 2], |sum, (j, value)| {
+                    let (jx, jy) = (j % width, j / width);
+                    let term = multiply(*value, multiply(twiddle(jx * kx % width, width), twiddle(jy * ky % height, height)));
+                    [sum[0] + term[0], sum[1] + term[1]]
+                })
+            }).collect();
+
+            fft_2d(&mut data, width, height);
+            assert_close(&data, &expected);
+        }
+    }
+}
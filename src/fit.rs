@@ -0,0 +1,45 @@
+/// Straight line y = slope x + intercept through some points, by least squares
+#[derive(Debug, Clone, Copy)]
+pub struct LinearFit {
+    pub slope: f64,
+    pub intercept: f64,
+    /// Standard error of the slope, from the scatter of the points about the line. None with only
+    /// two points, which the line passes through exactly.
+    pub slope_error: Option<f64>,
+}
+
+impl LinearFit {
+    pub fn at(&self, x: f64) -> f64 {
+        self.slope * x + self.intercept
+    }
+
+    /// Distance of each point above the line
+    pub fn residuals(&self, points: &[[f64; 2]]) -> Vec<[f64; 2]> {
+        points.iter().map(|[x, y]| [*x, y - self.at(*x)]).collect()
+    }
+}
+
+/// Least squares line through the points, None unless there are at least two with different x
+pub fn linear_fit(points: &[[f64; 2]]) -> Option<LinearFit> {
+    let count = points.len();
+    if count < 2 {
+        return None;
+    }
+
+    let mean_x = points.iter().map(|[x, _]| x).sum::<f64>() / count as f64;
+    let mean_y = points.iter().map(|[_, y]| y).sum::<f64>() / count as f64;
+    let spread_x = points.iter().map(|[x, _]| (x - mean_x).powi(2)).sum::<f64>();
+    if spread_x <= 0.0 {
+        return None;
+    }
+
+    let slope = points.iter().map(|[x, y]| (x - mean_x) * (y - mean_y)).sum::<f64>() / spread_x;
+    let intercept = mean_y - slope * mean_x;
+
+    let slope_error = (count > 2).then(|| {
+        let squares = points.iter().map(|[x, y]| (y - slope * x - intercept).powi(2)).sum::<f64>();
+        (squares / (count - 2) as f64 / spread_x).sqrt()
+    });
+
+    Some(LinearFit { slope, intercept, slope_error })
+}
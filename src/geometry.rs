@@ -0,0 +1,430 @@
+use std::fmt::Display;
+
+/// Arrangement of the sites, and which pairs of them interact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Geometry {
+    /// Ring of width sites with two neighbours each
+    Chain,
+    /// width × height sites with four neighbours each
+    Square,
+    /// Corner sharing triangles, three sites in each of width × height unit cells. Drawn as a
+    /// grid with one site missing from every 2 × 2 block, the third bond of each triangle running
+    /// along the block's diagonal.
+    Kagome,
+    /// width × height × depth sites with six neighbours each, drawn one z slice at a time
+    Cubic,
+    /// width × height sites, every one coupled equally to all the others (infinite range). Drawn
+    /// as a grid, but there are no bonds.
+    MeanField,
+    /// Network loaded from a file, see `Graph`
+    Graph,
+}
+
+impl Display for Geometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Geometry::Chain => f.write_str("Chain"),
+            Geometry::Square => f.write_str("Square"),
+            Geometry::Kagome => f.write_str("Kagome"),
+            Geometry::Cubic => f.write_str("Cubic"),
+            Geometry::MeanField => f.write_str("Mean Field"),
+            Geometry::Graph => f.write_str("Graph"),
+        }
+    }
+}
+
+/// Bond between two sites of a layout.
+#[derive(Debug, Clone, Copy)]
+pub struct Bond {
+    pub a: usize,
+    pub b: usize,
+    /// Which family of parallel bonds a nearest-neighbour bond belongs to, indexing
+    /// `Geometry::bond_directions`
+    pub direction: usize,
+    /// For each axis, whether the bond wraps around its periodic boundary: 1 if `a` is at the
+    /// high end of the axis and `b` at the low end, -1 the other way round, 0 if it doesn't wrap
+    pub wraps: [i8; 3],
+}
+
+impl Bond {
+    fn new(a: usize, b: usize, direction: usize, wraps: [i8; 3]) -> Bond {
+        Bond { a, b, direction, wraps }
+    }
+}
+
+/// Four sites around an elementary square of a layout, in order going round it.
+#[derive(Debug, Clone, Copy)]
+pub struct Plaquette {
+    pub sites: [usize; 4],
+    /// For each axis, whether the square wraps around its periodic boundary
+    pub wraps: [bool; 3],
+}
+
+/// Sites of a geometry, where they are drawn, and their bonds, assuming periodic boundaries.
+pub struct Layout {
+    pub image_width: usize,
+    pub image_height: usize,
+    /// Number of images needed to show every site, 1 for 2D geometries
+    pub layers: usize,
+    /// Pixel and layer of each site
+    pub positions: Vec<(usize, usize, usize)>,
+    /// Pairs of nearest neighbours, each listed once
+    pub bonds: Vec<Bond>,
+    /// Pairs of next-nearest neighbours (diagonals on square and cubic lattices), each listed once
+    pub next_nearest_bonds: Vec<Bond>,
+}
+
+impl Geometry {
+    /// Height is ignored for chains and depth for everything but cubic lattices. Graphs come with
+    /// their own layout, a single site stands in for them here.
+    pub fn layout(&self, width: usize, height: usize, depth: usize) -> Layout {
+        match self {
+            Geometry::Chain => chain(width),
+            Geometry::Square => square(width, height),
+            Geometry::Kagome => kagome(width, height),
+            Geometry::Cubic => cubic(width, height, depth),
+            Geometry::MeanField => mean_field(width, height),
+            Geometry::Graph => mean_field(1, 1),
+        }
+    }
+
+    /// Number of dimensions, 0 for graphs which have no size to choose
+    pub fn dimensions(&self) -> usize {
+        match self {
+            Geometry::Graph => 0,
+            Geometry::Chain => 1,
+            Geometry::Square | Geometry::Kagome | Geometry::MeanField => 2,
+            Geometry::Cubic => 3,
+        }
+    }
+
+    /// Bonds longer than nearest neighbours out to `cutoff` lattice spacings, with their lengths,
+    /// for power-law interactions. Only the chain, square and cubic lattices have them.
+    pub fn long_range_bonds(&self, width: usize, height: usize, depth: usize, cutoff: f32) -> Vec<(Bond, f32)> {
+        let sides = match self {
+            Geometry::Chain => [width, 1, 1],
+            Geometry::Square => [width, height, 1],
+            Geometry::Cubic => [width, height, depth],
+            Geometry::Kagome | Geometry::MeanField | Geometry::Graph => return Vec::new(),
+        };
+
+        // going further than half way round would reach the same sites again from the other side
+        let reach = |axis: usize| (cutoff.max(0.0) as usize).min((sides[axis] - 1) / 2) as isize;
+
+        let mut offsets = Vec::new();
+        for dz in -reach(2)..=reach(2) {
+            for dy in -reach(1)..=reach(1) {
+                for dx in -reach(0)..=reach(0) {
+                    // each pair once, r and -r are the same bond
+                    let distance = ((dx * dx + dy * dy + dz * dz) as f32).sqrt();
+                    if (dz, dy, dx) > (0, 0, 0) && distance > 1.0 && distance <= cutoff {
+                        offsets.push(([dx, dy, dz], distance));
+                    }
+                }
+            }
+        }
+
+        let [width, height, depth] = sides;
+        let mut bonds = Vec::with_capacity(width * height * depth * offsets.len());
+
+        for z in 0..depth {
+            for y in 0..height {
+                for x in 0..width {
+                    let here = x + y * width + z * width * height;
+
+                    for ([dx, dy, dz], distance) in &offsets {
+                        let (i, x_wrap) = offset(x, *dx, width);
+                        let (j, y_wrap) = offset(y, *dy, height);
+                        let (k, z_wrap) = offset(z, *dz, depth);
+                        bonds.push((Bond::new(here, i + j * width + k * width * height, 0, [x_wrap, y_wrap, z_wrap]), *distance));
+                    }
+                }
+            }
+        }
+
+        bonds
+    }
+
+    /// Bonds between sites two apart along x, for axial next-nearest-neighbour (ANNNI)
+    /// couplings. Only the chain, square and cubic lattices have them.
+    pub fn axial_bonds(&self, width: usize, height: usize, depth: usize) -> Vec<Bond> {
+        let [height, depth] = match self {
+            Geometry::Chain => [1, 1],
+            Geometry::Square => [height, 1],
+            Geometry::Cubic => [height, depth],
+            Geometry::Kagome | Geometry::MeanField | Geometry::Graph => return Vec::new(),
+        };
+
+        let mut bonds = Vec::with_capacity(width * height * depth);
+        for z in 0..depth {
+            for y in 0..height {
+                for x in 0..width {
+                    let row = y * width + z * width * height;
+                    let (i, x_wrap) = offset(x, 2, width);
+                    bonds.push(Bond::new(row + x, row + i, 0, [x_wrap, 0, 0]));
+                }
+            }
+        }
+
+        bonds.retain(|bond| bond.a != bond.b);
+        bonds
+    }
+
+    /// Elementary squares, for four-spin plaquette couplings. Only the square lattice and the
+    /// faces of the cubic lattice have them.
+    pub fn plaquettes(&self, width: usize, height: usize, depth: usize) -> Vec<Plaquette> {
+        let sides = match self {
+            Geometry::Square => [width, height, 1],
+            Geometry::Cubic => [width, height, depth],
+            Geometry::Chain | Geometry::Kagome | Geometry::MeanField | Geometry::Graph => return Vec::new(),
+        };
+        let planes: &[(usize, usize)] = match self {
+            Geometry::Cubic => &[(0, 1), (1, 2), (0, 2)],
+            _ => &[(0, 1)],
+        };
+
+        let [width, height, depth] = sides;
+        let index = |position: [usize; 3]| position[0] + position[1] * width + position[2] * width * height;
+
+        let mut plaquettes = Vec::with_capacity(width * height * depth * planes.len());
+        for z in 0..depth {
+            for y in 0..height {
+                for x in 0..width {
+                    for (first, second) in planes {
+                        let corner = [x, y, z];
+                        let step = |mut position: [usize; 3], axis: usize| {
+                            position[axis] = (position[axis] + 1) % sides[axis];
+                            position
+                        };
+                        let along = step(corner, *first);
+                        let across = step(along, *second);
+                        let back = step(corner, *second);
+
+                        let mut wrapped = [false; 3];
+                        for axis in [*first, *second] {
+                            wrapped[axis] = wraps(corner[axis], sides[axis]) != 0;
+                        }
+
+                        plaquettes.push(Plaquette { sites: [index(corner), index(along), index(across), index(back)], wraps: wrapped });
+                    }
+                }
+            }
+        }
+
+        // squares on a lattice only one site across meet themselves
+        plaquettes.retain(|plaquette| {
+            let [a, b, c, d] = plaquette.sites;
+            a != b && a != c && a != d && b != c && b != d && c != d
+        });
+        plaquettes
+    }
+
+    /// Names of the bond directions, which can be given different coupling strengths
+    pub fn bond_directions(&self) -> &'static [&'static str] {
+        match self {
+            Geometry::Chain | Geometry::MeanField | Geometry::Graph => &["J"],
+            Geometry::Square => &["Jx", "Jy"],
+            Geometry::Kagome => &["J Horizontal", "J Vertical", "J Diagonal"],
+            Geometry::Cubic => &["Jx", "Jy", "Jz"],
+        }
+    }
+
+    /// Direction, indexing `bond_directions`, of a nearest-neighbour bond between sites drawn in
+    /// neighbouring pixels of a layer, None if sites drawn that way are next-nearest neighbours
+    pub fn drawn_direction(&self, from: (usize, usize), to: (usize, usize)) -> Option<usize> {
+        let dx = to.0 as isize - from.0 as isize;
+        let dy = to.1 as isize - from.1 as isize;
+        match self {
+            _ if dy == 0 => Some(0),
+            Geometry::Square | Geometry::Kagome | Geometry::Cubic if dx == 0 => Some(1),
+            // a kagome lattice's diagonal bonds run up and to the right
+            Geometry::Kagome if dx == -dy => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Largest side length which still fits comfortably in memory
+    pub fn max_size(&self) -> usize {
+        match self {
+            Geometry::Chain | Geometry::Square | Geometry::Kagome | Geometry::MeanField | Geometry::Graph => 2048,
+            Geometry::Cubic => 128,
+        }
+    }
+}
+
+/// 1 if stepping forward from `i` wraps around an axis of length `length`, otherwise 0
+fn wraps(i: usize, length: usize) -> i8 {
+    (i + 1 == length) as i8
+}
+
+/// Index `step` sites along an axis of length `length` from `i`, and whether that wraps around
+/// the axis (as in `Bond::wraps`)
+fn offset(i: usize, step: isize, length: usize) -> (usize, i8) {
+    let j = i as isize + step;
+    let wrap = if j >= length as isize { 1 } else if j < 0 { -1 } else { 0 };
+
+    (j.rem_euclid(length as isize) as usize, wrap)
+}
+
+fn chain(size: usize) -> Layout {
+    let positions = (0..size).map(|x| (x, 0, 0)).collect();
+    let bonds = (0..size)
+        .map(|x| Bond::new(x, (x + 1) % size, 0, [wraps(x, size), 0, 0]))
+        .filter(|bond| bond.a != bond.b)
+        .collect();
+    let next_nearest_bonds = (0..size)
+        .map(|x| {
+            let (b, wrap) = offset(x, 2, size);
+            Bond::new(x, b, 0, [wrap, 0, 0])
+        })
+        .filter(|bond| bond.a != bond.b)
+        .collect();
+
+    Layout {
+        image_width: size,
+        image_height: 1,
+        layers: 1,
+        positions,
+        bonds,
+        next_nearest_bonds,
+    }
+}
+
+fn square(width: usize, height: usize) -> Layout {
+    let index = |x: usize, y: usize| (x % width) + (y % height) * width;
+
+    let mut positions = Vec::with_capacity(width * height);
+    let mut bonds = Vec::with_capacity(2 * width * height);
+    let mut next_nearest_bonds = Vec::with_capacity(2 * width * height);
+
+    for y in 0..height {
+        for x in 0..width {
+            positions.push((x, y, 0));
+
+            // right and down, left and up come from the neighbours
+            bonds.push(Bond::new(index(x, y), index(x + 1, y), 0, [wraps(x, width), 0, 0]));
+            bonds.push(Bond::new(index(x, y), index(x, y + 1), 1, [0, wraps(y, height), 0]));
+
+            // down and to the right, and down and to the left
+            for step in [1, -1] {
+                let (i, x_wrap) = offset(x, step, width);
+                next_nearest_bonds.push(Bond::new(index(x, y), index(i, y + 1), 0, [x_wrap, wraps(y, height), 0]));
+            }
+        }
+    }
+
+    // a single row or column has its bonds wrap onto itself
+    bonds.retain(|bond| bond.a != bond.b);
+    next_nearest_bonds.retain(|bond| bond.a != bond.b);
+
+    Layout {
+        image_width: width,
+        image_height: height,
+        layers: 1,
+        positions,
+        bonds,
+        next_nearest_bonds,
+    }
+}
+
+fn kagome(width: usize, height: usize) -> Layout {
+    // sublattices A, B, C of cell (i, j) are drawn at (2i, 2j), (2i + 1, 2j) and (2i, 2j + 1)
+    let cell = |i: usize, j: usize| 3 * ((i % width) + (j % height) * width);
+    let (a, b, c) = (0, 1, 2);
+
+    let mut positions = Vec::with_capacity(3 * width * height);
+    let mut bonds = Vec::with_capacity(6 * width * height);
+    let mut next_nearest_bonds = Vec::with_capacity(6 * width * height);
+
+    for j in 0..height {
+        for i in 0..width {
+            positions.push((2 * i, 2 * j, 0));
+            positions.push((2 * i + 1, 2 * j, 0));
+            positions.push((2 * i, 2 * j + 1, 0));
+
+            let here = cell(i, j);
+
+            // triangle within the cell, then the triangle pointing the other way, which is
+            // shared with the cells to the right, below, and up and to the right
+            bonds.push(Bond::new(here + a, here + b, 0, [0; 3]));
+            bonds.push(Bond::new(here + a, here + c, 1, [0; 3]));
+            bonds.push(Bond::new(here + b, here + c, 2, [0; 3]));
+            bonds.push(Bond::new(here + b, cell(i + 1, j) + a, 0, [wraps(i, width), 0, 0]));
+            bonds.push(Bond::new(here + c, cell(i, j + 1) + a, 1, [0, wraps(j, height), 0]));
+            bonds.push(Bond::new(here + b, cell(i + 1, j + height - 1) + c, 2, [wraps(i, width), -((j == 0) as i8), 0]));
+
+            // sites across the hexagons, four for each site
+            for (from, to, di, dj) in [(a, b, -1, 1), (a, b, 0, -1), (a, c, 1, -1), (a, c, -1, 0), (b, c, 0, -1), (b, c, 1, 0)] {
+                let (i2, x_wrap) = offset(i, di, width);
+                let (j2, y_wrap) = offset(j, dj, height);
+                next_nearest_bonds.push(Bond::new(here + from, cell(i2, j2) + to, 0, [x_wrap, y_wrap, 0]));
+            }
+        }
+    }
+
+    // a single cell has its bonds wrap onto itself
+    bonds.retain(|bond| bond.a != bond.b);
+    next_nearest_bonds.retain(|bond| bond.a != bond.b);
+
+    Layout {
+        image_width: 2 * width,
+        image_height: 2 * height,
+        layers: 1,
+        positions,
+        bonds,
+        next_nearest_bonds,
+    }
+}
+
+fn cubic(width: usize, height: usize, depth: usize) -> Layout {
+    let index = |x: usize, y: usize, z: usize| (x % width) + (y % height) * width + (z % depth) * width * height;
+
+    let mut positions = Vec::with_capacity(width * height * depth);
+    let mut bonds = Vec::with_capacity(3 * width * height * depth);
+    let mut next_nearest_bonds = Vec::with_capacity(6 * width * height * depth);
+
+    for z in 0..depth {
+        for y in 0..height {
+            for x in 0..width {
+                positions.push((x, y, z));
+
+                let here = index(x, y, z);
+                bonds.push(Bond::new(here, index(x + 1, y, z), 0, [wraps(x, width), 0, 0]));
+                bonds.push(Bond::new(here, index(x, y + 1, z), 1, [0, wraps(y, height), 0]));
+                bonds.push(Bond::new(here, index(x, y, z + 1), 2, [0, 0, wraps(z, depth)]));
+
+                // face diagonals, stepping forward along the second axis of each face
+                for step in [1, -1] {
+                    let (i, x_wrap) = offset(x, step, width);
+                    let (j, y_wrap) = offset(y, step, height);
+                    next_nearest_bonds.push(Bond::new(here, index(i, y + 1, z), 0, [x_wrap, wraps(y, height), 0]));
+                    next_nearest_bonds.push(Bond::new(here, index(i, y, z + 1), 0, [x_wrap, 0, wraps(z, depth)]));
+                    next_nearest_bonds.push(Bond::new(here, index(x, j, z + 1), 0, [0, y_wrap, wraps(z, depth)]));
+                }
+            }
+        }
+    }
+
+    bonds.retain(|bond| bond.a != bond.b);
+    next_nearest_bonds.retain(|bond| bond.a != bond.b);
+
+    Layout {
+        image_width: width,
+        image_height: height,
+        layers: depth,
+        positions,
+        bonds,
+        next_nearest_bonds,
+    }
+}
+
+fn mean_field(width: usize, height: usize) -> Layout {
+    Layout {
+        image_width: width,
+        image_height: height,
+        layers: 1,
+        positions: (0..height).flat_map(|y| (0..width).map(move |x| (x, y, 0))).collect(),
+        bonds: Vec::new(),
+        next_nearest_bonds: Vec::new(),
+    }
+}
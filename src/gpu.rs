@@ -0,0 +1,570 @@
+use std::{num::NonZeroU64, sync::Arc};
+
+use eframe::{egui_wgpu, wgpu};
+
+use crate::{lattice::Lattice, spin::Spin};
+
+/// Offset between the per-dispatch parameter blocks, the default minimum uniform buffer offset
+/// alignment.
+const PARAMS_STRIDE: usize = 256;
+const PARAMS_SIZE: usize = 32;
+const WORKGROUP_SIZE: u32 = 8;
+
+const SWEEP_SHADER: &str = r#"
+struct Params {
+    size: u32,
+    parity: u32,
+    seed: u32,
+    step: u32,
+    temperature: f32,
+    magnetic_field: f32,
+    coupling: f32,
+    pass_index: u32,
+}
+
+@group(0) @binding(0) var<storage, read_write> spins: array<i32>;
+@group(0) @binding(1) var<uniform> params: Params;
+// accepted flips, one counter per pass so they can't overflow
+@group(0) @binding(2) var<storage, read_write> accepted: array<atomic<u32>>;
+
+// PCG hash
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// one invocation per site of the current sublattice
+@compute @workgroup_size(8, 8)
+fn sweep(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = params.size;
+    let y = id.y;
+    let x = id.x * 2u + (y + params.parity) % 2u;
+
+    if (x >= size || y >= size) {
+        return;
+    }
+
+    let i = x + y * size;
+    let left = (x + size - 1u) % size + y * size;
+    let right = (x + 1u) % size + y * size;
+    let up = x + ((y + size - 1u) % size) * size;
+    let down = x + ((y + 1u) % size) * size;
+
+    let neighbour_sum = params.coupling * f32(spins[left] + spins[right] + spins[up] + spins[down]);
+    let d_energy = 2.0 * f32(spins[i]) * (neighbour_sum + params.magnetic_field);
+
+    var accept = d_energy <= 0.0;
+    if (!accept && params.temperature > 0.0) {
+        let r = f32(hash(i ^ hash(params.seed + params.step))) / 4294967296.0;
+        accept = r < exp(-d_energy / params.temperature);
+    }
+
+    if (accept) {
+        spins[i] = -spins[i];
+        atomicAdd(&accepted[params.pass_index], 1u);
+    }
+}
+"#;
+
+const RENDER_SHADER: &str = r#"
+struct View {
+    size: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+@group(0) @binding(0) var<storage, read> spins: array<i32>;
+@group(0) @binding(1) var<uniform> view: View;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// quad covering the callback rect, drawn as a triangle strip
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32(index & 1u), f32(index >> 1u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = view.size;
+    let x = min(u32(in.uv.x * f32(size)), size - 1u);
+    let y = min(u32(in.uv.y * f32(size)), size - 1u);
+
+    if (spins[x + y * size] > 0) {
+        // blue
+        return vec4<f32>(0.0, 0.0, 1.0, 1.0);
+    }
+    // red
+    return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+}
+"#;
+
+/// Checkerboard Metropolis sweeps in a compute shader. The spins live in a GPU buffer which is
+/// also read directly when drawing the lattice. They're only copied back to the CPU lattice when
+/// something there needs them, such as a measurement or a snapshot for the GUI.
+pub struct GpuBackend {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    size: usize,
+    spins: wgpu::Buffer,
+    staging: wgpu::Buffer,
+    params: wgpu::Buffer,
+    // accepted flip counter per pass
+    accepted: wgpu::Buffer,
+    accepted_staging: wgpu::Buffer,
+    // number of passes that fit in `params`, and in `accepted`
+    params_capacity: usize,
+    accepted_capacity: usize,
+    // passes and sweeps run since the spins were last read back
+    pending_passes: usize,
+    pending_sweeps: usize,
+    compute_pipeline: wgpu::ComputePipeline,
+    compute_bind_group_layout: wgpu::BindGroupLayout,
+    compute_bind_group: wgpu::BindGroup,
+    step: u32,
+}
+
+/// Stored in the renderer's paint callback resources, used to draw the spin buffer.
+struct GpuRenderResources {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+impl GpuBackend {
+    /// Upload the lattice to the GPU. The lattice must have uniform couplings.
+    pub fn new(render_state: &egui_wgpu::RenderState, lattice: &Lattice) -> GpuBackend {
+        let device = render_state.device.clone();
+        let queue = render_state.queue.clone();
+
+        let size = lattice.size();
+        let bytes = (size * size * std::mem::size_of::<i32>()) as u64;
+
+        let spins = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("lattice-spins"),
+            size: bytes,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("lattice-spins-staging"),
+            size: bytes,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let params_capacity = 2;
+        let accepted_capacity = 2;
+        let params = create_params_buffer(&device, params_capacity);
+        let (accepted, accepted_staging) = create_accepted_buffers(&device, accepted_capacity);
+
+        let compute_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lattice-sweep"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: NonZeroU64::new(PARAMS_SIZE as u64),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("lattice-sweep"),
+            source: wgpu::ShaderSource::Wgsl(SWEEP_SHADER.into()),
+        });
+
+        let compute_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("lattice-sweep"),
+            bind_group_layouts: &[&compute_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("lattice-sweep"),
+            layout: Some(&compute_pipeline_layout),
+            module: &compute_shader,
+            entry_point: "sweep",
+        });
+
+        let compute_bind_group = create_compute_bind_group(&device, &compute_bind_group_layout, &spins, &params, &accepted);
+
+        let mut backend = GpuBackend {
+            device,
+            queue,
+            size,
+            spins,
+            staging,
+            params,
+            accepted,
+            accepted_staging,
+            params_capacity,
+            accepted_capacity,
+            pending_passes: 0,
+            pending_sweeps: 0,
+            compute_pipeline,
+            compute_bind_group_layout,
+            compute_bind_group,
+            step: 0,
+        };
+
+        backend.upload(lattice);
+        backend.init_render_resources(render_state);
+
+        backend
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Overwrite the GPU spins with the lattice's, dropping any sweeps not yet read back
+    pub fn upload(&mut self, lattice: &Lattice) {
+        let data: Vec<u8> = lattice.spins().iter()
+            .flat_map(|spin| Into::<i32>::into(*spin).to_ne_bytes())
+            .collect();
+
+        self.queue.write_buffer(&self.spins, 0, &data);
+
+        if self.is_pending() {
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("lattice-upload"),
+            });
+            encoder.clear_buffer(&self.accepted, 0, NonZeroU64::new(counters_size(self.pending_passes)));
+            self.queue.submit(Some(encoder.finish()));
+            self.pending_passes = 0;
+            self.pending_sweeps = 0;
+        }
+    }
+
+    /// Queue the given number of sweeps without waiting for them. The lattice only supplies the
+    /// parameters, its spins stay stale until `download`.
+    pub fn run(&mut self, sweeps: usize, lattice: &mut Lattice) {
+        let Some(coupling) = lattice.uniform_coupling() else {
+            return;
+        };
+
+        let passes = 2 * sweeps;
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("lattice-sweep"),
+        });
+
+        if passes > self.params_capacity {
+            self.params_capacity = passes.next_power_of_two();
+            self.params = create_params_buffer(&self.device, self.params_capacity);
+            self.compute_bind_group = create_compute_bind_group(&self.device, &self.compute_bind_group_layout, &self.spins, &self.params, &self.accepted);
+        }
+
+        // counters of passes not yet read back are carried over into the bigger buffers
+        if self.pending_passes + passes > self.accepted_capacity {
+            self.accepted_capacity = (self.pending_passes + passes).next_power_of_two();
+            let (accepted, accepted_staging) = create_accepted_buffers(&self.device, self.accepted_capacity);
+            if self.pending_passes > 0 {
+                encoder.copy_buffer_to_buffer(&self.accepted, 0, &accepted, 0, counters_size(self.pending_passes));
+            }
+            (self.accepted, self.accepted_staging) = (accepted, accepted_staging);
+            self.compute_bind_group = create_compute_bind_group(&self.device, &self.compute_bind_group_layout, &self.spins, &self.params, &self.accepted);
+        }
+
+        let seed = lattice.gpu_seed();
+        let mut data = vec![0; passes * PARAMS_STRIDE];
+
+        for (pass, block) in data.chunks_exact_mut(PARAMS_STRIDE).enumerate() {
+            let fields = [
+                (self.size as u32).to_ne_bytes(),
+                (pass as u32 % 2).to_ne_bytes(),
+                seed.to_ne_bytes(),
+                self.step.wrapping_add(pass as u32).to_ne_bytes(),
+                lattice.temperature.to_ne_bytes(),
+                lattice.magnetic_field.to_ne_bytes(),
+                coupling.to_ne_bytes(),
+                ((self.pending_passes + pass) as u32).to_ne_bytes(),
+            ];
+
+            for (field, bytes) in block.chunks_exact_mut(4).zip(fields) {
+                field.copy_from_slice(&bytes);
+            }
+        }
+
+        self.step = self.step.wrapping_add(passes as u32);
+        self.queue.write_buffer(&self.params, 0, &data);
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("lattice-sweep"),
+            });
+
+            compute_pass.set_pipeline(&self.compute_pipeline);
+
+            let workgroups_x = ((self.size as u32 + 1) / 2).div_ceil(WORKGROUP_SIZE);
+            let workgroups_y = (self.size as u32).div_ceil(WORKGROUP_SIZE);
+
+            for pass in 0..passes {
+                compute_pass.set_bind_group(0, &self.compute_bind_group, &[(pass * PARAMS_STRIDE) as u32]);
+                compute_pass.dispatch_workgroups(workgroups_x, workgroups_y, 1);
+            }
+        }
+
+        self.queue.submit(Some(encoder.finish()));
+        self.pending_passes += passes;
+        self.pending_sweeps += sweeps;
+    }
+
+    /// Whether sweeps have run since the spins were last copied back
+    pub fn is_pending(&self) -> bool {
+        self.pending_passes > 0
+    }
+
+    /// Wait for the queued sweeps, then copy the spins and the flips accepted back into the
+    /// lattice
+    pub fn download(&mut self, lattice: &mut Lattice) {
+        if !self.is_pending() {
+            return;
+        }
+
+        let counter_bytes = counters_size(self.pending_passes);
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("lattice-download"),
+        });
+        encoder.copy_buffer_to_buffer(&self.spins, 0, &self.staging, 0, self.spins.size());
+        encoder.copy_buffer_to_buffer(&self.accepted, 0, &self.accepted_staging, 0, counter_bytes);
+        encoder.clear_buffer(&self.accepted, 0, NonZeroU64::new(counter_bytes));
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = self.staging.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| ());
+        let accepted_slice = self.accepted_staging.slice(..counter_bytes);
+        accepted_slice.map_async(wgpu::MapMode::Read, |_| ());
+        self.device.poll(wgpu::Maintain::Wait);
+
+        {
+            let data = accepted_slice.get_mapped_range();
+            let accepted: u64 = data.chunks_exact(4)
+                .map(|bytes| u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64)
+                .sum();
+
+            lattice.count_flips((self.pending_sweeps * self.size * self.size) as u64, accepted);
+        }
+
+        self.accepted_staging.unmap();
+
+        {
+            let data = slice.get_mapped_range();
+
+            lattice.load_spins(|spins| {
+                for (spin, bytes) in spins.iter_mut().zip(data.chunks_exact(4)) {
+                    *spin = Spin::from(i32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+                }
+            });
+        }
+
+        self.staging.unmap();
+        self.pending_passes = 0;
+        self.pending_sweeps = 0;
+    }
+
+    fn init_render_resources(&self, render_state: &egui_wgpu::RenderState) {
+        let device = &self.device;
+
+        let view = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("lattice-view"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        self.queue.write_buffer(&view, 0, &[(self.size as u32).to_ne_bytes(), [0; 4], [0; 4], [0; 4]].concat());
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lattice-render"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lattice-render"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.spins.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: view.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("lattice-render"),
+            source: wgpu::ShaderSource::Wgsl(RENDER_SHADER.into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("lattice-render"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("lattice-render"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(render_state.target_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        render_state.renderer.write().paint_callback_resources.insert(GpuRenderResources {
+            pipeline,
+            bind_group,
+        });
+    }
+}
+
+/// Paint callback drawing the spin buffer of the most recently created backend into `rect`
+pub fn paint_callback(rect: egui::Rect) -> egui::PaintCallback {
+    let callback = egui_wgpu::CallbackFn::new()
+        .paint(|_info, render_pass, paint_callback_resources| {
+            if let Some(resources) = paint_callback_resources.get::<GpuRenderResources>() {
+                render_pass.set_pipeline(&resources.pipeline);
+                render_pass.set_bind_group(0, &resources.bind_group, &[]);
+                render_pass.draw(0..4, 0..1);
+            }
+        });
+
+    egui::PaintCallback {
+        rect,
+        callback: Arc::new(callback),
+    }
+}
+
+fn create_params_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("lattice-sweep-params"),
+        size: (capacity * PARAMS_STRIDE) as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Bytes taken by the accepted flip counters of `passes` passes
+fn counters_size(passes: usize) -> u64 {
+    (passes * std::mem::size_of::<u32>()) as u64
+}
+
+/// Per-pass accepted flip counters and the buffer they are read back through
+fn create_accepted_buffers(device: &wgpu::Device, capacity: usize) -> (wgpu::Buffer, wgpu::Buffer) {
+    let size = counters_size(capacity);
+
+    let accepted = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("lattice-sweep-accepted"),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("lattice-sweep-accepted-staging"),
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    (accepted, staging)
+}
+
+fn create_compute_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, spins: &wgpu::Buffer, params: &wgpu::Buffer, accepted: &wgpu::Buffer) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("lattice-sweep"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: spins.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: params,
+                    offset: 0,
+                    size: NonZeroU64::new(PARAMS_SIZE as u64),
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: accepted.as_entire_binding(),
+            },
+        ],
+    })
+}
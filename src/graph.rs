@@ -0,0 +1,681 @@
+use std::{collections::{HashMap, HashSet}, fmt::Display, path::Path};
+
+use rand::Rng;
+
+use crate::geometry::{Bond, Layout};
+
+/// Network of sites loaded from a file, any pair of which may interact.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Graph {
+    /// File the graph came from
+    pub name: String,
+    /// Label of each node in the file
+    pub labels: Vec<String>,
+    /// Pairs of connected nodes with the weight their coupling is multiplied by
+    pub edges: Vec<(usize, usize, f32)>,
+}
+
+#[derive(Debug)]
+pub enum GraphError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+    Empty,
+}
+
+impl Display for GraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphError::Io(err) => write!(f, "{}", err),
+            GraphError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            GraphError::Empty => f.write_str("the file has no nodes"),
+        }
+    }
+}
+
+impl From<std::io::Error> for GraphError {
+    fn from(err: std::io::Error) -> Self {
+        GraphError::Io(err)
+    }
+}
+
+impl Graph {
+    /// Reads a DOT file (.dot, .gv), JSON (.json) or an edge list (anything else)
+    pub fn load(path: &Path) -> Result<Graph, GraphError> {
+        let text = std::fs::read_to_string(path)?;
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+
+        let mut builder = Builder::default();
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("dot" | "gv") => builder.parse_dot(&text)?,
+            Some("json") => builder.parse_json(&text)?,
+            _ => builder.parse_edge_list(&text)?,
+        }
+
+        if builder.labels.is_empty() {
+            return Err(GraphError::Empty);
+        }
+
+        Ok(Graph {
+            name,
+            labels: builder.labels,
+            edges: builder.edges,
+        })
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Nodes drawn in order, row by row, on the smallest square grid which fits them
+    pub fn layout(&self) -> Layout {
+        let side = (1..).find(|side| side * side >= self.node_count()).unwrap_or(1);
+
+        Layout {
+            image_width: side,
+            image_height: self.node_count().div_ceil(side),
+            layers: 1,
+            positions: (0..self.node_count()).map(|node| (node % side, node / side, 0)).collect(),
+            // the edges have weights which a layout can't carry, see `bonds`
+            bonds: Vec::new(),
+            next_nearest_bonds: Vec::new(),
+        }
+    }
+
+    /// Edges as bonds, with their weights
+    pub fn bonds(&self) -> Vec<(Bond, f32)> {
+        self.edges.iter()
+            .map(|(a, b, weight)| (Bond { a: *a, b: *b, direction: 0, wraps: [0; 3] }, *weight))
+            .collect()
+    }
+}
+
+/// Built-in random network models.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RandomGraph {
+    /// Ring where every node is bonded to its `neighbours` nearest nodes (half on each side),
+    /// each bond then moved to a random node with probability `rewiring`
+    WattsStrogatz { nodes: usize, neighbours: usize, rewiring: f64 },
+    /// Every pair of nodes bonded independently, with a probability giving `mean_degree` bonds
+    /// per node on average
+    ErdosRenyi { nodes: usize, mean_degree: f64 },
+}
+
+impl Display for RandomGraph {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RandomGraph::WattsStrogatz { nodes, neighbours, rewiring } => {
+                write!(f, "Watts-Strogatz (N = {}, k = {}, p = {})", nodes, neighbours, rewiring)
+            },
+            RandomGraph::ErdosRenyi { nodes, mean_degree } => {
+                write!(f, "Erdos-Renyi (N = {}, mean degree {})", nodes, mean_degree)
+            },
+        }
+    }
+}
+
+impl RandomGraph {
+    pub fn generate(&self, rng: &mut impl Rng) -> Graph {
+        let (nodes, edges) = match *self {
+            RandomGraph::WattsStrogatz { nodes, neighbours, rewiring } => (nodes, watts_strogatz(nodes, neighbours, rewiring, rng)),
+            RandomGraph::ErdosRenyi { nodes, mean_degree } => (nodes, erdos_renyi(nodes, mean_degree, rng)),
+        };
+
+        Graph {
+            name: self.to_string(),
+            labels: (0..nodes).map(|node| node.to_string()).collect(),
+            edges: edges.into_iter().map(|(a, b)| (a, b, 1.0)).collect(),
+        }
+    }
+}
+
+fn watts_strogatz(nodes: usize, neighbours: usize, rewiring: f64, rng: &mut impl Rng) -> Vec<(usize, usize)> {
+    // can't have more neighbours than there are other nodes
+    let reach = (neighbours / 2).min(nodes.saturating_sub(1) / 2);
+
+    let mut edges: Vec<(usize, usize)> = (0..nodes)
+        .flat_map(|a| (1..=reach).map(move |step| (a, (a + step) % nodes)))
+        .collect();
+    let mut present: HashSet<(usize, usize)> = edges.iter().map(|(a, b)| (*a.min(b), *a.max(b))).collect();
+
+    for edge in &mut edges {
+        if !rng.gen_bool(rewiring.clamp(0.0, 1.0)) {
+            continue;
+        }
+
+        // keep a, move the other end somewhere it isn't bonded already, giving up after a few
+        // tries on dense graphs
+        let a = edge.0;
+        for _ in 0..16 {
+            let b = rng.gen_range(0..nodes);
+            if b != a && !present.contains(&(a.min(b), a.max(b))) {
+                present.remove(&(a.min(edge.1), a.max(edge.1)));
+                present.insert((a.min(b), a.max(b)));
+                edge.1 = b;
+                break;
+            }
+        }
+    }
+
+    edges
+}
+
+fn erdos_renyi(nodes: usize, mean_degree: f64, rng: &mut impl Rng) -> Vec<(usize, usize)> {
+    let p = (mean_degree / nodes.saturating_sub(1).max(1) as f64).clamp(0.0, 1.0);
+    let mut edges = Vec::new();
+
+    if p <= 0.0 {
+        return edges;
+    }
+
+    // jump straight to the next bonded pair with geometrically distributed gaps (Batagelj &
+    // Brandes), instead of trying all N^2 / 2 pairs
+    let log_q = (1.0 - p).ln();
+    let (mut a, mut b) = (1, -1);
+    while a < nodes as i64 {
+        let gap = if p >= 1.0 { 0 } else { ((1.0 - rng.gen::<f64>()).ln() / log_q).floor() as i64 };
+        b += 1 + gap;
+        while b >= a && a < nodes as i64 {
+            b -= a;
+            a += 1;
+        }
+        if a < nodes as i64 {
+            edges.push((a as usize, b as usize));
+        }
+    }
+
+    edges
+}
+
+/// Numbers nodes in the order they first appear in the file
+#[derive(Default)]
+struct Builder {
+    labels: Vec<String>,
+    indices: HashMap<String, usize>,
+    edges: Vec<(usize, usize, f32)>,
+    // index in `edges` of each pair of nodes, smaller node first
+    edge_indices: HashMap<(usize, usize), usize>,
+}
+
+impl Builder {
+    fn node(&mut self, label: &str) -> usize {
+        if let Some(index) = self.indices.get(label) {
+            return *index;
+        }
+
+        self.labels.push(label.to_string());
+        self.indices.insert(label.to_string(), self.labels.len() - 1);
+        self.labels.len() - 1
+    }
+
+    /// Edges are undirected, so `a b` and `b a` are the same edge. One listed twice with the same
+    /// weight is kept once, with a different weight it's an error.
+    fn edge(&mut self, a: &str, b: &str, weight: f32, line: usize) -> Result<(), GraphError> {
+        let (a_index, b_index) = (self.node(a), self.node(b));
+
+        // a site can't interact with itself
+        if a_index == b_index {
+            return Ok(());
+        }
+
+        let pair = (a_index.min(b_index), a_index.max(b_index));
+        match self.edge_indices.get(&pair) {
+            Some(index) if self.edges[*index].2 == weight => Ok(()),
+            Some(index) => Err(GraphError::Parse {
+                line,
+                message: format!("edge {} {} listed again with weight {} instead of {}", a, b, weight, self.edges[*index].2),
+            }),
+            None => {
+                self.edge_indices.insert(pair, self.edges.len());
+                self.edges.push((a_index, b_index, weight));
+                Ok(())
+            },
+        }
+    }
+
+    /// One edge per line, `a b` or `a b weight`, separated by whitespace or commas. Lines with a
+    /// single node add it unconnected, lines starting with # or % are comments.
+    fn parse_edge_list(&mut self, text: &str) -> Result<(), GraphError> {
+        for (line_index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('%') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == ',').filter(|field| !field.is_empty()).collect();
+
+            match fields.as_slice() {
+                [node] => {
+                    self.node(node);
+                },
+                [a, b] => self.edge(a, b, 1.0, line_index + 1)?,
+                [a, b, weight, ..] => {
+                    let weight = weight.parse().map_err(|_| GraphError::Parse {
+                        line: line_index + 1,
+                        message: format!("invalid weight \"{}\"", weight),
+                    })?;
+                    self.edge(a, b, weight, line_index + 1)?;
+                },
+                [] => {},
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Node and edge statements of a DOT graph, directed edges are treated as undirected. Edge
+    /// chains (`a -- b -- c`) and a `weight` attribute are understood, subgraphs are flattened and
+    /// every other attribute is ignored.
+    fn parse_dot(&mut self, text: &str) -> Result<(), GraphError> {
+        let text = strip_dot_comments(text);
+
+        let (Some(start), Some(end)) = (text.find('{'), text.rfind('}')) else {
+            return Err(GraphError::Parse { line: 1, message: "expected a graph { ... }".into() });
+        };
+
+        let body = &text[start + 1..end];
+        let first_line = text[..=start].lines().count();
+
+        for (line_index, row) in body.split('\n').enumerate() {
+            for statement in row.split(';') {
+                self.parse_dot_statement(statement, first_line + line_index)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_dot_statement(&mut self, statement: &str, line: usize) -> Result<(), GraphError> {
+        let statement = statement.replace(['{', '}'], " ");
+        let (statement, attributes) = match statement.find('[') {
+            Some(index) => (&statement[..index], &statement[index..]),
+            None => (statement.as_str(), ""),
+        };
+        let mut statement = statement.trim();
+
+        let keyword = statement.split_whitespace().next().unwrap_or_default();
+        let is_edge = statement.contains("--") || statement.contains("->");
+
+        // edges opening a subgraph, `subgraph name { a -- b`
+        if keyword == "subgraph" && is_edge {
+            let mut words = statement.splitn(3, char::is_whitespace).filter(|word| !word.is_empty());
+            words.next();
+            words.next();
+            statement = words.next().unwrap_or_default().trim();
+        }
+
+        if statement.is_empty()
+            || matches!(keyword, "graph" | "digraph" | "node" | "edge" | "strict")
+            || (keyword == "subgraph" && !is_edge)
+            || (statement.contains('=') && !is_edge)
+        {
+            return Ok(());
+        }
+
+        let weight = match dot_attribute(attributes, "weight") {
+            Some(weight) => weight.parse().map_err(|_| GraphError::Parse {
+                line,
+                message: format!("invalid weight \"{}\"", weight),
+            })?,
+            None => 1.0,
+        };
+
+        let nodes: Vec<String> = statement.replace("->", "--")
+            .split("--")
+            .map(dot_node_id)
+            .collect();
+
+        if nodes.iter().any(|node| node.is_empty()) {
+            return Err(GraphError::Parse { line, message: format!("expected a node in \"{}\"", statement) });
+        }
+
+        match nodes.as_slice() {
+            [node] => {
+                self.node(node);
+            },
+            nodes => {
+                for pair in nodes.windows(2) {
+                    self.edge(&pair[0], &pair[1], weight, line)?;
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// A graph in one of the common JSON layouts:
+    /// - node-link, as written by networkx and d3: `{"nodes": [{"id": "a"}, ...], "links": [{"source":
+    ///   "a", "target": "b", "weight": 2}, ...]}`, with `edges` also accepted for `links`
+    /// - an edge list: `[["a", "b"], ["b", "c", 2], ...]`, or edges as objects as above
+    /// - adjacency: `{"a": ["b", "c"], ...}`, or `{"a": {"b": 2}, ...}` with weights
+    ///
+    /// Nodes may be strings or numbers.
+    fn parse_json(&mut self, text: &str) -> Result<(), GraphError> {
+        let document = JsonParser { text: text.as_bytes(), position: 0 }.document()?;
+
+        match &document.kind {
+            Json::Array(edges) => {
+                for edge in edges {
+                    self.json_edge(edge)?;
+                }
+            },
+            Json::Object(fields) if fields.iter().any(|(key, _)| matches!(key.as_str(), "nodes" | "links" | "edges")) => {
+                for (key, value) in fields {
+                    let Json::Array(items) = &value.kind else {
+                        continue;
+                    };
+
+                    match key.as_str() {
+                        "nodes" => for node in items {
+                            let id = match &node.kind {
+                                Json::Object(_) => node.field("id").ok_or_else(|| node.error("expected a node with an \"id\""))?,
+                                _ => node,
+                            };
+                            self.node(&id.node_label()?);
+                        },
+                        "links" | "edges" => for edge in items {
+                            self.json_edge(edge)?;
+                        },
+                        _ => {},
+                    }
+                }
+            },
+            Json::Object(fields) => {
+                for (node, neighbours) in fields {
+                    self.node(node);
+
+                    match &neighbours.kind {
+                        Json::Array(neighbours) => for neighbour in neighbours {
+                            self.edge(node, &neighbour.node_label()?, 1.0, neighbour.line)?;
+                        },
+                        Json::Object(neighbours) => for (neighbour, weight) in neighbours {
+                            let line = weight.line;
+                            // networkx's adjacency data has the weight in an object of attributes
+                            let weight = match &weight.kind {
+                                Json::Object(_) => weight.field("weight").map(|weight| weight.weight()).transpose()?.unwrap_or(1.0),
+                                _ => weight.weight()?,
+                            };
+                            self.edge(node, neighbour, weight, line)?;
+                        },
+                        _ => return Err(neighbours.error("expected a list or object of neighbours")),
+                    }
+                }
+            },
+            _ => return Err(document.error("expected a list of edges or an object of nodes")),
+        }
+
+        Ok(())
+    }
+
+    /// `["a", "b"]`, `["a", "b", weight]` or `{"source": "a", "target": "b", "weight": weight}`
+    fn json_edge(&mut self, edge: &JsonValue) -> Result<(), GraphError> {
+        let (a, b, weight) = match &edge.kind {
+            Json::Array(ends) => match ends.as_slice() {
+                [a, b] => (a, b, None),
+                [a, b, weight] => (a, b, Some(weight)),
+                _ => return Err(edge.error("expected an edge [a, b] or [a, b, weight]")),
+            },
+            Json::Object(_) => match (edge.field("source"), edge.field("target")) {
+                (Some(a), Some(b)) => (a, b, edge.field("weight")),
+                _ => return Err(edge.error("expected an edge with a \"source\" and \"target\"")),
+            },
+            _ => return Err(edge.error("expected an edge")),
+        };
+
+        let weight = weight.map(|weight| weight.weight()).transpose()?.unwrap_or(1.0);
+        self.edge(&a.node_label()?, &b.node_label()?, weight, edge.line)
+    }
+}
+
+#[derive(Debug)]
+enum Json {
+    Null,
+    Bool,
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    // fields in the order they're written
+    Object(Vec<(String, JsonValue)>),
+}
+
+/// JSON value with the line it starts on, for errors
+#[derive(Debug)]
+struct JsonValue {
+    kind: Json,
+    line: usize,
+}
+
+impl JsonValue {
+    fn error(&self, message: &str) -> GraphError {
+        GraphError::Parse { line: self.line, message: message.to_string() }
+    }
+
+    fn field(&self, key: &str) -> Option<&JsonValue> {
+        match &self.kind {
+            Json::Object(fields) => fields.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Label of a node given as a string or number
+    fn node_label(&self) -> Result<String, GraphError> {
+        match &self.kind {
+            Json::String(label) => Ok(label.clone()),
+            Json::Number(number) if number.fract() == 0.0 => Ok((*number as i64).to_string()),
+            Json::Number(number) => Ok(number.to_string()),
+            _ => Err(self.error("expected a node, a string or number")),
+        }
+    }
+
+    fn weight(&self) -> Result<f32, GraphError> {
+        match &self.kind {
+            Json::Number(weight) => Ok(*weight as f32),
+            _ => Err(self.error("expected a number for the weight")),
+        }
+    }
+}
+
+/// Just enough of a JSON parser for graphs
+struct JsonParser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl JsonParser<'_> {
+    /// A single value, with nothing but whitespace after it
+    fn document(mut self) -> Result<JsonValue, GraphError> {
+        let value = self.value()?;
+        self.skip_whitespace();
+        if self.position < self.text.len() {
+            return Err(self.error("unexpected text after the end of the JSON"));
+        }
+        Ok(value)
+    }
+
+    fn line(&self) -> usize {
+        1 + self.text[..self.position].iter().filter(|byte| **byte == b'\n').count()
+    }
+
+    fn error(&self, message: &str) -> GraphError {
+        GraphError::Parse { line: self.line(), message: message.to_string() }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.text.get(self.position).is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), GraphError> {
+        self.skip_whitespace();
+        if self.text.get(self.position) != Some(&byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<JsonValue, GraphError> {
+        self.skip_whitespace();
+        let line = self.line();
+
+        let kind = match self.text.get(self.position) {
+            Some(b'{') => {
+                self.position += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.text.get(self.position) == Some(&b'}') {
+                    self.position += 1;
+                } else {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        fields.push((key, self.value()?));
+
+                        self.skip_whitespace();
+                        match self.text.get(self.position) {
+                            Some(b',') => self.position += 1,
+                            Some(b'}') => {
+                                self.position += 1;
+                                break;
+                            },
+                            _ => return Err(self.error("expected ',' or '}'")),
+                        }
+                    }
+                }
+                Json::Object(fields)
+            },
+            Some(b'[') => {
+                self.position += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.text.get(self.position) == Some(&b']') {
+                    self.position += 1;
+                } else {
+                    loop {
+                        items.push(self.value()?);
+
+                        self.skip_whitespace();
+                        match self.text.get(self.position) {
+                            Some(b',') => self.position += 1,
+                            Some(b']') => {
+                                self.position += 1;
+                                break;
+                            },
+                            _ => return Err(self.error("expected ',' or ']'")),
+                        }
+                    }
+                }
+                Json::Array(items)
+            },
+            Some(b'"') => Json::String(self.string()?),
+            Some(b't') if self.text[self.position..].starts_with(b"true") => {
+                self.position += 4;
+                Json::Bool
+            },
+            Some(b'f') if self.text[self.position..].starts_with(b"false") => {
+                self.position += 5;
+                Json::Bool
+            },
+            Some(b'n') if self.text[self.position..].starts_with(b"null") => {
+                self.position += 4;
+                Json::Null
+            },
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.position;
+                while self.text.get(self.position).is_some_and(|byte| matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+                    self.position += 1;
+                }
+                let number = std::str::from_utf8(&self.text[start..self.position]).unwrap_or_default();
+                Json::Number(number.parse().map_err(|_| self.error(&format!("invalid number \"{}\"", number)))?)
+            },
+            Some(_) => return Err(self.error("expected a JSON value")),
+            None => return Err(self.error("unexpected end of the JSON")),
+        };
+
+        Ok(JsonValue { kind, line })
+    }
+
+    fn string(&mut self) -> Result<String, GraphError> {
+        if self.text.get(self.position) != Some(&b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.position += 1;
+
+        let mut bytes = Vec::new();
+        loop {
+            match self.text.get(self.position) {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.position += 1;
+                    match self.text.get(self.position) {
+                        Some(b'n') => bytes.push(b'\n'),
+                        Some(b't') => bytes.push(b'\t'),
+                        Some(b'r') => bytes.push(b'\r'),
+                        Some(b'b') => bytes.push(0x08),
+                        Some(b'f') => bytes.push(0x0c),
+                        Some(b'u') => {
+                            let code = self.text.get(self.position + 1..self.position + 5)
+                                .and_then(|hex| u32::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())
+                                .ok_or_else(|| self.error("invalid \\u escape"))?;
+                            // surrogate pairs aren't joined, they're rare in node names
+                            let character = char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER);
+                            bytes.extend_from_slice(character.encode_utf8(&mut [0; 4]).as_bytes());
+                            self.position += 4;
+                        },
+                        Some(byte) => bytes.push(*byte),
+                        None => return Err(self.error("unterminated string")),
+                    }
+                },
+                Some(byte) => bytes.push(*byte),
+                None => return Err(self.error("unterminated string")),
+            }
+            self.position += 1;
+        }
+        self.position += 1;
+
+        String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8 in a string"))
+    }
+}
+
+/// Removes //, # and /* */ comments, keeping the line breaks
+fn strip_dot_comments(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(index) = rest.find("/*") {
+        stripped.push_str(&rest[..index]);
+        let comment_end = rest[index..].find("*/").map(|end| index + end + 2).unwrap_or(rest.len());
+        stripped.extend(rest[index..comment_end].chars().filter(|c| *c == '\n'));
+        rest = &rest[comment_end..];
+    }
+    stripped.push_str(rest);
+
+    stripped.lines()
+        .map(|line| {
+            let line = line.split("//").next().unwrap_or_default();
+            if line.trim_start().starts_with('#') { "" } else { line }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Node name without quotes or a port (`a:n`)
+fn dot_node_id(node: &str) -> String {
+    let node = node.trim();
+
+    if let Some(quoted) = node.strip_prefix('"') {
+        quoted.split('"').next().unwrap_or_default().to_string()
+    } else {
+        node.split(':').next().unwrap_or_default().trim().to_string()
+    }
+}
+
+/// Value of an attribute in a `[key=value, ...]` list
+fn dot_attribute<'a>(attributes: &'a str, key: &str) -> Option<&'a str> {
+    attributes.trim_matches(|c: char| c == '[' || c == ']' || c.is_whitespace())
+        .split([',', ';'])
+        .filter_map(|attribute| attribute.split_once('='))
+        .find(|(name, _)| name.trim() == key)
+        .map(|(_, value)| value.trim().trim_matches(|c: char| c == '"' || c == ']' || c.is_whitespace()))
+}
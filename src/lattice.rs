@@ -1,38 +1,76 @@
+use std::{fmt::Display, sync::Arc};
+
 use rand::Rng;
 
-use crate::spin::Spin;
+use crate::{spin::Spin, multispin::Multispin, rng::{LatticeRng, RngAlgorithm}, geometry::{Geometry, Layout}, graph::Graph, couplings::Couplings, model::{self, Model}, fft, union_find::UnionFind};
+
+/// Sweeps it takes the flip activity of a site to halve
+const ACTIVITY_HALF_LIFE: f32 = 10.0;
 
 /// Boltzman probability for given energy/delta energy and temperature.
 fn boltzman(energy: f32, temperature: f32) -> f32 {
     f32::exp(-energy / temperature)
 }
 
+/// Metropolis acceptance probability, min(1, exp(-dE/T)), well defined at T = 0.
+fn acceptance(d_energy: f32, temperature: f32) -> f32 {
+    if d_energy <= 0.0 {
+        1.0
+    } else if temperature <= 0.0 {
+        0.0
+    } else {
+        boltzman(d_energy, temperature)
+    }
+}
+
+/// Acceptance probabilities for every energy change a single flip can cause with ±1 couplings.
+/// Indexed by the spin before the flip and the sum of its neighbours' spins weighted by coupling,
+/// which lies in -max_neighbours..=max_neighbours.
 #[derive(Debug, Clone)]
-struct InterationsStorage {
-    up: f32,
-    left: f32,
-    // down/right interations stored in up/left interactions of neighbours
-    // down: f32,
-    // right: f32,
+struct BoltzmanTable {
+    temperature: f32,
+    magnetic_field: f32,
+    max_neighbours: usize,
+    factors: [Vec<f32>; 2],
 }
 
-impl InterationsStorage {
-    const FERROMAGNETIC: InterationsStorage = InterationsStorage {
-        up: 1.0,
-        left: 1.0,
-    };
-    
-    const ANTIFERROMAGNETIC: InterationsStorage = InterationsStorage {
-        up: -1.0,
-        left: -1.0,
-    };
-    
-    pub fn spin_glass(antiferromagnetic_probability: f64) -> InterationsStorage {
-        InterationsStorage {
-            up: (rand::thread_rng().gen_bool(1.0 - antiferromagnetic_probability) as i32 * 2 - 1) as f32,
-            left: (rand::thread_rng().gen_bool(1.0 - antiferromagnetic_probability) as i32 * 2 - 1) as f32,
+impl BoltzmanTable {
+    fn new(temperature: f32, magnetic_field: f32, max_neighbours: usize) -> BoltzmanTable {
+        let factors = [Spin::Up, Spin::Down].map(|spin| {
+            let spin = Into::<i32>::into(spin) as f32;
+
+            (0..=2 * max_neighbours)
+                .map(|j| {
+                    let neighbour_sum = j as f32 - max_neighbours as f32;
+                    acceptance(2.0 * spin * (neighbour_sum + magnetic_field), temperature)
+                })
+                .collect()
+        });
+
+        BoltzmanTable {
+            temperature,
+            magnetic_field,
+            max_neighbours,
+            factors,
+        }
+    }
+
+    /// Rebuild table if the temperature or magnetic field has changed
+    fn update(&mut self, temperature: f32, magnetic_field: f32) {
+        if self.temperature != temperature || self.magnetic_field != magnetic_field {
+            *self = BoltzmanTable::new(temperature, magnetic_field, self.max_neighbours);
         }
     }
+
+    fn get(&self, spin: Spin, neighbour_sum: f32) -> f32 {
+        let i = match spin {
+            Spin::Up => 0,
+            Spin::Down => 1,
+        };
+        let j = neighbour_sum.round() as isize + self.max_neighbours as isize;
+
+        self.factors[i][j as usize]
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -42,266 +80,2506 @@ pub enum LatticeInitialState {
     AllDown,
 }
 
-#[derive(Debug, Clone)]
-struct Interactions {
-    up: f32,
-    left: f32,
-    down: f32,
-    right: f32,
+/// What happens to the bonds which wrap around the edges of the lattice along one axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Boundary {
+    /// Edges wrap around onto the opposite side
+    Periodic,
+    /// Edges wrap around with the sign of the coupling across the seam flipped, which forces an
+    /// odd number of interfaces in a ferromagnet
+    AntiPeriodic,
+    /// Free edges, sites on them have fewer neighbours
+    Open,
+    /// Edges are bonded to a layer of spins pinned up
+    FixedUp,
+    /// Edges are bonded to a layer of spins pinned down
+    FixedDown,
+    /// Pinned spins, up beyond the low end of the axis (the top, or the left) and down beyond the
+    /// high end, forcing an interface across the middle
+    FixedMixed,
+}
+
+impl Boundary {
+    pub const ALL: [Boundary; 6] = [
+        Boundary::Periodic,
+        Boundary::AntiPeriodic,
+        Boundary::Open,
+        Boundary::FixedUp,
+        Boundary::FixedDown,
+        Boundary::FixedMixed,
+    ];
+
+    /// Pinned spin beyond the low or high end of the axis, for fixed boundaries
+    fn pinned_spin(&self, low_end: bool) -> Option<Spin> {
+        match self {
+            Boundary::Periodic | Boundary::AntiPeriodic | Boundary::Open => None,
+            Boundary::FixedUp => Some(Spin::Up),
+            Boundary::FixedDown => Some(Spin::Down),
+            Boundary::FixedMixed => Some(if low_end { Spin::Up } else { Spin::Down }),
+        }
+    }
+}
+
+impl Display for Boundary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Boundary::Periodic => f.write_str("Periodic"),
+            Boundary::AntiPeriodic => f.write_str("Anti-periodic"),
+            Boundary::Open => f.write_str("Open"),
+            Boundary::FixedUp => f.write_str("Fixed Up"),
+            Boundary::FixedDown => f.write_str("Fixed Down"),
+            Boundary::FixedMixed => f.write_str("Fixed Mixed"),
+        }
+    }
+}
+
+/// Order in which sites are visited during an epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SweepOrder {
+    /// N random site picks (with replacement)
+    Random,
+    /// Row by row, left to right (typewriter)
+    Sequential,
+    /// One sublattice after the other (even then odd sites on a square lattice), no two
+    /// neighbours are in the same sublattice
+    Checkerboard,
+}
+
+/// What the colour of each site in the image shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SiteColouring {
+    /// The state of the site
+    State,
+    /// How often the site has flipped lately, from black through red and yellow to white for a
+    /// site flipping every sweep. Only tracked while asked for, see Lattice::set_track_activity.
+    Activity,
+    /// Energy of each site with its neighbours and the field, from black at -E to white at E,
+    /// where E is the largest energy of any site either way. Frustrated sites stand out.
+    Energy,
+    /// A colour of its own for each domain of sites in the same state, see cluster_sizes()
+    Clusters,
+}
+
+impl SiteColouring {
+    pub const ALL: [SiteColouring; 4] = [SiteColouring::State, SiteColouring::Activity, SiteColouring::Energy, SiteColouring::Clusters];
+}
+
+impl Display for SiteColouring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SiteColouring::State => f.write_str("State"),
+            SiteColouring::Activity => f.write_str("Flip Activity"),
+            SiteColouring::Energy => f.write_str("Local Energy"),
+            SiteColouring::Clusters => f.write_str("Domains"),
+        }
+    }
+}
+
+/// Colours of up and down Ising spins in the lattice image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub up: [u8; 3],
+    pub down: [u8; 3],
+}
+
+impl Palette {
+    /// Blue for up and red for down
+    pub const CLASSIC: Palette = Palette { up: [0, 0, 255], down: [255, 0, 0] };
+    /// Blue and orange of Okabe and Ito's palette, told apart with any colour vision deficiency
+    pub const COLOURBLIND: Palette = Palette { up: [0, 114, 178], down: [230, 159, 0] };
+    /// White for up and black for down, for printing
+    pub const GREYSCALE: Palette = Palette { up: [255, 255, 255], down: [0, 0, 0] };
+
+    pub const PRESETS: [(&'static str, Palette); 3] = [
+        ("Classic", Palette::CLASSIC),
+        ("Colourblind", Palette::COLOURBLIND),
+        ("Greyscale", Palette::GREYSCALE),
+    ];
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LatticeType {
     Ferromagnetic,
     Antiferromagnetic,
+    /// ±1 couplings, antiferromagnetic with probability p_antiferro
     SpinGlass { p_antiferro: f64 },
+    /// Normally distributed couplings with mean 0 (Edwards-Anderson)
+    GaussianSpinGlass { sigma: f64 },
 }
 
-#[derive(Debug)]
-pub struct Lattice {
-    state: Vec<Spin>,
-    interations: Vec<InterationsStorage>,
-    size: usize,
-    pub temperature: f32,
-    // magnetic field B, z component
-    pub magnetic_field: f32,
+impl Display for LatticeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LatticeType::Ferromagnetic => f.write_str("Ferromagnetic"),
+            LatticeType::Antiferromagnetic => f.write_str("Antiferromagnetic"),
+            LatticeType::SpinGlass { p_antiferro } => write!(f, "±J Spin Glass (p = {:.2})", p_antiferro),
+            LatticeType::GaussianSpinGlass { sigma } => write!(f, "Gaussian Spin Glass (σ = {:.2})", sigma),
+        }
+    }
 }
 
-impl Lattice {
-    /// Lattice with random initial state
-    pub fn new_random(size: usize, temperature: f32, magnetic_field: f32, lattice_type: LatticeType) -> Lattice {
-        let mut spins = Vec::with_capacity(size * size);
+impl LatticeType {
+    /// Average coupling of a bond
+    fn mean_coupling(&self) -> f32 {
+        match self {
+            LatticeType::Ferromagnetic => 1.0,
+            LatticeType::Antiferromagnetic => -1.0,
+            LatticeType::SpinGlass { p_antiferro } => 1.0 - 2.0 * *p_antiferro as f32,
+            LatticeType::GaussianSpinGlass { .. } => 0.0,
+        }
+    }
 
-        for _ in 0..size*size {
-            let spin = if rand::thread_rng().gen::<bool>() {
-                Spin::Up
-            } else {
-                Spin::Down
-            };
+    fn coupling(&self, rng: &mut impl Rng) -> f32 {
+        match self {
+            LatticeType::Ferromagnetic => 1.0,
+            LatticeType::Antiferromagnetic => -1.0,
+            LatticeType::SpinGlass { p_antiferro } => (rng.gen_bool(1.0 - p_antiferro) as i32 * 2 - 1) as f32,
+            LatticeType::GaussianSpinGlass { sigma } => *sigma as f32 * standard_normal(rng),
+        }
+    }
+}
+
+/// Pixels an image of a lattice is drawn into, in the order of the rows
+pub trait ImageBuffer {
+    /// Empty the buffer, making room for `pixels` pixels
+    fn reset(&mut self, pixels: usize);
+    fn push(&mut self, colour: [u8; 3]);
+    fn get(&self, index: usize) -> [u8; 3];
+    fn set(&mut self, index: usize, colour: [u8; 3]);
+}
+
+/// Three bytes per pixel, as saved to files
+impl ImageBuffer for Vec<u8> {
+    fn reset(&mut self, pixels: usize) {
+        self.clear();
+        self.reserve(3 * pixels);
+    }
+
+    fn push(&mut self, colour: [u8; 3]) {
+        self.extend_from_slice(&colour);
+    }
+
+    fn get(&self, index: usize) -> [u8; 3] {
+        [self[3 * index], self[3 * index + 1], self[3 * index + 2]]
+    }
+
+    fn set(&mut self, index: usize, colour: [u8; 3]) {
+        self[3 * index..3 * index + 3].copy_from_slice(&colour);
+    }
+}
+
+/// Everything needed to generate a lattice
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatticeSettings {
+    pub model: Model,
+    pub geometry: Geometry,
+    /// Side lengths, in sites or unit cells depending on the geometry. Only as many as the
+    /// geometry has dimensions are used.
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    /// Exchange constant J, multiplying every coupling below. Temperatures are in units of J/k_B
+    /// when it's 1, 0 leaves a paramagnet.
+    pub exchange: f32,
+    /// Strength of the nearest-neighbour couplings along each of the geometry's bond directions,
+    /// multiplying the sign given by the lattice type
+    pub coupling_strengths: [f32; 3],
+    /// Coupling of next-nearest neighbours, the same for every lattice type
+    pub next_nearest_coupling: f32,
+    /// ANNNI competition κ, sites two apart along x are coupled by -κ Jx whatever the lattice
+    /// type. Only for the chain, square and cubic lattices.
+    pub axial_kappa: f32,
+    /// Four-spin coupling K, adding -K s1 s2 s3 s4 for every elementary square. Only for the
+    /// Ising model on square and cubic lattices.
+    pub plaquette_coupling: f32,
+    /// Interactions beyond nearest neighbours, added to the other couplings. Only for the chain,
+    /// square and cubic lattices.
+    pub long_range: Option<LongRange>,
+    /// Antiferromagnetic dipolar interactions between spins perpendicular to a film, also only
+    /// for the chain, square and cubic lattices
+    pub dipolar: Option<Dipolar>,
+    /// Boundary along x, y and z, again only as many as the geometry has dimensions are used
+    pub boundaries: [Boundary; 3],
+    pub lattice_type: LatticeType,
+    /// Quenched random field, drawn once for each site
+    pub random_field: Option<RandomField>,
+    /// Probability of each site being left empty
+    pub p_vacancy: f64,
+    /// Probability of each bond being removed, whatever its coupling
+    pub p_bond_dilution: f64,
+    pub initial_state: LatticeInitialState,
+    pub seed: u64,
+    pub rng_algorithm: RngAlgorithm,
+    /// Network used by the graph geometry
+    pub graph: Option<Arc<Graph>>,
+    /// Slices of imaginary time of the transverse-field Ising model, simulated as a classical
+    /// lattice with one copy of the geometry per slice (Suzuki-Trotter). 1 for a classical model,
+    /// only the Ising model on a chain, square, kagome or cubic lattice can have more.
+    pub trotter_slices: usize,
+}
 
-            spins.push(spin);
+impl LatticeSettings {
+    /// Trotter slices actually used, see `trotter_slices`
+    pub fn slices(&self) -> usize {
+        match (self.model, self.geometry) {
+            (Model::Ising, Geometry::Chain | Geometry::Square | Geometry::Kagome | Geometry::Cubic) => self.trotter_slices.max(1),
+            _ => 1,
         }
+    }
 
-        let interations = match lattice_type {
-            LatticeType::Ferromagnetic => vec![InterationsStorage::FERROMAGNETIC;size*size],
-            LatticeType::Antiferromagnetic => vec![InterationsStorage::ANTIFERROMAGNETIC;size*size],
-            LatticeType::SpinGlass { p_antiferro } => {
-                let mut ints = Vec::with_capacity(size*size);
-                for _ in 0..size*size {
-                    ints.push(InterationsStorage::spin_glass(p_antiferro));
-                }
-                ints
+    fn layout(&self) -> Layout {
+        match (self.geometry, &self.graph) {
+            (Geometry::Graph, Some(graph)) => graph.layout(),
+            _ => self.geometry.layout(self.width, self.height, self.depth),
+        }
+    }
+}
+
+/// Distribution of the quenched random field on each site, on top of the uniform field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RandomField {
+    /// +strength or -strength with equal probability
+    Binary { strength: f32 },
+    /// Normally distributed with mean 0
+    Gaussian { width: f32 },
+}
+
+impl RandomField {
+    fn sample(&self, rng: &mut impl Rng) -> f32 {
+        match self {
+            RandomField::Binary { strength } => if rng.gen::<bool>() { *strength } else { -strength },
+            RandomField::Gaussian { width } => width * standard_normal(rng),
+        }
+    }
+}
+
+/// Normally distributed sample with mean 0 and standard deviation 1 (Box-Muller)
+fn standard_normal(rng: &mut impl Rng) -> f32 {
+    let u: f64 = 1.0 - rng.gen::<f64>();
+    let v: f64 = rng.gen();
+
+    ((-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()) as f32
+}
+
+/// Shape of the external field over the lattice, as a multiple of the magnetic field B at each
+/// site. Positions are taken from the lattice image, so 3D lattices get the same profile in every
+/// layer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldProfile {
+    Uniform,
+    /// Rising linearly from -B to B across the lattice, along a direction `angle` radians
+    /// anticlockwise from the x axis
+    Gradient { angle: f32 },
+    /// B cos(2π r / wavelength), with r measured in sites along the `angle` direction
+    Sinusoidal { wavelength: f32, angle: f32 },
+    /// B within a circle around the middle of the lattice and no field outside. The radius is a
+    /// fraction of half the shorter side.
+    Circle { radius: f32 },
+    /// Grayscale image stretched over the lattice, black is -B and white is B
+    Mask(Arc<FieldMask>),
+}
+
+impl FieldProfile {
+    /// Multiple of B at pixel (x, y) of an image of the given size
+    fn weight(&self, x: usize, y: usize, width: usize, height: usize) -> f32 {
+        // centre of the pixel, in sites from the middle of the image
+        let (x, y) = (x as f32 + 0.5 - 0.5 * width as f32, y as f32 + 0.5 - 0.5 * height as f32);
+
+        match self {
+            FieldProfile::Uniform => 1.0,
+            FieldProfile::Gradient { angle } => {
+                let (sin, cos) = angle.sin_cos();
+                // furthest any pixel reaches along the direction
+                let reach = 0.5 * (width as f32 * cos.abs() + height as f32 * sin.abs());
+                // y points down the image
+                ((x * cos - y * sin) / reach).clamp(-1.0, 1.0)
             },
-        };
+            FieldProfile::Sinusoidal { wavelength, angle } => {
+                let (sin, cos) = angle.sin_cos();
+                (std::f32::consts::TAU * (x * cos - y * sin) / wavelength).cos()
+            },
+            FieldProfile::Circle { radius } => {
+                let radius = radius * 0.5 * width.min(height) as f32;
+                if x * x + y * y <= radius * radius { 1.0 } else { 0.0 }
+            },
+            FieldProfile::Mask(mask) => {
+                let u = (x / width as f32 + 0.5) * mask.width as f32;
+                let v = (y / height as f32 + 0.5) * mask.height as f32;
+                mask.values[(u as usize).min(mask.width - 1) + (v as usize).min(mask.height - 1) * mask.width]
+            },
+        }
+    }
+}
 
-        Lattice {
-            state: spins,
-            interations,
-            size,
-            temperature,
-            magnetic_field,
+impl Display for FieldProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldProfile::Uniform => f.write_str("Uniform"),
+            FieldProfile::Gradient { .. } => f.write_str("Gradient"),
+            FieldProfile::Sinusoidal { .. } => f.write_str("Sinusoidal"),
+            FieldProfile::Circle { .. } => f.write_str("Circle"),
+            FieldProfile::Mask(_) => f.write_str("Mask"),
         }
     }
+}
+
+/// Field profile read from an image, in -1..=1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldMask {
+    width: usize,
+    height: usize,
+    values: Vec<f32>,
+}
+
+impl FieldMask {
+    pub fn load(path: &std::path::Path) -> Result<FieldMask, image::ImageError> {
+        let image = image::open(path)?.into_luma8();
+
+        Ok(FieldMask {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            values: image.pixels().map(|pixel| pixel.0[0] as f32 / 127.5 - 1.0).collect(),
+        })
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+}
+
+/// Power-law interactions J(r) = J / r^exponent between every pair of sites at most `cutoff`
+/// lattice spacings apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LongRange {
+    pub exponent: f32,
+    pub cutoff: f32,
+}
 
-    /// Lattice with uniform initial state
-    pub fn new_uniform(size: usize, temperature: f32, magnetic_field: f32, spin: Spin, lattice_type: LatticeType) -> Lattice {
-        let mut spins = Vec::with_capacity(size * size);
+/// Dipolar interactions J(r) = -strength / r³ between every pair of sites at most `cutoff`
+/// lattice spacings apart, summed directly rather than with an Ewald sum. Competing with the
+/// exchange they break a film up into stripes and bubbles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dipolar {
+    pub strength: f32,
+    pub cutoff: f32,
+}
+
+/// Where the coupling of a bond comes from
+#[derive(Debug, Clone, Copy)]
+enum BondKind {
+    Nearest,
+    NextNearest,
+    /// Two sites apart along x
+    Axial,
+    /// Power-law bond of the given length
+    LongRange(f32),
+    /// Dipolar bond of the given length, beyond nearest neighbours
+    Dipolar(f32),
+    /// Graph edge with the given weight
+    Weighted(f32),
+}
+
+/// Bond between sites drawn next to each other, as shown by the coupling editor.
+#[derive(Debug, Clone, Copy)]
+pub struct DrawnBond {
+    pub a: usize,
+    pub b: usize,
+    /// Pixels of the two sites
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub coupling: f32,
+    /// Size of the coupling a bond in this direction is generated with, what painting it
+    /// ferromagnetic or antiferromagnetic sets it to
+    pub strength: f32,
+}
+
+/// What the hover tooltip shows about a site.
+#[derive(Debug, Clone)]
+pub struct SiteInfo {
+    /// Pixel and layer of the site
+    pub position: (usize, usize, usize),
+    /// The site's state, as an arrow for Ising spins
+    pub state: String,
+    /// Effective field on an Ising spin from its neighbours and the applied field, the applied
+    /// field alone for other models
+    pub field: f32,
+    /// Energy of the site's bonds and field, see SiteColouring::Energy
+    pub energy: f32,
+    /// Neighbouring sites and the couplings to them
+    pub couplings: Vec<(usize, f32)>,
+}
+
+/// What a pixel of the lattice image shows
+#[derive(Debug, Clone, Copy)]
+enum Pixel {
+    /// Not part of the geometry, like the hole in every 2 × 2 block of a kagome lattice
+    Empty,
+    /// Site left out by dilution
+    Vacancy,
+    Site(usize),
+}
+
+/// Sites and couplings, fixed once the lattice is generated.
+#[derive(Debug, Clone)]
+struct Structure {
+    image_width: usize,
+    image_height: usize,
+    layers: usize,
+    // what is drawn at each pixel, row by row and layer by layer
+    pixels: Vec<Pixel>,
+    // pixel and layer of each site
+    positions: Vec<(usize, usize, usize)>,
+    // neighbours of site i and their couplings are neighbours[neighbour_offsets[i]..neighbour_offsets[i + 1]]
+    neighbour_offsets: Vec<usize>,
+    neighbours: Vec<(usize, f32)>,
+    // the other three sites of each elementary square site i is a corner of, with the square's
+    // coupling, are plaquettes[plaquette_offsets[i]..plaquette_offsets[i + 1]]
+    plaquette_offsets: Vec<usize>,
+    plaquettes: Vec<([usize; 3], f32)>,
+    // field on each site from the pinned boundary spins bonded to it (weighted by coupling) and
+    // the quenched random field, and the number of boundary bonds
+    local_fields: Vec<f32>,
+    boundary_bonds: Vec<usize>,
+    // coupling J/N of every pair of sites, 0 unless the geometry is mean field
+    mean_field_coupling: f32,
+    // whether every coupling is -1, 0 or 1, so the energy changes of a flip can be tabulated
+    unit_couplings: bool,
+    // whether couplings have been changed by hand since the lattice was generated
+    edited: bool,
+    // groups of sites with no bonds within a group
+    sublattices: Vec<Vec<usize>>,
+    // Trotter slices, and the sites in each, which are numbered slice by slice
+    slices: usize,
+    slice_sites: usize,
+}
 
-        for _ in 0..size*size {
-            spins.push(spin);
+impl Structure {
+    /// `occupied` says which of the layout's sites are there, the others are vacancies
+    fn new(layout: Layout, occupied: &[bool], settings: &LatticeSettings, rng: &mut impl Rng) -> Structure {
+        // sites are numbered without the vacancies
+        let mut site_of = vec![None; layout.positions.len()];
+        let mut sites = 0;
+        for (position, occupied) in occupied.iter().enumerate() {
+            if *occupied {
+                site_of[position] = Some(sites);
+                sites += 1;
+            }
         }
 
-        let interations = match lattice_type {
-            LatticeType::Ferromagnetic => vec![InterationsStorage::FERROMAGNETIC;size*size],
-            LatticeType::Antiferromagnetic => vec![InterationsStorage::ANTIFERROMAGNETIC;size*size],
-            LatticeType::SpinGlass { p_antiferro } => {
-                let mut ints = Vec::with_capacity(size*size);
-                for _ in 0..size*size {
-                    ints.push(InterationsStorage::spin_glass(p_antiferro));
-                }
-                ints
-            },
+        let layer_pixels = layout.image_width * layout.image_height;
+        let mut pixels = vec![Pixel::Empty; layer_pixels * layout.layers];
+        for (position, (x, y, layer)) in layout.positions.iter().enumerate() {
+            pixels[x + y * layout.image_width + layer * layer_pixels] = match site_of[position] {
+                Some(site) => Pixel::Site(site),
+                None => Pixel::Vacancy,
+            };
+        }
+
+        let mut bonds: Vec<(usize, usize, f32)> = Vec::with_capacity(layout.bonds.len());
+        let mut local_fields = vec![0.0; sites];
+        let mut boundary_bonds = vec![0; sites];
+
+        let long_range_bonds = match settings.long_range {
+            Some(long_range) => settings.geometry.long_range_bonds(settings.width, settings.height, settings.depth, long_range.cutoff),
+            None => Vec::new(),
         };
 
-        Lattice {
-            state: spins,
-            interations,
-            size,
-            temperature,
-            magnetic_field,
+        let nearest = layout.bonds.iter().map(|bond| (bond, BondKind::Nearest));
+        let next_nearest = layout.next_nearest_bonds.iter()
+            .filter(|_| settings.next_nearest_coupling != 0.0)
+            .map(|bond| (bond, BondKind::NextNearest));
+        let long_range = long_range_bonds.iter().map(|(bond, distance)| (bond, BondKind::LongRange(*distance)));
+
+        let dipolar_bonds = match settings.dipolar {
+            Some(dipolar) => settings.geometry.long_range_bonds(settings.width, settings.height, settings.depth, dipolar.cutoff),
+            None => Vec::new(),
+        };
+        let dipolar = dipolar_bonds.iter().map(|(bond, distance)| (bond, BondKind::Dipolar(*distance)));
+        // nearest neighbours are one spacing apart, their share is added to the exchange bond
+        let dipolar_strength = match (settings.dipolar, settings.geometry) {
+            (Some(dipolar), Geometry::Chain | Geometry::Square | Geometry::Cubic) => dipolar.strength,
+            _ => 0.0,
+        };
+
+        let axial_bonds = if settings.axial_kappa != 0.0 {
+            settings.geometry.axial_bonds(settings.width, settings.height, settings.depth)
+        } else {
+            Vec::new()
+        };
+        let axial = axial_bonds.iter().map(|bond| (bond, BondKind::Axial));
+
+        let graph_bonds = match (settings.geometry, &settings.graph) {
+            (Geometry::Graph, Some(graph)) => graph.bonds(),
+            _ => Vec::new(),
+        };
+        let graph = graph_bonds.iter().map(|(bond, weight)| (bond, BondKind::Weighted(*weight)));
+
+        for (bond, kind) in nearest.chain(next_nearest).chain(axial).chain(long_range).chain(dipolar).chain(graph) {
+            if settings.p_bond_dilution > 0.0 && rng.gen_bool(settings.p_bond_dilution.clamp(0.0, 1.0)) {
+                continue;
+            }
+
+            let mut coupling = || settings.exchange * match kind {
+                BondKind::Nearest => settings.coupling_strengths[bond.direction] * settings.lattice_type.coupling(rng) - dipolar_strength,
+                BondKind::NextNearest => settings.next_nearest_coupling,
+                BondKind::Axial => -settings.axial_kappa * settings.coupling_strengths[0],
+                BondKind::LongRange(distance) => {
+                    let exponent = settings.long_range.map(|long_range| long_range.exponent).unwrap_or_default();
+                    settings.lattice_type.coupling(rng) / distance.powf(exponent)
+                },
+                BondKind::Dipolar(distance) => -dipolar_strength / distance.powi(3),
+                BondKind::Weighted(weight) => settings.coupling_strengths[0] * weight * settings.lattice_type.coupling(rng),
+            };
+
+            // boundaries of the axes the bond wraps around, with which way it wraps
+            let crossed: Vec<(Boundary, i8)> = (0..3)
+                .filter(|axis| bond.wraps[*axis] != 0)
+                .map(|axis| (settings.boundaries[axis], bond.wraps[axis]))
+                .collect();
+
+            // a bond wrapping around several axes (a diagonal across a corner) is cut if any of
+            // them is open, pinned if any is fixed, and flipped once per anti-periodic axis
+            if crossed.iter().any(|(boundary, _)| *boundary == Boundary::Open) {
+                continue;
+            }
+
+            if let Some((boundary, direction)) = crossed.iter().find(|(boundary, _)| boundary.pinned_spin(true).is_some()) {
+                // cut the bond, bonding both ends to the pinned spins beyond the edge instead
+                for (position, low_end) in [(bond.a, *direction < 0), (bond.b, *direction > 0)] {
+                    let Some(site) = site_of[position] else {
+                        continue;
+                    };
+                    let pinned = boundary.pinned_spin(low_end).expect("boundary is fixed");
+                    local_fields[site] += coupling() * Into::<i32>::into(pinned) as f32;
+                    boundary_bonds[site] += 1;
+                }
+                continue;
+            }
+
+            // bonds to vacancies aren't there
+            let (Some(a), Some(b)) = (site_of[bond.a], site_of[bond.b]) else {
+                continue;
+            };
+
+            let flips = crossed.iter().filter(|(boundary, _)| *boundary == Boundary::AntiPeriodic).count();
+            let sign = if flips % 2 == 0 { 1.0 } else { -1.0 };
+            bonds.push((a, b, sign * coupling()));
+        }
+
+        let mut plaquettes = Vec::new();
+        if settings.plaquette_coupling != 0.0 && settings.model == Model::Ising {
+            for plaquette in settings.geometry.plaquettes(settings.width, settings.height, settings.depth) {
+                // squares across an open or fixed boundary are cut, an anti-periodic boundary
+                // flips two of a square's spins which leaves their product alone
+                let cut = (0..3).any(|axis| plaquette.wraps[axis]
+                    && !matches!(settings.boundaries[axis], Boundary::Periodic | Boundary::AntiPeriodic));
+                if cut {
+                    continue;
+                }
+
+                // as are squares with a vacancy
+                if let [Some(a), Some(b), Some(c), Some(d)] = plaquette.sites.map(|position| site_of[position]) {
+                    plaquettes.push(([a, b, c, d], settings.exchange * settings.plaquette_coupling));
+                }
+            }
+        }
+
+        if let Some(random_field) = settings.random_field {
+            for field in &mut local_fields {
+                *field += random_field.sample(rng);
+            }
         }
+
+        // a mean-field spin glass gets the average coupling, as the number of bonds would grow
+        // as N^2
+        let mean_field_coupling = match settings.geometry {
+            Geometry::MeanField => settings.exchange * settings.coupling_strengths[0] * settings.lattice_type.mean_coupling() / sites as f32,
+            _ => 0.0,
+        };
+
+        let unit_couplings = mean_field_coupling == 0.0 && bonds.iter().all(|(_, _, coupling)| [-1.0, 0.0, 1.0].contains(coupling))
+            && plaquettes.is_empty()
+            && settings.random_field.is_none()
+            && local_fields.iter().all(|field| field.fract() == 0.0);
+
+        let positions = layout.positions.iter().zip(occupied)
+            .filter(|(_, occupied)| **occupied)
+            .map(|(position, _)| *position)
+            .collect();
+
+        let mut structure = Structure {
+            image_width: layout.image_width,
+            image_height: layout.image_height,
+            layers: layout.layers,
+            pixels,
+            positions,
+            neighbour_offsets: Vec::new(),
+            neighbours: Vec::new(),
+            plaquette_offsets: Vec::new(),
+            plaquettes: Vec::new(),
+            local_fields,
+            boundary_bonds,
+            mean_field_coupling,
+            unit_couplings,
+            edited: false,
+            sublattices: Vec::new(),
+            slices: 1,
+            slice_sites: sites,
+        };
+        structure.set_bonds(&bonds);
+        structure.set_plaquettes(&plaquettes);
+        structure
     }
 
-    pub fn internal_energy(&self) -> f32 {
-        let mut energy = 0.0;
+    /// One copy of the structure for each of `slices` Trotter slices, stacked along y for a chain
+    /// and as extra layers otherwise. The couplings, plaquettes included, and local fields are
+    /// divided between the slices, and every site is bonded to itself in the neighbouring slices
+    /// by a coupling which set_trotter_coupling() sets (0 to begin with).
+    fn stack(&self, slices: usize) -> Structure {
+        let sites = self.site_count();
+        let scale = 1.0 / slices as f32;
+        let along_y = self.image_height == 1 && self.layers == 1;
+
+        let mut bonds = Vec::with_capacity(slices * (self.neighbours.len() / 2 + sites));
+        let mut plaquettes = Vec::with_capacity(slices * self.plaquettes.len() / 4);
+        for slice in 0..slices {
+            let offset = slice * sites;
+            for a in 0..sites {
+                for (b, coupling) in self.neighbours(a) {
+                    if *b > a {
+                        bonds.push((offset + a, offset + b, scale * coupling));
+                    }
+                }
+                // imaginary time is periodic
+                bonds.push((offset + a, (slice + 1) % slices * sites + a, 0.0));
 
-        let s = self.size as isize;
-        
-        for y in 0..s {
-            for x in 0..s {
-                energy += self.hamiltonian(x, y);
+                // each square once, from its lowest site
+                for (others, coupling) in self.plaquettes(a) {
+                    if others.iter().all(|b| *b > a) {
+                        plaquettes.push(([a, others[0], others[1], others[2]].map(|site| offset + site), scale * coupling));
+                    }
+                }
             }
         }
-        
-        energy
+
+        let pixels = (0..slices)
+            .flat_map(|slice| self.pixels.iter().map(move |pixel| match pixel {
+                Pixel::Site(site) => Pixel::Site(slice * sites + site),
+                other => *other,
+            }))
+            .collect();
+
+        let positions = (0..slices)
+            .flat_map(|slice| self.positions.iter().map(move |(x, y, layer)| if along_y {
+                (*x, slice, *layer)
+            } else {
+                (*x, *y, layer + slice * self.layers)
+            }))
+            .collect();
+
+        let mut structure = Structure {
+            image_width: self.image_width,
+            image_height: if along_y { slices } else { self.image_height },
+            layers: if along_y { 1 } else { slices * self.layers },
+            pixels,
+            positions,
+            neighbour_offsets: Vec::new(),
+            neighbours: Vec::new(),
+            plaquette_offsets: Vec::new(),
+            plaquettes: Vec::new(),
+            local_fields: self.local_fields.iter().map(|field| scale * field).cycle().take(slices * sites).collect(),
+            boundary_bonds: self.boundary_bonds.iter().copied().cycle().take(slices * sites).collect(),
+            mean_field_coupling: self.mean_field_coupling,
+            unit_couplings: false,
+            edited: false,
+            sublattices: Vec::new(),
+            slices,
+            slice_sites: sites,
+        };
+        structure.set_bonds(&bonds);
+        structure.set_plaquettes(&plaquettes);
+        structure
     }
 
-    pub fn heat_capacity(&self) -> f32 {
-        let mut energy = 0.0;
+    /// Change the coupling of every bond between Trotter slices
+    fn set_trotter_coupling(&mut self, coupling: f32) {
+        for site in 0..self.site_count() {
+            let slice = site / self.slice_sites;
+            let range = self.neighbour_offsets[site]..self.neighbour_offsets[site + 1];
 
-        let s = self.size as isize;
-        
-        for y in 0..s {
-            for x in 0..s {
-                let h = self.hamiltonian(x, y);
-                energy += h*h;
+            for (neighbour, neighbour_coupling) in &mut self.neighbours[range] {
+                if *neighbour / self.slice_sites != slice {
+                    *neighbour_coupling = coupling;
+                }
             }
         }
-        
-        (energy - self.internal_energy()) as f32 / self.state.len() as f32
     }
 
-    pub fn magnetisation(&self) -> f32 {
-        self.state.iter().map(|s| Into::<i32>::into(*s)).sum::<i32>() as f32 / self.state.len() as f32
+    /// Replace the bonds (and so the sublattices) with pairs of sites and their couplings
+    fn set_bonds(&mut self, bonds: &[(usize, usize, f32)]) {
+        let sites = self.positions.len();
+
+        let mut neighbour_offsets = vec![0; sites + 1];
+        for (a, b, _) in bonds {
+            neighbour_offsets[a + 1] += 1;
+            neighbour_offsets[b + 1] += 1;
+        }
+        for site in 0..sites {
+            neighbour_offsets[site + 1] += neighbour_offsets[site];
+        }
+
+        let mut neighbours = vec![(0, 0.0); neighbour_offsets[sites]];
+        let mut filled = neighbour_offsets.clone();
+        for (a, b, coupling) in bonds {
+            neighbours[filled[*a]] = (*b, *coupling);
+            filled[*a] += 1;
+            neighbours[filled[*b]] = (*a, *coupling);
+            filled[*b] += 1;
+        }
+
+        self.neighbour_offsets = neighbour_offsets;
+        self.neighbours = neighbours;
+        self.sublattices = self.colour();
+    }
+
+    /// Replace the plaquettes with squares of four sites and their couplings
+    fn set_plaquettes(&mut self, plaquettes: &[([usize; 4], f32)]) {
+        let sites = self.positions.len();
+
+        let mut plaquette_offsets = vec![0; sites + 1];
+        for (square, _) in plaquettes {
+            for site in square {
+                plaquette_offsets[site + 1] += 1;
+            }
+        }
+        for site in 0..sites {
+            plaquette_offsets[site + 1] += plaquette_offsets[site];
+        }
+
+        let mut entries = vec![([0; 3], 0.0); plaquette_offsets[sites]];
+        let mut filled = plaquette_offsets.clone();
+        for (square, coupling) in plaquettes {
+            for (corner, site) in square.iter().enumerate() {
+                entries[filled[*site]] = ([1, 2, 3].map(|i| square[(corner + i) % 4]), *coupling);
+                filled[*site] += 1;
+            }
+        }
+
+        self.plaquette_offsets = plaquette_offsets;
+        self.plaquettes = entries;
     }
 
-    pub fn hamiltonian(&self, x: isize, y: isize) -> f32 {
-        let mut energy = 0.0;
-        
-        // Spin interaction component
-        let spin = self.get(x, y);
+    fn site_count(&self) -> usize {
+        self.neighbour_offsets.len() - 1
+    }
 
-        let interactions = self.get_interactions(x, y);
-        
-        energy += -interactions.left  * (spin * self.get(x-1, y)) as f32;
-        energy += -interactions.up    * (spin * self.get(x, y-1)) as f32;
-        energy += -interactions.right * (spin * self.get(x+1, y)) as f32;
-        energy += -interactions.down  * (spin * self.get(x, y+1)) as f32;
+    fn neighbours(&self, site: usize) -> &[(usize, f32)] {
+        &self.neighbours[self.neighbour_offsets[site]..self.neighbour_offsets[site + 1]]
+    }
 
-        // Magnetic component
-        energy -= Into::<i32>::into(spin) as f32 * self.magnetic_field;
+    fn plaquettes(&self, site: usize) -> &[([usize; 3], f32)] {
+        &self.plaquettes[self.plaquette_offsets[site]..self.plaquette_offsets[site + 1]]
+    }
 
-        energy
+    /// Most bonds of any site, including bonds to the boundary
+    fn max_neighbours(&self) -> usize {
+        (0..self.site_count())
+            .map(|site| self.neighbours(site).len() + self.boundary_bonds[site])
+            .max()
+            .unwrap_or_default()
     }
 
-    pub fn step(&mut self) {
-        let s = self.size as isize;
-        let x = rand::thread_rng().gen_range(0..s);
-        let y = rand::thread_rng().gen_range(0..s);
-
-        let mut d_energy = -self.hamiltonian(x, y);
-
-        d_energy -= self.hamiltonian(x-1, y);
-        d_energy -= self.hamiltonian(x, y-1);
-        d_energy -= self.hamiltonian(x+1, y);
-        d_energy -= self.hamiltonian(x, y+1);
-
-        self.flip(x, y);
-
-        d_energy += self.hamiltonian(x, y);
-
-        d_energy += self.hamiltonian(x-1, y);
-        d_energy += self.hamiltonian(x, y-1);
-        d_energy += self.hamiltonian(x+1, y);
-        d_energy += self.hamiltonian(x, y+1);
-
-        // internal energy increases with this change, accept with boltzman probability
-        if d_energy > 0.0 && rand::thread_rng().gen_range(0.0..1.0) > boltzman(d_energy, self.temperature) {
-            // failed dice roll, undo flip
-            self.flip(x, y);
-        }
-    }
-
-    pub fn epoch(&mut self) {
-        for _ in 0..self.size*self.size {
-            self.step();
-        }
-    }
-
-    // I know it's horribly inefficient to generate the image this way, but it's too much work to do it properly.
-    pub fn as_image(&self, available_space: usize) -> egui::ColorImage {
-        let scale = available_space / self.size + 1;
-        
-        let mut rgb = Vec::with_capacity(self.size * self.size * scale * scale * 3);
-
-        for y in 0..self.size {
-            for _ in 0..scale {
-                for x in 0..self.size {
-                    match self.get(x as isize, y as isize) {
-                        Spin::Up => {
-                            // blue
-                            for _ in 0..scale {
-                                rgb.push(0);
-                                rgb.push(0);
-                                rgb.push(255);
-                            }
-                        },
-                        Spin::Down => {
-                            // red
-                            for _ in 0..scale {
-                                rgb.push(255);
-                                rgb.push(0);
-                                rgb.push(0);
-                            }
-                        },
-                    }
-                }
+    /// Greedy colouring in site order, which gives the usual checkerboard for even square lattices
+    fn colour(&self) -> Vec<Vec<usize>> {
+        let mut colours: Vec<usize> = Vec::with_capacity(self.site_count());
+        let mut sublattices: Vec<Vec<usize>> = Vec::new();
+
+        for site in 0..self.site_count() {
+            let taken: Vec<usize> = self.neighbours(site).iter()
+                .filter(|(neighbour, _)| *neighbour < site)
+                .map(|(neighbour, _)| colours[*neighbour])
+                .collect();
+
+            let colour = (0..).find(|colour| !taken.contains(colour)).unwrap_or_default();
+
+            if colour == sublattices.len() {
+                sublattices.push(Vec::new());
             }
+            sublattices[colour].push(site);
+            colours.push(colour);
         }
 
-        egui::ColorImage::from_rgb([self.size * scale, self.size * scale], rgb.as_slice())
+        sublattices
     }
+}
+
+#[derive(Debug, Clone)]
+pub struct Lattice {
+    // for models other than Ising, up for the sites in a state favoured by a positive field and
+    // down for the rest
+    state: Vec<Spin>,
+    // state of each site and number of sites in each state, empty unless the model is discrete
+    discrete_state: Vec<u8>,
+    discrete_counts: Vec<i64>,
+    // spin of each site and their sum, empty unless the model has vector spins
+    vector_state: Vec<[f32; 3]>,
+    vector_total: [f64; 3],
+    // shared between snapshots of the same lattice
+    structure: Arc<Structure>,
+    settings: LatticeSettings,
+    pub temperature: f32,
+    // difference in temperature between the right and left edges, which are at
+    // temperature ± temperature_gradient / 2
+    pub temperature_gradient: f32,
+    // magnetic field B, z component
+    pub magnetic_field: f32,
+    // multiple of B at each site, None for a uniform field
+    field_weights: Option<Arc<Vec<f32>>>,
+    // transverse field Γ of the transverse-field Ising model, and the temperature and Γ the
+    // couplings between Trotter slices were last set for
+    pub transverse_field: f32,
+    trotter_parameters: (f32, f32),
+    pub sweep_order: SweepOrder,
+    // all randomness (initial state, couplings, updates) comes from here, so runs are reproducible
+    rng: LatticeRng,
+    boltzman_table: BoltzmanTable,
+    // running totals, updated on every flip
+    exchange_energy_total: f64,
+    spin_total: i64,
+    // sum of the spins weighted by field_weights, only kept up to date if there are any or the
+    // model isn't Ising, in which case the sites count as field(state)
+    field_total: f64,
+    // Metropolis moves since the lattice was created
+    attempted_flips: u64,
+    accepted_flips: u64,
+    // accepted moves at each site, decaying by half every ACTIVITY_HALF_LIFE sweeps, empty unless
+    // tracked
+    activity: Vec<f32>,
+    // whether each site flipped during the latest sweep, tracked along with the activity
+    last_flips: Vec<bool>,
+}
 
-    // I know it's horribly inefficient to generate the image this way, but it's too much work to do it properly.
-    pub fn as_image_raw(&self) -> (Vec<u8>, usize) {
-        let mut rgb = Vec::with_capacity(self.size * self.size * 3);
+impl Lattice {
+    pub fn new(settings: &LatticeSettings, temperature: f32, magnetic_field: f32) -> Lattice {
+        let mut rng = LatticeRng::new(settings.rng_algorithm, settings.seed);
+        let layout = settings.layout();
 
-        for y in 0..self.size {
-            for x in 0..self.size {
-                match self.get(x as isize, y as isize) {
-                    Spin::Up => {
-                        // blue
-                        rgb.push(0);
-                        rgb.push(0);
-                        rgb.push(255);
-                    },
-                    Spin::Down => {
-                        // red
-                        rgb.push(255);
-                        rgb.push(0);
-                        rgb.push(0);
-                    },
-                }
+        let mut occupied: Vec<bool> = if settings.p_vacancy > 0.0 {
+            layout.positions.iter().map(|_| !rng.gen_bool(settings.p_vacancy.clamp(0.0, 1.0))).collect()
+        } else {
+            vec![true; layout.positions.len()]
+        };
+        // a lattice needs at least one site
+        if !occupied.contains(&true) {
+            occupied[0] = true;
+        }
+        // all Trotter slices start out independent
+        let sites = occupied.iter().filter(|occupied| **occupied).count() * settings.slices();
+
+        let vector_state: Vec<[f32; 3]> = match (settings.model, settings.initial_state) {
+            (model, _) if !model.is_vector() => Vec::new(),
+            (model, LatticeInitialState::Random) => (0..sites).map(|_| model.random_vector(&mut rng)).collect(),
+            (model, LatticeInitialState::AllUp) => vec![model.up_vector(true); sites],
+            (model, LatticeInitialState::AllDown) => vec![model.up_vector(false); sites],
+        };
+
+        let discrete_state: Vec<u8> = match (settings.model, settings.initial_state) {
+            (model, _) if model == Model::Ising || model.is_vector() => Vec::new(),
+            (model, LatticeInitialState::Random) => (0..sites).map(|_| rng.gen_range(0..model.state_count()) as u8).collect(),
+            (_, LatticeInitialState::AllUp) => vec![0; sites],
+            (model, LatticeInitialState::AllDown) => vec![model.down_state(); sites],
+        };
+
+        let state = match settings.initial_state {
+            _ if settings.model.is_vector() => vector_state.iter()
+                .map(|vector| settings.model.vector_spin(*vector))
+                .collect(),
+            _ if settings.model != Model::Ising => discrete_state.iter()
+                .map(|value| settings.model.spin(*value))
+                .collect(),
+            LatticeInitialState::Random => (0..sites)
+                .map(|_| if rng.gen::<bool>() { Spin::Up } else { Spin::Down })
+                .collect(),
+            LatticeInitialState::AllUp => vec![Spin::Up; sites],
+            LatticeInitialState::AllDown => vec![Spin::Down; sites],
+        };
+
+        let mut structure = Structure::new(layout, &occupied, settings, &mut rng);
+        if settings.slices() > 1 {
+            structure = structure.stack(settings.slices());
+        }
+        let max_neighbours = structure.max_neighbours();
+
+        let mut lattice = Lattice {
+            state,
+            discrete_state,
+            discrete_counts: vec![0; settings.model.state_count()],
+            vector_state,
+            vector_total: [0.0; 3],
+            structure: Arc::new(structure),
+            settings: settings.clone(),
+            temperature,
+            temperature_gradient: 0.0,
+            magnetic_field,
+            field_weights: None,
+            transverse_field: 1.0,
+            trotter_parameters: (f32::NAN, f32::NAN),
+            sweep_order: SweepOrder::Random,
+            rng,
+            boltzman_table: BoltzmanTable::new(temperature, magnetic_field, max_neighbours),
+            exchange_energy_total: 0.0,
+            spin_total: 0,
+            field_total: 0.0,
+            attempted_flips: 0,
+            accepted_flips: 0,
+            activity: Vec::new(),
+            last_flips: Vec::new(),
+        };
+        // spread the field over the Trotter slices
+        lattice.set_field_profile(&FieldProfile::Uniform);
+        lattice.update_trotter();
+        lattice
+    }
+
+    /// Second copy of the lattice with the same couplings and fields but its own random numbers,
+    /// starting from a random state, for measuring the overlap between replicas
+    pub fn replica(&self, seed: u64) -> Lattice {
+        let mut replica = self.clone();
+        replica.rng = LatticeRng::new(self.settings.rng_algorithm, seed);
+        replica.randomise();
+        replica
+    }
+
+    /// Give every site a random state, as at infinite temperature, keeping the bonds and fields
+    pub fn randomise(&mut self) {
+        let model = self.settings.model;
+
+        if model.is_vector() {
+            for (vector, spin) in self.vector_state.iter_mut().zip(self.state.iter_mut()) {
+                *vector = model.random_vector(&mut self.rng);
+                *spin = model.vector_spin(*vector);
+            }
+        } else if model != Model::Ising {
+            for (value, spin) in self.discrete_state.iter_mut().zip(self.state.iter_mut()) {
+                *value = self.rng.gen_range(0..model.state_count()) as u8;
+                *spin = model.spin(*value);
+            }
+        } else {
+            for spin in self.state.iter_mut() {
+                *spin = if self.rng.gen::<bool>() { Spin::Up } else { Spin::Down };
             }
         }
 
-        (rgb, self.size)
+        self.attempted_flips = 0;
+        self.accepted_flips = 0;
+        self.activity.clear();
+        self.last_flips.clear();
+        self.recount();
     }
 
-    fn index(&self, x: isize, y: isize) -> usize {
-        let s = self.size as isize;
-        (x.rem_euclid(s) + y.rem_euclid(s) * s) as usize
+    pub fn internal_energy(&self) -> f32 {
+        let field_total = if self.field_weights.is_some() || self.settings.model != Model::Ising {
+            self.field_total
+        } else {
+            self.spin_total as f64
+        };
+
+        (self.exchange_energy_total - self.magnetic_field as f64 * field_total) as f32
     }
 
-    fn get(&self, x: isize, y: isize) -> Spin {
-        self.state[self.index(x, y)]
+    /// Shape the magnetic field over the lattice
+    pub fn set_field_profile(&mut self, profile: &FieldProfile) {
+        let slices = self.structure.slices;
+
+        self.field_weights = match profile {
+            FieldProfile::Uniform if slices == 1 => None,
+            _ => {
+                let (width, mut height) = (self.structure.image_width, self.structure.image_height);
+                // a chain's slices are stacked as rows
+                let along_y = slices > 1 && self.structure.layers == 1;
+                if along_y {
+                    height = 1;
+                }
+
+                Some(Arc::new(self.structure.positions.iter()
+                    .map(|(x, y, _)| profile.weight(*x, if along_y { 0 } else { *y }, width, height) / slices as f32)
+                    .collect()))
+            },
+        };
+
+        self.recount();
     }
 
-    fn get_interactions(&self, x: isize, y: isize) -> Interactions {
-        let current = &self.interations[self.index(x, y)];
-        let right = &self.interations[self.index(x+1, y)];
-        let bottom = &self.interations[self.index(x, y+1)];
-        
-        Interactions {
-            up: current.up,
-            left: current.left,
-            down: bottom.up,
-            right: right.left,
+    /// Set the coupling between Trotter slices if the temperature or transverse field has changed,
+    /// K = -T/2 ln tanh(Γ / (M T)) for M slices
+    fn update_trotter(&mut self) {
+        let parameters = (self.temperature, self.transverse_field);
+        if self.structure.slices == 1 || self.trotter_parameters == parameters {
+            return;
         }
+        self.trotter_parameters = parameters;
+
+        let slices = self.structure.slices as f32;
+        let coupling = if self.temperature > 0.0 {
+            // without a transverse field the slices are locked together
+            let gamma = self.transverse_field.max(1e-6);
+            -0.5 * self.temperature * (gamma / (slices * self.temperature)).tanh().ln()
+        } else {
+            0.0
+        };
+
+        Arc::make_mut(&mut self.structure).set_trotter_coupling(coupling);
+        self.recount();
     }
 
-    fn flip(&mut self, x: isize, y: isize) {
-        let i = self.index(x, y);
-        self.state[i] = -self.state[i];
+    /// Trotter slices of a transverse-field Ising model, 1 for a classical model
+    pub fn trotter_slices(&self) -> usize {
+        self.structure.slices
+    }
+
+    /// Temperature at a site, varying linearly across x with the temperature gradient and never
+    /// below 0
+    fn temperature_at(&self, site: usize) -> f32 {
+        if self.temperature_gradient == 0.0 {
+            return self.temperature;
+        }
+
+        let x = self.structure.positions[site].0 as f32 + 0.5;
+        let offset = x / self.structure.image_width as f32 - 0.5;
+        (self.temperature + self.temperature_gradient * offset).max(0.0)
+    }
+
+    /// Multiple of B on a site, from the field profile
+    fn field_weight(&self, site: usize) -> f32 {
+        match &self.field_weights {
+            Some(weights) => weights[site],
+            None => 1.0,
+        }
+    }
+
+    /// Field on a site, B times the profile
+    fn field_at(&self, site: usize) -> f32 {
+        self.magnetic_field * self.field_weight(site)
+    }
+
+    /// Recompute the running energy and magnetisation totals from scratch
+    fn recount(&mut self) {
+        // needed first, the mean-field part of the neighbour sums depends on it
+        self.spin_total = self.state.iter().map(|spin| Into::<i32>::into(*spin) as i64).sum();
+
+        if self.settings.model.is_vector() {
+            self.vector_total = [0.0; 3];
+            for vector in &self.vector_state {
+                for (total, component) in self.vector_total.iter_mut().zip(vector) {
+                    *total += *component as f64;
+                }
+            }
+
+            let mut exchange_energy = 0.0;
+            let mut field_total = 0.0;
+            for site in 0..self.state.len() {
+                let vector = self.vector_state[site];
+                let (pair, single) = self.vector_energy(site, vector);
+
+                exchange_energy += 0.5 * pair as f64 + single as f64;
+                field_total += (self.field_weight(site) * self.settings.model.vector_field(vector)) as f64;
+            }
+
+            self.exchange_energy_total = exchange_energy;
+            self.field_total = field_total;
+            return;
+        }
+
+        if self.settings.model != Model::Ising {
+            self.discrete_counts.fill(0);
+            for value in &self.discrete_state {
+                self.discrete_counts[*value as usize] += 1;
+            }
+
+            let mut exchange_energy = 0.0;
+            let mut field_total = 0.0;
+            for site in 0..self.state.len() {
+                let value = self.discrete_state[site];
+                let (pair, single) = self.discrete_energy(site, value);
+
+                // as for spins, pair energies are shared between the two sites
+                exchange_energy += 0.5 * pair as f64 + single as f64;
+                field_total += (self.field_weight(site) * self.settings.model.field(value)) as f64;
+            }
+
+            self.exchange_energy_total = exchange_energy;
+            self.field_total = field_total;
+            return;
+        }
+
+        if let Some(weights) = &self.field_weights {
+            self.field_total = self.state.iter().zip(weights.iter())
+                .map(|(spin, weight)| Into::<i32>::into(*spin) as f64 * *weight as f64)
+                .sum();
+        }
+
+        let mut exchange_energy = 0.0;
+
+        for site in 0..self.state.len() {
+            let spin = Into::<i32>::into(self.state[site]) as f64;
+            let local_field = self.structure.local_fields[site] as f64;
+            let plaquette = self.plaquette_sum(site) as f64;
+
+            // each bond is shared between two sites and each plaquette between four, bonds to the
+            // boundary and the random field belong to one
+            exchange_energy -= spin * (0.5 * (self.neighbour_sum(site) as f64 - local_field - plaquette) + local_field + 0.25 * plaquette);
+        }
+
+        self.exchange_energy_total = exchange_energy;
+    }
+
+    /// Settings the lattice was generated with
+    pub fn settings(&self) -> &LatticeSettings {
+        &self.settings
+    }
+
+    /// Seed the lattice was generated and is being updated with
+    pub fn seed(&self) -> u64 {
+        self.settings.seed
+    }
+
+    pub fn rng_algorithm(&self) -> RngAlgorithm {
+        self.rng.algorithm()
+    }
+
+    /// Metropolis moves attempted since the lattice was created
+    pub fn attempted_flips(&self) -> u64 {
+        self.attempted_flips
+    }
+
+    /// Metropolis moves accepted since the lattice was created
+    pub fn accepted_flips(&self) -> u64 {
+        self.accepted_flips
+    }
+
+    /// Coupling J between every pair of sites of a mean-field lattice (before dividing by N), 0
+    /// for any other geometry
+    pub fn mean_field_coupling(&self) -> f32 {
+        self.structure.mean_field_coupling * self.state.len() as f32
+    }
+
+    /// Staggered magnetisation, the order parameter of an antiferromagnet: the magnetisation with
+    /// the spins of every other site flipped. None unless the model is Ising and the bonds split
+    /// the sites into two sublattices.
+    pub fn staggered_magnetisation(&self) -> Option<f32> {
+        let [even, odd] = self.sublattice_sums()?;
+        Some((even - odd) as f32 / self.state.len() as f32)
+    }
+
+    /// Magnetisation of each of the two sublattices on its own, m_A and m_B, with the same
+    /// conditions as staggered_magnetisation(). An ordered antiferromagnet has m_A = -m_B.
+    pub fn sublattice_magnetisations(&self) -> Option<[f32; 2]> {
+        let sums = self.sublattice_sums()?;
+        let [even, odd] = self.structure.sublattices.as_slice() else {
+            return None;
+        };
+
+        Some([sums[0] as f32 / even.len().max(1) as f32, sums[1] as f32 / odd.len().max(1) as f32])
+    }
+
+    /// Sum of the spins of each of the two sublattices, for the Ising model
+    fn sublattice_sums(&self) -> Option<[i64; 2]> {
+        let [even, odd] = self.structure.sublattices.as_slice() else {
+            return None;
+        };
+        if self.settings.model != Model::Ising {
+            return None;
+        }
+
+        let sum = |sites: &[usize]| sites.iter().map(|site| Into::<i32>::into(self.state[*site]) as i64).sum::<i64>();
+        Some([sum(even), sum(odd)])
+    }
+
+    /// Spin overlap q = (1/N) Σ s_i s'_i with a replica, using the up and down spins drawn for
+    /// models other than Ising. The order parameter of a spin glass.
+    pub fn overlap(&self, replica: &Lattice) -> f32 {
+        let sum: i64 = self.state.iter().zip(&replica.state)
+            .map(|(a, b)| (Into::<i32>::into(*a) * Into::<i32>::into(*b)) as i64)
+            .sum();
+
+        sum as f32 / self.state.len() as f32
+    }
+
+    /// Fraction of sites occupied in the lattice-gas reading of the Ising model, where up spins
+    /// are particles and down spins are empty, n = (1 + s) / 2
+    pub fn density(&self) -> f32 {
+        0.5 * (1.0 + self.magnetisation())
+    }
+
+    /// Chemical potential μ of the lattice gas a field B corresponds to. With n = (1 + s) / 2 the
+    /// Ising Hamiltonian becomes -Σ 4J n_i n_j - μ Σ n_i plus a constant, where μ = 2B - 2 Σ_j J_ij
+    /// (averaged over the sites, so exact for lattices with the same couplings everywhere).
+    pub fn chemical_potential(&self, magnetic_field: f32) -> f32 {
+        2.0 * (magnetic_field - self.mean_coupling_sum())
+    }
+
+    /// Field which gives the lattice gas a chemical potential μ, see chemical_potential()
+    pub fn field_for_chemical_potential(&self, chemical_potential: f32) -> f32 {
+        0.5 * chemical_potential + self.mean_coupling_sum()
+    }
+
+    /// Energy and entropy per site at infinite temperature, where every site is in each state
+    /// with equal probability, the starting point for thermodynamic integration. None for vector
+    /// spins, whose entropy has no natural zero, and with Trotter slices, whose energy isn't the
+    /// quantum model's.
+    pub fn infinite_temperature(&self) -> Option<(f32, f32)> {
+        let model = self.settings.model;
+        if model.is_vector() || self.structure.slices > 1 {
+            return None;
+        }
+
+        let q = model.state_count();
+        let states = 0..q as u8;
+        let mean = |value: &dyn Fn(u8) -> f32| states.clone().map(value).sum::<f32>() / q as f32;
+        let pair = mean(&|a| mean(&|b| model.pair(a, b)));
+
+        let sites = self.state.len();
+        let field = (0..sites).map(|site| self.structure.local_fields[site] + self.field_at(site)).sum::<f32>() / sites as f32;
+
+        // each bond counts towards the coupling sums of both its sites
+        let energy = -0.5 * pair * self.mean_coupling_sum() + mean(&|a| model.onsite(a)) - field * mean(&|a| model.field(a));
+
+        Some((energy, (q as f32).ln()))
+    }
+
+    /// Sum of the couplings of a site to all the others, averaged over the sites
+    fn mean_coupling_sum(&self) -> f32 {
+        let sites = self.state.len();
+        let bonds: f64 = self.structure.neighbours.iter().map(|(_, coupling)| *coupling as f64).sum();
+
+        (bonds / sites as f64) as f32 + self.structure.mean_field_coupling * (sites - 1) as f32
+    }
+
+    /// Number of sites
+    pub fn spin_count(&self) -> usize {
+        self.state.len()
+    }
+
+    /// Magnetisation per site, or the model's order parameter if it isn't Ising
+    pub fn magnetisation(&self) -> f32 {
+        if self.settings.model.is_vector() {
+            let [x, y, z] = self.vector_total;
+            return ((x * x + y * y + z * z).sqrt() / self.state.len() as f64) as f32;
+        }
+        if self.settings.model != Model::Ising {
+            return self.settings.model.order_parameter(&self.discrete_counts);
+        }
+
+        self.spin_total as f32 / self.state.len() as f32
+    }
+
+    /// Sum of the neighbouring spins, including pinned boundary spins and (in mean field) every
+    /// other spin, weighted by their coupling to the site, plus the site's random field and
+    /// plaquettes
+    fn neighbour_sum(&self, site: usize) -> f32 {
+        let mean_field = if self.structure.mean_field_coupling != 0.0 {
+            let others = self.spin_total - Into::<i32>::into(self.state[site]) as i64;
+            self.structure.mean_field_coupling * others as f32
+        } else {
+            0.0
+        };
+
+        self.structure.neighbours(site).iter()
+            .map(|(neighbour, coupling)| coupling * Into::<i32>::into(self.state[*neighbour]) as f32)
+            .sum::<f32>()
+            + self.structure.local_fields[site]
+            + self.plaquette_sum(site)
+            + mean_field
+    }
+
+    /// Sum over the plaquettes of a site of their coupling times the other three spins
+    fn plaquette_sum(&self, site: usize) -> f32 {
+        self.structure.plaquettes(site).iter()
+            .map(|(others, coupling)| {
+                let product: i32 = others.iter().map(|other| Into::<i32>::into(self.state[*other])).product();
+                coupling * product as f32
+            })
+            .sum()
+    }
+
+    /// Energy of a site of a discrete model if it were in the given state, split into the bonds
+    /// (including the mean-field coupling to every other site) and the rest, but without the
+    /// external field
+    fn discrete_energy(&self, site: usize, value: u8) -> (f32, f32) {
+        let model = self.settings.model;
+
+        let mut pair = -self.structure.neighbours(site).iter()
+            .map(|(neighbour, coupling)| coupling * model.pair(value, self.discrete_state[*neighbour]))
+            .sum::<f32>();
+
+        if self.structure.mean_field_coupling != 0.0 {
+            let current = self.discrete_state[site] as usize;
+            pair -= self.structure.mean_field_coupling * self.discrete_counts.iter().enumerate()
+                .map(|(other, count)| (count - (other == current) as i64) as f32 * model.pair(value, other as u8))
+                .sum::<f32>();
+        }
+
+        // pinned boundary spins act through the local field, so pinned down spins favour every
+        // state but 0
+        (pair, model.onsite(value) - self.structure.local_fields[site] * model.field(value))
+    }
+
+    /// Metropolis update of a site of a discrete model, proposing one of the other states
+    fn step_discrete(&mut self, site: usize) {
+        let model = self.settings.model;
+        let old = self.discrete_state[site];
+        let mut new = self.rng.gen_range(0..model.state_count() - 1) as u8;
+        if new >= old {
+            new += 1;
+        }
+
+        let (old_pair, old_single) = self.discrete_energy(site, old);
+        let (new_pair, new_single) = self.discrete_energy(site, new);
+        let d_exchange = new_pair + new_single - old_pair - old_single;
+        let d_field = self.field_weight(site) * (model.field(new) - model.field(old));
+
+        let factor = acceptance(d_exchange - self.magnetic_field * d_field, self.temperature_at(site));
+
+        self.attempted_flips += 1;
+
+        if self.rng.gen_range(0.0..1.0) < factor {
+            self.exchange_energy_total += d_exchange as f64;
+            self.field_total += d_field as f64;
+            self.discrete_counts[old as usize] -= 1;
+            self.discrete_counts[new as usize] += 1;
+            self.discrete_state[site] = new;
+
+            let spin = model.spin(new);
+            self.spin_total += (Into::<i32>::into(spin) - Into::<i32>::into(self.state[site])) as i64;
+            self.state[site] = spin;
+
+            self.accept(site);
+        }
+    }
+
+    /// As discrete_energy(), for a model with vector spins
+    fn vector_energy(&self, site: usize, vector: [f32; 3]) -> (f32, f32) {
+        let dot = |a: [f32; 3], b: [f32; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+
+        let mut pair = -self.structure.neighbours(site).iter()
+            .map(|(neighbour, coupling)| coupling * dot(vector, self.vector_state[*neighbour]))
+            .sum::<f32>();
+
+        if self.structure.mean_field_coupling != 0.0 {
+            let current = self.vector_state[site];
+            let others = [0, 1, 2].map(|i| (self.vector_total[i] - current[i] as f64) as f32);
+            pair -= self.structure.mean_field_coupling * dot(vector, others);
+        }
+
+        (pair, -self.structure.local_fields[site] * self.settings.model.vector_field(vector))
+    }
+
+    /// Metropolis update of a site of a model with vector spins
+    fn step_vector(&mut self, site: usize) {
+        let model = self.settings.model;
+        let old = self.vector_state[site];
+        let new = model.propose_vector(old, &mut self.rng);
+
+        let (old_pair, old_single) = self.vector_energy(site, old);
+        let (new_pair, new_single) = self.vector_energy(site, new);
+        let d_exchange = new_pair + new_single - old_pair - old_single;
+        let d_field = self.field_weight(site) * (model.vector_field(new) - model.vector_field(old));
+
+        let factor = acceptance(d_exchange - self.magnetic_field * d_field, self.temperature_at(site));
+
+        self.attempted_flips += 1;
+
+        if self.rng.gen_range(0.0..1.0) < factor {
+            self.exchange_energy_total += d_exchange as f64;
+            self.field_total += d_field as f64;
+            for i in 0..3 {
+                self.vector_total[i] += (new[i] - old[i]) as f64;
+            }
+            self.vector_state[site] = new;
+
+            let spin = model.vector_spin(new);
+            self.spin_total += (Into::<i32>::into(spin) - Into::<i32>::into(self.state[site])) as i64;
+            self.state[site] = spin;
+
+            self.accept(site);
+        }
+    }
+
+    /// Count an accepted move at a site
+    fn accept(&mut self, site: usize) {
+        self.accepted_flips += 1;
+        if let Some(activity) = self.activity.get_mut(site) {
+            *activity += 1.0;
+        }
+        if let Some(flipped) = self.last_flips.get_mut(site) {
+            *flipped = true;
+        }
+    }
+
+    /// Start or stop keeping track of how often each site flips, for SiteColouring::Activity, and
+    /// which sites flipped in the latest sweep, for overlay_last_flips()
+    pub fn set_track_activity(&mut self, track: bool) {
+        if track != self.tracks_activity() {
+            self.activity = if track { vec![0.0; self.state.len()] } else { Vec::new() };
+            self.last_flips = if track { vec![false; self.state.len()] } else { Vec::new() };
+        }
+    }
+
+    pub fn tracks_activity(&self) -> bool {
+        !self.activity.is_empty()
+    }
+
+    fn decay_activity(&mut self, sweeps: usize) {
+        let factor = 0.5f32.powf(sweeps as f32 / ACTIVITY_HALF_LIFE);
+        for activity in &mut self.activity {
+            *activity *= factor;
+        }
+    }
+
+    /// Metropolis update of a randomly chosen site
+    pub fn step(&mut self) {
+        let site = self.rng.gen_range(0..self.state.len());
+
+        self.step_at(site);
+    }
+
+    /// Metropolis update of the given site
+    pub fn step_at(&mut self, site: usize) {
+        self.update_trotter();
+
+        if self.settings.model.is_vector() {
+            self.step_vector(site);
+            return;
+        }
+        if self.settings.model != Model::Ising {
+            self.step_discrete(site);
+            return;
+        }
+
+        self.boltzman_table.update(self.temperature, self.magnetic_field);
+
+        // flipping s changes the energy by dE = 2s(sum + B), which only depends on the local field
+        let spin = self.state[site];
+        let neighbour_sum = self.neighbour_sum(site);
+        let factor = if self.structure.unit_couplings && self.field_weights.is_none() && self.temperature_gradient == 0.0 {
+            self.boltzman_table.get(spin, neighbour_sum)
+        } else {
+            let spin = Into::<i32>::into(spin) as f32;
+            acceptance(2.0 * spin * (neighbour_sum + self.field_at(site)), self.temperature_at(site))
+        };
+
+        self.attempted_flips += 1;
+
+        // accept with boltzman probability
+        if self.rng.gen_range(0.0..1.0) < factor {
+            self.flip(site);
+            self.accept(site);
+        }
+    }
+
+    /// Several sweeps (N site updates each) in a row, using the bit-packed kernel where possible
+    pub fn epochs(&mut self, count: usize) {
+        if !self.multispin_active() {
+            for _ in 0..count {
+                self.sweep();
+            }
+            return;
+        }
+
+        self.boltzman_table.update(self.temperature, self.magnetic_field);
+
+        let mut factors = [[0.0; 5]; 2];
+        for (s, spin) in [Spin::Up, Spin::Down].into_iter().enumerate() {
+            for (c, factor) in factors[s].iter_mut().enumerate() {
+                // c unsatisfied bonds out of four
+                let neighbour_sum = Into::<i32>::into(spin) * (4 - 2 * c as i32);
+                *factor = self.boltzman_table.get(spin, neighbour_sum as f32);
+            }
+        }
+
+        let antiferromagnetic = self.settings.lattice_type == LatticeType::Antiferromagnetic;
+        // the packed kernel doesn't report which sites flipped, only the sites which end up
+        // different count towards the activity
+        let before = self.tracks_activity().then(|| self.state.clone());
+        let mut before_last = before.clone();
+        let mut bits = Multispin::pack(&self.state, self.settings.width, self.settings.height);
+        for sweep in 0..count {
+            if let (true, Some(before_last)) = (sweep > 0 && sweep + 1 == count, &mut before_last) {
+                bits.unpack(before_last);
+            }
+            self.accepted_flips += bits.sweep(antiferromagnetic, &factors, &mut self.rng);
+        }
+        self.attempted_flips += (count * self.state.len()) as u64;
+        bits.unpack(&mut self.state);
+
+        if let Some(before_last) = before_last {
+            for ((flipped, old), new) in self.last_flips.iter_mut().zip(before_last).zip(&self.state) {
+                *flipped = old != *new;
+            }
+        }
+        if let Some(before) = before {
+            self.decay_activity(count);
+            for ((activity, old), new) in self.activity.iter_mut().zip(before).zip(&self.state) {
+                if !matches!((old, new), (Spin::Up, Spin::Up) | (Spin::Down, Spin::Down)) {
+                    *activity += 1.0;
+                }
+            }
+        }
+
+        (self.spin_total, self.exchange_energy_total) = bits.totals(antiferromagnetic);
+    }
+
+    /// Whether sweeps use the bit-packed multispin kernel, which requires a periodic square
+    /// lattice with uniform nearest-neighbour couplings and a checkerboard decomposition
+    pub fn multispin_active(&self) -> bool {
+        self.sweep_order == SweepOrder::Checkerboard
+            && self.settings.model == Model::Ising
+            && self.settings.geometry == Geometry::Square
+            && self.settings.next_nearest_coupling == 0.0
+            && self.settings.axial_kappa == 0.0
+            && self.settings.plaquette_coupling == 0.0
+            && self.structure.slices == 1
+            && self.settings.long_range.is_none()
+            && self.settings.dipolar.is_none()
+            && self.settings.p_vacancy == 0.0
+            && self.settings.p_bond_dilution == 0.0
+            && self.settings.random_field.is_none()
+            && self.field_weights.is_none()
+            && self.temperature_gradient == 0.0
+            && !self.structure.edited
+            && self.settings.exchange == 1.0
+            && self.settings.coupling_strengths[..2] == [1.0; 2]
+            && self.settings.boundaries[..2] == [Boundary::Periodic; 2]
+            && matches!(self.settings.lattice_type, LatticeType::Ferromagnetic | LatticeType::Antiferromagnetic)
+            && self.settings.width & 1 == 0
+            && self.settings.height & 1 == 0
+    }
+
+    fn sweep(&mut self) {
+        self.last_flips.fill(false);
+
+        match self.sweep_order {
+            SweepOrder::Random => {
+                for _ in 0..self.state.len() {
+                    self.step();
+                }
+            },
+            SweepOrder::Sequential => {
+                for site in 0..self.state.len() {
+                    self.step_at(site);
+                }
+            },
+            SweepOrder::Checkerboard => {
+                let structure = self.structure.clone();
+
+                for sublattice in &structure.sublattices {
+                    for site in sublattice {
+                        self.step_at(*site);
+                    }
+                }
+            },
+        }
+
+        self.decay_activity(1);
+    }
+
+    /// Flip a site by hand: an Ising spin turns over, a discrete state moves on to the next one
+    /// and a vector is reversed
+    pub fn flip_site(&mut self, site: usize) {
+        let model = self.settings.model;
+
+        if model.is_vector() {
+            let vector = self.vector_state[site].map(|component| -component);
+            self.vector_state[site] = vector;
+            self.state[site] = model.vector_spin(vector);
+        } else if model != Model::Ising {
+            let value = ((self.discrete_state[site] as usize + 1) % model.state_count()) as u8;
+            self.discrete_state[site] = value;
+            self.state[site] = model.spin(value);
+        } else {
+            self.state[site] = -self.state[site];
+        }
+
+        self.recount();
+    }
+
+    /// Set sites by hand to the state most aligned with the field for Spin::Up, or against it for
+    /// Spin::Down
+    pub fn paint(&mut self, sites: &[usize], spin: Spin) {
+        let model = self.settings.model;
+        let sign = Into::<i32>::into(spin) as f32;
+
+        if model.is_vector() {
+            let vector = match model {
+                Model::Heisenberg => [0.0, 0.0, sign],
+                _ => [sign, 0.0, 0.0],
+            };
+            for site in sites {
+                self.vector_state[*site] = vector;
+                self.state[*site] = spin;
+            }
+        } else if model != Model::Ising {
+            let value = (0..model.state_count() as u8)
+                .max_by(|a, b| (sign * model.field(*a)).total_cmp(&(sign * model.field(*b))))
+                .unwrap_or_default();
+            for site in sites {
+                self.discrete_state[*site] = value;
+                self.state[*site] = model.spin(value);
+            }
+        } else {
+            for site in sites {
+                self.state[*site] = spin;
+            }
+        }
+
+        self.recount();
+    }
+
+    /// Site drawn at pixel (x, y) of a layer's image, if there is one
+    pub fn site_at(&self, x: usize, y: usize, layer: usize) -> Option<usize> {
+        let (width, height) = (self.structure.image_width, self.structure.image_height);
+        if x >= width || y >= height || layer >= self.structure.layers {
+            return None;
+        }
+
+        match self.structure.pixels[x + y * width + layer * width * height] {
+            Pixel::Site(site) => Some(site),
+            Pixel::Vacancy | Pixel::Empty => None,
+        }
+    }
+
+    /// Vector spin of a site, None unless the model has vector spins
+    pub fn vector(&self, site: usize) -> Option<[f32; 3]> {
+        self.vector_state.get(site).copied()
+    }
+
+    pub fn site_info(&self, site: usize) -> SiteInfo {
+        let model = self.settings.model;
+        let state = if model.is_vector() {
+            let [x, y, z] = self.vector_state[site];
+            format!("({:.2}, {:.2}, {:.2})", x, y, z)
+        } else if model != Model::Ising {
+            format!("State {}", self.discrete_state[site])
+        } else {
+            match self.state[site] {
+                Spin::Up => "↑".to_string(),
+                Spin::Down => "↓".to_string(),
+            }
+        };
+        let field = if model == Model::Ising {
+            self.neighbour_sum(site) + self.field_at(site)
+        } else {
+            self.field_at(site)
+        };
+
+        SiteInfo {
+            position: self.structure.positions[site],
+            state,
+            field,
+            energy: self.local_energy(site),
+            couplings: self.structure.neighbours(site).to_vec(),
+        }
+    }
+
+    /// Bonds between sites in neighbouring pixels (diagonals included) of a layer, each listed
+    /// once. Bonds wrapping around the boundary or reaching further aren't drawn.
+    pub fn drawn_bonds(&self, layer: usize) -> Vec<DrawnBond> {
+        let positions = &self.structure.positions;
+        let settings = &self.settings;
+        let slice_sites = self.structure.slice_sites;
+        let mut bonds = Vec::new();
+
+        let strength = |a: usize, b: usize, from, to, coupling: f32| {
+            // bonds between Trotter slices and graph edges have no strength of their own to go
+            // back to, they keep the one they have unless they've been cut
+            if a / slice_sites != b / slice_sites || settings.geometry == Geometry::Graph {
+                return if coupling != 0.0 { coupling.abs() } else { (settings.exchange * settings.coupling_strengths[0]).abs() };
+            }
+            let strength = match settings.geometry.drawn_direction(from, to) {
+                Some(direction) => settings.coupling_strengths[direction],
+                None => settings.next_nearest_coupling,
+            };
+            (settings.exchange * strength).abs() / self.structure.slices as f32
+        };
+
+        for (a, (ax, ay, a_layer)) in positions.iter().enumerate() {
+            if *a_layer != layer {
+                continue;
+            }
+
+            for (b, coupling) in self.structure.neighbours(a) {
+                let (bx, by, b_layer) = positions[*b];
+                if *b > a && b_layer == layer && ax.abs_diff(bx) <= 1 && ay.abs_diff(by) <= 1 {
+                    let (from, to) = ((*ax, *ay), (bx, by));
+                    bonds.push(DrawnBond { a, b: *b, from, to, coupling: *coupling, strength: strength(a, *b, from, to, *coupling) });
+                }
+            }
+        }
+
+        bonds
+    }
+
+    /// Change the coupling of every bond between sites a and b, for hand crafted interactions
+    pub fn set_coupling(&mut self, a: usize, b: usize, coupling: f32) {
+        let structure = Arc::make_mut(&mut self.structure);
+
+        for (from, to) in [(a, b), (b, a)] {
+            let range = structure.neighbour_offsets[from]..structure.neighbour_offsets[from + 1];
+            for (neighbour, neighbour_coupling) in &mut structure.neighbours[range] {
+                if *neighbour == to {
+                    *neighbour_coupling = coupling;
+                }
+            }
+        }
+
+        structure.unit_couplings &= [-1.0, 0.0, 1.0].contains(&coupling);
+        structure.edited = true;
+
+        self.recount();
+    }
+
+    /// Every bond (listed once) and local field, to save this realisation of the disorder
+    pub fn couplings(&self) -> Couplings {
+        let sites = self.structure.site_count();
+
+        let bonds = (0..sites)
+            .flat_map(|a| self.structure.neighbours(a).iter()
+                .filter(move |(b, _)| *b > a)
+                .map(move |(b, coupling)| (a, *b, *coupling)))
+            .collect();
+
+        let fields = self.structure.local_fields.iter().enumerate()
+            .filter(|(_, field)| **field != 0.0)
+            .map(|(site, field)| (site, *field))
+            .collect();
+
+        Couplings { sites, bonds, fields }
+    }
+
+    /// Replace every bond and local field, the site count must match. The sites keep their
+    /// positions, so bonds loaded from elsewhere needn't be drawn between neighbours.
+    pub fn load_couplings(&mut self, couplings: &Couplings) {
+        assert_eq!(couplings.sites, self.state.len(), "couplings are for a different number of sites");
+
+        let structure = Arc::make_mut(&mut self.structure);
+        structure.set_bonds(&couplings.bonds);
+
+        structure.local_fields = vec![0.0; couplings.sites];
+        for (site, field) in &couplings.fields {
+            structure.local_fields[*site] += field;
+        }
+        // integer fields count as bonds to the boundary in the Boltzmann table
+        structure.boundary_bonds = structure.local_fields.iter()
+            .map(|field| field.abs().ceil() as usize)
+            .collect();
+
+        structure.unit_couplings = structure.mean_field_coupling == 0.0
+            && structure.slices == 1
+            && structure.plaquettes.is_empty()
+            && couplings.bonds.iter().all(|(_, _, coupling)| [-1.0, 0.0, 1.0].contains(coupling))
+            && structure.local_fields.iter().all(|field| field.fract() == 0.0);
+        structure.edited = true;
+
+        self.boltzman_table = BoltzmanTable::new(self.temperature, self.magnetic_field, structure.max_neighbours());
+        self.recount();
+    }
+
+    /// Width and height of each layer's image, in pixels
+    pub fn image_size(&self) -> (usize, usize) {
+        (self.structure.image_width, self.structure.image_height)
+    }
+
+    /// Number of images (z slices) needed to show every site
+    pub fn layers(&self) -> usize {
+        self.structure.layers
+    }
+
+    /// Colour of the pixel at (x, y) of a layer, the palette's colour for an up or down spin (or
+    /// the state's colour for models other than Ising), light grey for a vacancy and dark grey if
+    /// there's no site there
+    /// `colours` is the colour of each site for colourings other than SiteColouring::State
+    fn pixel(&self, x: usize, y: usize, layer: usize, colours: Option<&[[u8; 3]]>, palette: Palette) -> [u8; 3] {
+        let (width, height) = (self.structure.image_width, self.structure.image_height);
+
+        match (self.structure.pixels[x + y * width + layer * width * height], colours) {
+            (Pixel::Site(site), Some(colours)) => colours[site],
+            (Pixel::Site(site), None) if self.settings.model.is_vector() => self.settings.model.vector_colour(self.vector_state[site]),
+            (Pixel::Site(site), None) if self.settings.model != Model::Ising => self.settings.model.colour(self.discrete_state[site]),
+            (Pixel::Site(site), None) => match self.state[site] {
+                Spin::Up => palette.up,
+                Spin::Down => palette.down,
+            },
+            (Pixel::Vacancy, _) => [200, 200, 200],
+            (Pixel::Empty, _) => [40, 40, 40],
+        }
+    }
+
+    /// Colour of each site, None for SiteColouring::State
+    fn site_colours(&self, colouring: SiteColouring) -> Option<Vec<[u8; 3]>> {
+        if colouring == SiteColouring::Clusters {
+            let mut domains = self.domains();
+            // roots spread around the colour wheel by the golden ratio
+            return Some((0..self.state.len())
+                .map(|site| model::hue((domains.find(site) as f32 * 0.618_034).fract()))
+                .collect());
+        }
+
+        // black through red and yellow to white
+        let heat = |level: f32| {
+            let channel = |offset: f32| (255.0 * (3.0 * level - offset).clamp(0.0, 1.0)) as u8;
+            [channel(0.0), channel(1.0), channel(2.0)]
+        };
+        Some(self.site_levels(colouring)?.into_iter().map(heat).collect())
+    }
+
+    /// Brightness from 0 to 1 of each site for the colourings which are heatmaps, None for the
+    /// others
+    fn site_levels(&self, colouring: SiteColouring) -> Option<Vec<f32>> {
+        match colouring {
+            SiteColouring::State | SiteColouring::Clusters => None,
+            SiteColouring::Activity => {
+                // activity of a site flipping every sweep
+                let saturated = 1.0 / (1.0 - 0.5f32.powf(1.0 / ACTIVITY_HALF_LIFE));
+                Some((0..self.state.len())
+                    .map(|site| (self.activity.get(site).copied().unwrap_or_default() / saturated).clamp(0.0, 1.0).sqrt())
+                    .collect())
+            },
+            SiteColouring::Energy => {
+                let energies: Vec<f32> = (0..self.state.len()).map(|site| self.local_energy(site)).collect();
+                let scale = energies.iter().fold(0.0f32, |scale, energy| scale.max(energy.abs())).max(f32::EPSILON);
+                Some(energies.iter().map(|energy| 0.5 * (1.0 + energy / scale)).collect())
+            },
+        }
+    }
+
+    /// Energy of a site's bonds, plaquettes and field, with the whole of each bond counted (so
+    /// the sum over the sites counts the bonds twice)
+    fn local_energy(&self, site: usize) -> f32 {
+        let model = self.settings.model;
+
+        if model.is_vector() {
+            let vector = self.vector_state[site];
+            let (pair, single) = self.vector_energy(site, vector);
+            return pair + single - self.field_at(site) * model.vector_field(vector);
+        }
+        if model != Model::Ising {
+            let value = self.discrete_state[site];
+            let (pair, single) = self.discrete_energy(site, value);
+            return pair + single - self.field_at(site) * model.field(value);
+        }
+
+        -(Into::<i32>::into(self.state[site]) as f32) * (self.neighbour_sum(site) + self.field_at(site))
+    }
+
+    /// One pixel per site, returns the pixels with the width and height
+    pub fn as_image_raw(&self, layer: usize, colouring: SiteColouring, palette: Palette) -> (Vec<u8>, usize, usize) {
+        let mut rgb = Vec::new();
+        let (width, height) = self.image_into(&mut rgb, layer, colouring, palette);
+        (rgb, width, height)
+    }
+
+    /// as_image_raw() into a buffer kept between frames, or straight into the pixels of a texture.
+    /// The buffer is cleared first. Returns the width and height.
+    pub fn image_into(&self, image: &mut impl ImageBuffer, layer: usize, colouring: SiteColouring, palette: Palette) -> (usize, usize) {
+        let (width, height) = (self.structure.image_width, self.structure.image_height);
+        let colours = self.site_colours(colouring);
+
+        image.reset(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                image.push(self.pixel(x, y, layer, colours.as_deref(), palette));
+            }
+        }
+
+        (width, height)
+    }
+
+    /// Blend each `block` × `block` square of an image of a layer from as_image_raw() towards a
+    /// grey for the mean spin of the sites in it, from black for all down to white for all up
+    /// (for vector models, from black for no net spin to white for all aligned). This is one step
+    /// of real space renormalisation: near Tc the blocks look like the lattice itself.
+    pub fn overlay_coarse_grained(&self, image: &mut impl ImageBuffer, layer: usize, block: usize) {
+        let (width, height) = (self.structure.image_width, self.structure.image_height);
+        let block = block.max(1);
+        let values = self.site_values();
+
+        for block_y in (0..height).step_by(block) {
+            for block_x in (0..width).step_by(block) {
+                let pixels = || (block_y..(block_y + block).min(height))
+                    .flat_map(move |y| (block_x..(block_x + block).min(width)).map(move |x| (x, y)));
+
+                let mut total = [0.0; 3];
+                let mut count = 0;
+                for (x, y) in pixels() {
+                    if let Pixel::Site(site) = self.structure.pixels[x + y * width + layer * width * height] {
+                        total = [0, 1, 2].map(|i| total[i] + values[site][i]);
+                        count += 1;
+                    }
+                }
+                if count == 0 {
+                    continue;
+                }
+
+                let mean = total.map(|component| component / count as f32);
+                let level = if self.settings.model.is_vector() {
+                    (mean[0] * mean[0] + mean[1] * mean[1] + mean[2] * mean[2]).sqrt()
+                } else {
+                    0.5 * (1.0 + mean[0])
+                };
+                let grey = 255.0 * level.clamp(0.0, 1.0);
+
+                for (x, y) in pixels() {
+                    let colour = image.get(x + y * width).map(|channel| (0.3 * channel as f32 + 0.7 * grey) as u8);
+                    image.set(x + y * width, colour);
+                }
+            }
+        }
+    }
+
+    /// Mark the sites of an image of a layer from as_image_raw() which flipped during the latest
+    /// sweep in bright green, if they're being tracked (see set_track_activity). At low
+    /// temperature these are the rare thermally activated flips, which undo themselves before they
+    /// show up in the spins.
+    pub fn overlay_last_flips(&self, image: &mut impl ImageBuffer, layer: usize) {
+        let (width, height) = (self.structure.image_width, self.structure.image_height);
+        let pixels = &self.structure.pixels[layer * width * height..(layer + 1) * width * height];
+
+        for (index, pixel) in pixels.iter().enumerate() {
+            if let Pixel::Site(site) = pixel {
+                if self.last_flips.get(*site).copied().unwrap_or_default() {
+                    image.set(index, [0, 255, 0]);
+                }
+            }
+        }
+    }
+
+    /// Spin of each site as a vector for correlations, the component along the field for discrete
+    /// models other than Ising
+    fn site_values(&self) -> Vec<[f32; 3]> {
+        let model = self.settings.model;
+
+        (0..self.state.len())
+            .map(|site| if model.is_vector() {
+                self.vector_state[site]
+            } else if model != Model::Ising {
+                [model.field(self.discrete_state[site]), 0.0, 0.0]
+            } else {
+                [Into::<i32>::into(self.state[site]) as f32, 0.0, 0.0]
+            })
+            .collect()
+    }
+
+    /// Structure factor S(k) = |Σ_j s_j exp(i k·x_j)|² / N at k = 0 and at the smallest non-zero
+    /// wavevector, 2π/L along x for an image L pixels wide, averaged over the axes as long as x.
+    /// Spins are as in correlation_function(). None for geometries without distances.
+    pub fn structure_factor(&self) -> Option<[f32; 2]> {
+        let dimensions = match self.settings.geometry {
+            Geometry::MeanField | Geometry::Graph => return None,
+            geometry => geometry.dimensions(),
+        };
+
+        let (width, height) = (self.structure.image_width, self.structure.image_height);
+        let depth = self.structure.layers / self.structure.slices;
+        let sides = [width, height, depth];
+        let axes: Vec<usize> = (0..dimensions).filter(|axis| sides[*axis] == width).collect();
+
+        let phases: Vec<(f32, f32)> = (0..width)
+            .map(|x| (std::f32::consts::TAU * x as f32 / width as f32).sin_cos())
+            .collect();
+
+        let values = self.site_values();
+        let mut total = [0.0f64; 3];
+        // real and imaginary parts of each component's Fourier amplitude, for each axis
+        let mut modes = vec![[[0.0f64; 2]; 3]; axes.len()];
+        for ((x, y, layer), value) in self.structure.positions.iter().zip(&values) {
+            let coordinates = [*x, *y, layer % depth];
+
+            for (total, component) in total.iter_mut().zip(value) {
+                *total += *component as f64;
+            }
+            for (mode, axis) in modes.iter_mut().zip(&axes) {
+                let (sin, cos) = phases[coordinates[*axis]];
+                for (amplitude, component) in mode.iter_mut().zip(value) {
+                    amplitude[0] += (component * cos) as f64;
+                    amplitude[1] += (component * sin) as f64;
+                }
+            }
+        }
+
+        let sites = values.len() as f64;
+        let zero = total.iter().map(|total| total * total).sum::<f64>() / sites;
+        let lowest = modes.iter()
+            .flat_map(|mode| mode.iter().map(|[real, imaginary]| real * real + imaginary * imaginary))
+            .sum::<f64>() / (sites * axes.len() as f64);
+
+        Some([zero as f32, lowest as f32])
+    }
+
+    /// Structure factor S(k) = |Σ_j s_j exp(-i k·x_j)|² / N of the sites of a layer, one pixel per
+    /// wavevector with k = 0 in the middle, on a log scale from black to white. Vacancies and
+    /// pixels without a site count as 0, spins are as in correlation_function(). The buffer is
+    /// cleared first. Returns the width and height.
+    pub fn structure_factor_image_into(&self, image: &mut impl ImageBuffer, layer: usize) -> (usize, usize) {
+        let (width, height) = (self.structure.image_width, self.structure.image_height);
+        let layer_pixels = &self.structure.pixels[layer * width * height..(layer + 1) * width * height];
+
+        let values = self.site_values();
+        let components = if self.settings.model.is_vector() { 3 } else { 1 };
+
+        // one component of the spins at each pixel
+        let channel = |component: usize| -> Vec<fft::Complex> {
+            layer_pixels.iter()
+                .map(|pixel| match pixel {
+                    Pixel::Site(site) => [values[*site][component] as f64, 0.0],
+                    Pixel::Vacancy | Pixel::Empty => [0.0; 2],
+                })
+                .collect()
+        };
+
+        let mut power = vec![0.0; width * height];
+        for mut amplitudes in (0..components).map(channel) {
+            fft::fft_2d(&mut amplitudes, width, height);
+
+            for (power, [real, imaginary]) in power.iter_mut().zip(amplitudes) {
+                *power += real * real + imaginary * imaginary;
+            }
+        }
+
+        let sites = layer_pixels.iter().filter(|pixel| matches!(pixel, Pixel::Site(_))).count().max(1) as f64;
+        let scale = (1.0 + power.iter().fold(0.0, |max: f64, power| max.max(*power)) / sites).ln().max(f64::MIN_POSITIVE);
+
+        image.reset(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                // k = 0 is at (width / 2, height / 2)
+                let (kx, ky) = ((x + width - width / 2) % width, (y + height - height / 2) % height);
+                let brightness = (1.0 + power[kx + ky * width] / sites).ln() / scale;
+                image.push([(255.0 * brightness) as u8; 3]);
+            }
+        }
+
+        (width, height)
+    }
+
+    /// Connected spin-spin correlation function C(r) = <s_i · s_j> - <s>² for sites r pixels
+    /// apart along each axis of the geometry, from r = 0 to at most `max_distance` and half way
+    /// round the shortest axis, averaged over the axes and every site. Summed directly, and only
+    /// along the axes, so it costs sites × distances × dimensions. Pixels are lattice spacings on
+    /// the chain, square and cubic lattices. Models other than Ising use the spin's component
+    /// along the field (all of it for vector spins). Empty for geometries without distances.
+    pub fn correlation_function(&self, max_distance: usize) -> Vec<f32> {
+        let dimensions = match self.settings.geometry {
+            Geometry::MeanField | Geometry::Graph => return Vec::new(),
+            geometry => geometry.dimensions(),
+        };
+
+        let (width, height) = (self.structure.image_width, self.structure.image_height);
+        // z layers of one Trotter slice, the slices themselves are imaginary time
+        let depth = self.structure.layers / self.structure.slices;
+        let sides = [width, height, depth];
+        let max_distance = sides[..dimensions].iter()
+            .map(|side| side / 2)
+            .fold(max_distance, usize::min);
+
+        let values = self.site_values();
+        let dot = |a: [f32; 3], b: [f32; 3]| (a[0] * b[0] + a[1] * b[1] + a[2] * b[2]) as f64;
+
+        let mut mean = [0.0; 3];
+        for value in &values {
+            for i in 0..3 {
+                mean[i] += value[i] / values.len() as f32;
+            }
+        }
+
+        let site_at = |x: usize, y: usize, layer: usize| match self.structure.pixels[x + y * width + layer * width * height] {
+            Pixel::Site(site) => Some(site),
+            Pixel::Vacancy | Pixel::Empty => None,
+        };
+
+        let mut sums = vec![0.0; max_distance + 1];
+        let mut counts = vec![0u64; max_distance + 1];
+        for (site, (x, y, layer)) in self.structure.positions.iter().enumerate() {
+            let (slice_layer, z) = (layer - layer % depth, layer % depth);
+
+            for r in 0..=max_distance {
+                let partners = [
+                    site_at((x + r) % width, *y, *layer),
+                    if dimensions >= 2 { site_at(*x, (y + r) % height, *layer) } else { None },
+                    if dimensions >= 3 { site_at(*x, *y, slice_layer + (z + r) % depth) } else { None },
+                ];
+
+                for partner in partners.into_iter().flatten() {
+                    sums[r] += dot(values[site], values[partner]);
+                    counts[r] += 1;
+                }
+            }
+        }
+
+        sums.iter().zip(&counts)
+            .map(|(sum, count)| if *count > 0 {
+                (sum / *count as f64 - dot(mean, mean)) as f32
+            } else {
+                0.0
+            })
+            .collect()
+    }
+
+    /// Sizes of the domains of the lattice, clusters of sites in the same state joined by bonds
+    /// (vector spins count as up or down, by their component along the field). Labelled with
+    /// union-find rather than Hoshen-Kopelman as sites needn't sit on a grid.
+    pub fn cluster_sizes(&self) -> Vec<usize> {
+        self.domains().set_sizes()
+    }
+
+    /// Sites joined into their domains, see cluster_sizes()
+    fn domains(&self) -> UnionFind {
+        let mut clusters = UnionFind::new(self.state.len());
+
+        for site in 0..self.state.len() {
+            for (neighbour, coupling) in self.structure.neighbours(site) {
+                if *neighbour > site && *coupling != 0.0 && self.same_state(site, *neighbour) {
+                    clusters.union(site, *neighbour);
+                }
+            }
+        }
+
+        clusters
+    }
+
+    /// Number of bonds with a non-zero coupling, the most domain wall there can be
+    pub fn bond_count(&self) -> usize {
+        (0..self.state.len())
+            .map(|site| self.structure.neighbours(site).iter()
+                .filter(|(neighbour, coupling)| *neighbour > site && *coupling != 0.0)
+                .count())
+            .sum()
+    }
+
+    /// Total length of the domain walls, the number of bonds between sites in different states
+    pub fn domain_wall_length(&self) -> usize {
+        (0..self.state.len())
+            .map(|site| self.structure.neighbours(site).iter()
+                .filter(|(neighbour, coupling)| *neighbour > site && *coupling != 0.0 && !self.same_state(site, *neighbour))
+                .count())
+            .sum()
+    }
+
+    /// Whether two sites are in the same state, as far as domains go
+    fn same_state(&self, a: usize, b: usize) -> bool {
+        if self.settings.model != Model::Ising && !self.settings.model.is_vector() {
+            self.discrete_state[a] == self.discrete_state[b]
+        } else {
+            matches!((self.state[a], self.state[b]), (Spin::Up, Spin::Up) | (Spin::Down, Spin::Down))
+        }
+    }
+
+    fn flip(&mut self, site: usize) {
+        let spin = Into::<i32>::into(self.state[site]);
+
+        self.exchange_energy_total += 2.0 * spin as f64 * self.neighbour_sum(site) as f64;
+        self.spin_total -= 2 * spin as i64;
+        if let Some(weights) = &self.field_weights {
+            self.field_total -= 2.0 * spin as f64 * weights[site] as f64;
+        }
+
+        self.state[site] = -self.state[site];
+    }
+}
+
+/// Exact spontaneous magnetisation per spin of the square Ising ferromagnet with couplings jx and
+/// jy at temperature t, (1 - (sinh(2Jx/T) sinh(2Jy/T))⁻²)^(1/8) below Tc (Onsager, Yang) and zero
+/// above. None unless both couplings are positive.
+pub fn spontaneous_magnetisation(jx: f32, jy: f32, t: f32) -> Option<f32> {
+    if jx <= 0.0 || jy <= 0.0 {
+        return None;
+    }
+
+    let product = (2.0 * jx as f64 / t as f64).sinh() * (2.0 * jy as f64 / t as f64).sinh();
+    Some((1.0 - product.powi(-2)).max(0.0).powf(0.125) as f32)
+}
+
+/// Exact critical temperature of the square Ising ferromagnet with couplings jx and jy, which
+/// solves sinh(2Jx/T) sinh(2Jy/T) = 1 (Onsager). None unless both couplings are positive.
+pub fn critical_temperature(jx: f32, jy: f32) -> Option<f32> {
+    if jx <= 0.0 || jy <= 0.0 {
+        return None;
+    }
+
+    // the left hand side falls monotonically with T, from infinity to zero
+    let f = |t: f64| (2.0 * jx as f64 / t).sinh() * (2.0 * jy as f64 / t).sinh() - 1.0;
+    let (mut low, mut high) = (1e-3, 2.0 * (jx + jy) as f64 / f64::ln(1.0 + f64::sqrt(2.0)));
+    for _ in 0..100 {
+        let middle = 0.5 * (low + high);
+        if f(middle) > 0.0 {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    Some((0.5 * (low + high)) as f32)
+}
+
+/// Magnetisation predicted by mean-field theory, the solution of m = tanh((Jm + B)/T) with the
+/// same sign as B (positive if B = 0).
+pub fn mean_field_magnetisation(coupling: f32, magnetic_field: f32, temperature: f32) -> f32 {
+    let sign = if magnetic_field < 0.0 { -1.0 } else { 1.0 };
+    if temperature <= 0.0 {
+        return sign;
+    }
+
+    // fixed point iteration from the saturated state converges on the stable solution, slowly
+    // near Tc
+    let mut m = sign as f64;
+    for _ in 0..10_000 {
+        let next = ((coupling as f64 * m + magnetic_field as f64) / temperature as f64).tanh();
+        if (next - m).abs() < 1e-9 {
+            break;
+        }
+        m = next;
+    }
+
+    m as f32
+}
+
+/// Blow up an RGB image `width` pixels wide by an integer factor, each pixel becoming a `scale` ×
+/// `scale` square
+pub fn scale_rgb(rgb: &[u8], width: usize, scale: usize) -> Vec<u8> {
+    let mut scaled = Vec::with_capacity(rgb.len() * scale * scale);
+
+    for row in rgb.chunks_exact(width * 3) {
+        for _ in 0..scale {
+            for pixel in row.chunks_exact(3) {
+                for _ in 0..scale {
+                    scaled.extend_from_slice(pixel);
+                }
+            }
+        }
+    }
+
+    scaled
+}
+
+/// Access used by the GPU backend, which keeps its own copy of the spins.
+#[cfg(feature = "gpu")]
+impl Lattice {
+    /// Side length of a square lattice
+    pub fn size(&self) -> usize {
+        self.settings.width
+    }
+
+    /// Coupling shared by every bond of a periodic square lattice with equal, even width and
+    /// height, if there is one. On an odd side the checkerboard wraps onto itself, so neighbours
+    /// would be updated in the same pass.
+    pub fn uniform_coupling(&self) -> Option<f32> {
+        if self.settings.model != Model::Ising
+            || self.settings.geometry != Geometry::Square
+            || self.settings.width != self.settings.height
+            || self.settings.width % 2 != 0
+            || self.settings.boundaries[..2] != [Boundary::Periodic; 2]
+            || self.settings.next_nearest_coupling != 0.0
+            || self.settings.axial_kappa != 0.0
+            || self.settings.plaquette_coupling != 0.0
+            || self.structure.slices != 1
+            || self.settings.long_range.is_some()
+            || self.settings.dipolar.is_some()
+            || self.settings.p_vacancy != 0.0
+            || self.settings.p_bond_dilution != 0.0
+            || self.settings.random_field.is_some()
+            || self.field_weights.is_some()
+            || self.temperature_gradient != 0.0
+            || self.structure.edited
+            || self.settings.coupling_strengths[..2] != [1.0; 2]
+        {
+            return None;
+        }
+
+        match self.settings.lattice_type {
+            LatticeType::Ferromagnetic => Some(self.settings.exchange),
+            LatticeType::Antiferromagnetic => Some(-self.settings.exchange),
+            LatticeType::SpinGlass { .. } | LatticeType::GaussianSpinGlass { .. } => None,
+        }
+    }
+
+    /// Next seed for the GPU's random number generator
+    pub fn gpu_seed(&mut self) -> u32 {
+        self.rng.gen()
+    }
+
+    pub fn spins(&self) -> &[Spin] {
+        &self.state
+    }
+
+    /// Overwrite the spins in place, keeping the running totals consistent
+    pub fn load_spins(&mut self, load: impl FnOnce(&mut [Spin])) {
+        load(&mut self.state);
+        self.recount();
+    }
+
+    /// Record Metropolis moves made outside the lattice
+    pub fn count_flips(&mut self, attempted: u64, accepted: u64) {
+        self.attempted_flips += attempted;
+        self.accepted_flips += accepted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(geometry: Geometry, boundary: Boundary, lattice_type: LatticeType) -> LatticeSettings {
+        LatticeSettings {
+            model: Model::Ising,
+            geometry,
+            width: 6,
+            height: 5,
+            depth: 4,
+            exchange: 1.0,
+            coupling_strengths: [1.0, 0.7, 1.3],
+            next_nearest_coupling: 0.0,
+            axial_kappa: 0.0,
+            plaquette_coupling: 0.0,
+            long_range: None,
+            dipolar: None,
+            boundaries: [boundary; 3],
+            lattice_type,
+            random_field: None,
+            p_vacancy: 0.0,
+            p_bond_dilution: 0.0,
+            initial_state: LatticeInitialState::Random,
+            seed: 7,
+            rng_algorithm: RngAlgorithm::Xoshiro256PlusPlus,
+            graph: None,
+            trotter_slices: 1,
+        }
+    }
+
+    fn assert_close(actual: f32, expected: f32, what: &str) {
+        let tolerance = 1e-3 * expected.abs().max(1.0);
+        assert!((actual - expected).abs() <= tolerance, "{what}: {actual} != {expected}");
+    }
+
+    /// Flip every site of an Ising lattice in turn, checking 2s(sum + B) against the difference
+    /// of the energies before and after, each recounted from scratch
+    fn check_flips(settings: &LatticeSettings) {
+        let mut lattice = Lattice::new(settings, 1.0, 0.3);
+        lattice.set_field_profile(&FieldProfile::Gradient { angle: 0.4 });
+
+        for site in 0..lattice.spin_count() {
+            let spin = Into::<i32>::into(lattice.state[site]) as f32;
+            let d_energy = 2.0 * spin * (lattice.neighbour_sum(site) + lattice.field_at(site));
+
+            let before = lattice.internal_energy();
+            lattice.flip_site(site);
+            let after = lattice.internal_energy();
+
+            assert_close(d_energy, after - before, &format!("{:?} site {site}", settings.geometry));
+        }
+    }
+
+    /// Run Metropolis updates hot enough that most are accepted, checking the running energy
+    /// against one recounted from scratch after each
+    fn check_steps(settings: &LatticeSettings) {
+        let mut lattice = Lattice::new(settings, 5.0, 0.3);
+        lattice.set_field_profile(&FieldProfile::Sinusoidal { wavelength: 3.0, angle: 0.2 });
+
+        for step in 0..4 * lattice.spin_count() {
+            lattice.step_at(step % lattice.spin_count());
+
+            let mut recounted = lattice.clone();
+            recounted.recount();
+            assert_close(lattice.internal_energy(), recounted.internal_energy(), &format!("{:?} step {step}", settings.model));
+        }
+        assert!(lattice.accepted_flips() > 0);
+    }
+
+    #[test]
+    fn flip_energy_matches_boundaries() {
+        for geometry in [Geometry::Square, Geometry::Kagome, Geometry::Cubic] {
+            for boundary in Boundary::ALL {
+                let settings = settings(geometry, boundary, LatticeType::Ferromagnetic);
+                check_flips(&settings);
+                check_steps(&settings);
+            }
+        }
+    }
+
+    #[test]
+    fn flip_energy_matches_spin_glass() {
+        for geometry in [Geometry::Square, Geometry::Kagome, Geometry::Cubic] {
+            for lattice_type in [LatticeType::SpinGlass { p_antiferro: 0.5 }, LatticeType::GaussianSpinGlass { sigma: 1.0 }] {
+                let settings = settings(geometry, Boundary::Periodic, lattice_type);
+                check_flips(&settings);
+                check_steps(&settings);
+            }
+        }
+    }
+
+    #[test]
+    fn flip_energy_matches_extra_terms() {
+        let square = settings(Geometry::Square, Boundary::Periodic, LatticeType::Ferromagnetic);
+        let cubic = settings(Geometry::Cubic, Boundary::Open, LatticeType::Antiferromagnetic);
+
+        for base in [square, cubic] {
+            let variants = [
+                LatticeSettings { next_nearest_coupling: -0.6, ..base.clone() },
+                LatticeSettings { axial_kappa: 0.5, ..base.clone() },
+                LatticeSettings { plaquette_coupling: 0.4, ..base.clone() },
+                LatticeSettings { random_field: Some(RandomField::Gaussian { width: 0.8 }), ..base.clone() },
+                LatticeSettings { random_field: Some(RandomField::Binary { strength: 0.5 }), ..base.clone() },
+                LatticeSettings { long_range: Some(LongRange { exponent: 2.5, cutoff: 2.5 }), ..base.clone() },
+                LatticeSettings { dipolar: Some(Dipolar { strength: 0.2, cutoff: 2.5 }), ..base.clone() },
+                LatticeSettings { p_vacancy: 0.2, p_bond_dilution: 0.2, ..base.clone() },
+                LatticeSettings { trotter_slices: 3, ..base.clone() },
+            ];
+
+            for settings in &variants {
+                check_flips(settings);
+                check_steps(settings);
+            }
+        }
+    }
+
+    #[test]
+    fn flip_energy_matches_mean_field() {
+        for lattice_type in [LatticeType::Ferromagnetic, LatticeType::SpinGlass { p_antiferro: 0.3 }] {
+            let settings = settings(Geometry::MeanField, Boundary::Periodic, lattice_type);
+            check_flips(&settings);
+            check_steps(&settings);
+        }
+    }
+
+    #[test]
+    fn discrete_step_energy_matches() {
+        for model in [Model::Potts { q: 3 }, Model::Clock { q: 6 }, Model::BlumeCapel { crystal_field: 0.5 }] {
+            for geometry in [Geometry::Square, Geometry::Cubic, Geometry::MeanField] {
+                for boundary in [Boundary::Periodic, Boundary::FixedMixed] {
+                    check_steps(&LatticeSettings { model, ..settings(geometry, boundary, LatticeType::GaussianSpinGlass { sigma: 1.0 }) });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn vector_step_energy_matches() {
+        for model in [Model::XY, Model::Heisenberg] {
+            for geometry in [Geometry::Square, Geometry::Kagome, Geometry::MeanField] {
+                for boundary in [Boundary::AntiPeriodic, Boundary::FixedUp] {
+                    check_steps(&LatticeSettings { model, ..settings(geometry, boundary, LatticeType::SpinGlass { p_antiferro: 0.5 }) });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn drawn_bond_strengths_match_generated() {
+        for geometry in [Geometry::Chain, Geometry::Square, Geometry::Kagome, Geometry::Cubic] {
+            let settings = LatticeSettings { exchange: 2.0, ..settings(geometry, Boundary::Open, LatticeType::Ferromagnetic) };
+            let lattice = Lattice::new(&settings, 1.0, 0.0);
+
+            for bond in lattice.drawn_bonds(0) {
+                assert_close(bond.strength, bond.coupling, &format!("{geometry:?} bond {} {}", bond.a, bond.b));
+            }
+        }
     }
 }
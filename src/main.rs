@@ -2,13 +2,31 @@
 
 use app::IsingApp;
 
+mod annotate;
 mod app;
+mod couplings;
+mod fft;
+mod fit;
+#[cfg(feature = "gpu")]
+mod gpu;
 mod spin;
 mod lattice;
+mod geometry;
+mod graph;
+mod measurement;
+mod model;
+mod multispin;
+mod rng;
+mod sweep;
+mod union_find;
+mod video;
+mod worker;
 
 fn main() -> Result<(), eframe::Error> {
     let native_options = eframe::NativeOptions {
         min_window_size: Some(egui::vec2(550.0, 275.0)),
+        #[cfg(feature = "gpu")]
+        renderer: eframe::Renderer::Wgpu,
         ..Default::default()
     };
     eframe::run_native(
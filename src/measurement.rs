@@ -0,0 +1,535 @@
+use std::{collections::VecDeque, fmt::Display};
+
+use rand::Rng;
+
+use crate::rng::{LatticeRng, RngAlgorithm};
+
+/// Most blocks kept before neighbouring ones are merged
+const MAX_BLOCKS: usize = 64;
+/// Fewest complete blocks an error bar is estimated from
+const MIN_BLOCKS: usize = 16;
+/// Resampled windows a bootstrap error bar is estimated from
+const BOOTSTRAP_RESAMPLES: usize = 100;
+
+// observables accumulated in blocks by Measurements
+const ENERGY: usize = 0;
+const MAGNETISATION: usize = 1;
+const ABS_MAGNETISATION: usize = 2;
+const MAGNETISATION_SQUARED: usize = 3;
+const MAGNETISATION_FOURTH: usize = 4;
+const STRUCTURE_FACTOR: [usize; 2] = [5, 6];
+const OBSERVABLES: usize = 7;
+
+/// Observables of the lattice after one sweep.
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    /// Incremented every time a parameter which changes the equilibrium state (temperature,
+    /// field, the lattice itself) changes
+    pub generation: u64,
+    /// Sweeps since the parameters last changed
+    pub sweeps_since_change: u64,
+    /// Energy per spin
+    pub energy: f32,
+    pub magnetisation: f32,
+    /// Structure factor at k = 0 and the smallest non-zero wavevector, see
+    /// `Lattice::structure_factor`
+    pub structure_factor: Option<[f32; 2]>,
+    /// Bonds between sites in different states, see `Lattice::domain_wall_length`
+    pub domain_wall_length: usize,
+    /// Overlap with the replica, if one is running, see `Lattice::overlap`
+    pub overlap: Option<f32>,
+}
+
+/// Running averages over the samples of the current parameters, leaving out the first
+/// `burn_in` sweeps while the lattice relaxes from wherever it started.
+pub struct Measurements {
+    /// Thermalisation period, in sweeps
+    pub burn_in: u64,
+    /// Samples in each of the two windows compared by the equilibration detector
+    pub equilibration_window: usize,
+    /// Latest samples the heat capacity and susceptibility are estimated from
+    pub fluctuation_window: usize,
+    generation: u64,
+    // latest sweeps_since_change seen
+    sweeps_since_change: u64,
+    // sweeps between the latest two samples of the generation
+    sample_interval: u64,
+    // e, m, |m|, m², m⁴ and the structure factor of every sample counted, 0 for samples
+    // without a structure factor
+    blocks: Blocks<OBSERVABLES>,
+    structure_factor_count: u64,
+    // energy and magnetisation of the last fluctuation_window samples counted
+    recent_samples: VecDeque<(f32, f32)>,
+    // last 2 * equilibration_window energies of this generation
+    recent_energies: VecDeque<f32>,
+    equilibrated_at: Option<u64>,
+}
+
+impl Measurements {
+    pub fn new(burn_in: u64, equilibration_window: usize, fluctuation_window: usize) -> Measurements {
+        Measurements {
+            burn_in,
+            equilibration_window,
+            fluctuation_window,
+            generation: 0,
+            sweeps_since_change: 0,
+            sample_interval: 1,
+            blocks: Blocks::new(),
+            structure_factor_count: 0,
+            recent_samples: VecDeque::new(),
+            recent_energies: VecDeque::new(),
+            equilibrated_at: None,
+        }
+    }
+
+    pub fn record(&mut self, sample: &Sample) {
+        if sample.generation != self.generation {
+            self.generation = sample.generation;
+            self.clear();
+            self.recent_energies.clear();
+            self.equilibrated_at = None;
+            self.sweeps_since_change = 0;
+        }
+
+        if sample.sweeps_since_change > self.sweeps_since_change {
+            self.sample_interval = sample.sweeps_since_change - self.sweeps_since_change;
+        }
+        self.sweeps_since_change = sample.sweeps_since_change;
+        self.detect_equilibration(sample.energy);
+
+        if sample.sweeps_since_change < self.burn_in {
+            return;
+        }
+
+        let magnetisation = sample.magnetisation as f64;
+        let mut values = [0.0; OBSERVABLES];
+        values[ENERGY] = sample.energy as f64;
+        values[MAGNETISATION] = magnetisation;
+        values[ABS_MAGNETISATION] = magnetisation.abs();
+        values[MAGNETISATION_SQUARED] = magnetisation.powi(2);
+        values[MAGNETISATION_FOURTH] = magnetisation.powi(4);
+        if let Some(structure_factor) = sample.structure_factor {
+            for (index, value) in STRUCTURE_FACTOR.into_iter().zip(structure_factor) {
+                values[index] = value as f64;
+            }
+            self.structure_factor_count += 1;
+        }
+        self.blocks.add(values);
+
+        self.recent_samples.push_back((sample.energy, sample.magnetisation));
+        while self.recent_samples.len() > self.fluctuation_window.max(2) {
+            self.recent_samples.pop_front();
+        }
+    }
+
+    /// Throw away the accumulated samples
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.structure_factor_count = 0;
+        self.recent_samples.clear();
+    }
+
+    /// Generation of the latest sample, see Sample
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Energy per spin and magnetisation of each sample of the fluctuation window, oldest first
+    pub fn fluctuation_samples(&self) -> Vec<(f32, f32)> {
+        self.recent_samples.iter().copied().collect()
+    }
+
+    /// Approximate bytes held by the samples kept
+    pub fn memory_usage(&self) -> usize {
+        self.recent_samples.len() * std::mem::size_of::<(f32, f32)>()
+            + self.recent_energies.len() * std::mem::size_of::<f32>()
+            + self.blocks.complete.len() * std::mem::size_of::<[f64; OBSERVABLES]>()
+    }
+
+    /// Whether samples are still being excluded
+    pub fn thermalising(&self) -> bool {
+        self.sweeps_since_change < self.burn_in
+    }
+
+    /// Sweeps left until samples are recorded
+    pub fn burn_in_remaining(&self) -> u64 {
+        self.burn_in.saturating_sub(self.sweeps_since_change)
+    }
+
+    /// Number of samples in the averages
+    pub fn count(&self) -> u64 {
+        self.blocks.count()
+    }
+
+    /// Mean energy per spin
+    pub fn mean_energy(&self) -> Option<Estimate> {
+        self.blocks.estimate(|means| Some(means[ENERGY]))
+    }
+
+    /// Mean absolute magnetisation
+    pub fn mean_abs_magnetisation(&self) -> Option<Estimate> {
+        self.blocks.estimate(|means| Some(means[ABS_MAGNETISATION]))
+    }
+
+    /// Mean fraction of occupied sites (1 + m) / 2 in the lattice-gas reading
+    pub fn mean_density(&self) -> Option<Estimate> {
+        self.blocks.estimate(|means| Some(0.5 * (1.0 + means[MAGNETISATION])))
+    }
+
+    /// Mean square magnetisation <m²>
+    pub fn mean_magnetisation_squared(&self) -> Option<Estimate> {
+        self.blocks.estimate(|means| Some(means[MAGNETISATION_SQUARED]))
+    }
+
+    /// Mean fourth power of the magnetisation <m⁴>
+    pub fn mean_magnetisation_fourth(&self) -> Option<Estimate> {
+        self.blocks.estimate(|means| Some(means[MAGNETISATION_FOURTH]))
+    }
+
+    /// Kurtosis <m⁴> / <m²>² of the magnetisation, 3 for the Gaussian fluctuations well above Tc
+    /// and 1 in an ordered phase
+    pub fn magnetisation_kurtosis(&self) -> Option<Estimate> {
+        self.blocks.estimate(|means| {
+            let squared = means[MAGNETISATION_SQUARED];
+            (squared > 0.0).then(|| means[MAGNETISATION_FOURTH] / (squared * squared))
+        })
+    }
+
+    /// Binder cumulant U4 = 1 - <m⁴> / (3 <m²>²), which tends to 2/3 in an ordered phase and 0 in
+    /// a disordered one. Curves for different sizes cross at Tc.
+    pub fn binder_cumulant(&self) -> Option<Estimate> {
+        self.blocks.estimate(|means| {
+            let squared = means[MAGNETISATION_SQUARED];
+            (squared > 0.0).then(|| 1.0 - means[MAGNETISATION_FOURTH] / (3.0 * squared * squared))
+        })
+    }
+
+    /// Second-moment correlation length ξ = sqrt(S(0) / S(k) - 1) / (2 sin(k / 2)) of a lattice
+    /// `side` lattice spacings across, with k = 2π / side the smallest wavevector the structure
+    /// factor is sampled at. Only meaningful above Tc, below it S(0) includes the magnetisation.
+    pub fn correlation_length(&self, side: usize) -> Option<Estimate> {
+        if self.structure_factor_count == 0 || side < 2 {
+            return None;
+        }
+
+        let k = std::f64::consts::TAU / side as f64;
+
+        self.blocks.estimate(|means| {
+            let [zero, lowest] = STRUCTURE_FACTOR.map(|index| means[index]);
+            (lowest > 0.0).then(|| (zero / lowest - 1.0).max(0.0).sqrt() / (2.0 * (0.5 * k).sin()))
+        })
+    }
+
+    /// Heat capacity per spin C = N (<e²> - <e>²) / T² of a lattice of `sites` spins at
+    /// temperature `temperature`, from the fluctuations of the energy per spin e over the
+    /// fluctuation window, with a bootstrap error bar
+    pub fn heat_capacity(&self, sites: usize, temperature: f32) -> Option<Estimate> {
+        let scale = sites as f64 / (temperature as f64).powi(2);
+
+        self.bootstrap(|samples| Some(scale * variance(samples, |(energy, _)| energy)?))
+    }
+
+    /// Magnetic susceptibility per spin χ = N (<m²> - <m>²) / T, as heat_capacity()
+    pub fn susceptibility(&self, sites: usize, temperature: f32) -> Option<Estimate> {
+        let scale = sites as f64 / temperature as f64;
+
+        self.bootstrap(|samples| Some(scale * variance(samples, |(_, magnetisation)| magnetisation)?))
+    }
+
+    /// Some statistic of the fluctuation window, with an error bar from a moving block
+    /// bootstrap: the window is rebuilt many times from blocks of about 2τ samples starting at
+    /// random, so correlated samples stay together, and the spread of the statistic over the
+    /// rebuilt windows gives the error. The random numbers are the same every time, so the
+    /// error bar doesn't flicker while the window is unchanged.
+    fn bootstrap(&self, statistic: impl Fn(&[(f32, f32)]) -> Option<f64>) -> Option<Estimate> {
+        let samples: Vec<(f32, f32)> = self.recent_samples.iter().copied().collect();
+        let value = statistic(&samples)? as f32;
+
+        let count = samples.len();
+        let block_length = self.autocorrelation_time().map_or(1, |tau| (2.0 * tau).ceil() as usize);
+        if count < 4 * block_length {
+            return Some(Estimate { value, error: None });
+        }
+
+        let mut rng = LatticeRng::new(RngAlgorithm::Xoshiro256PlusPlus, 0);
+        let mut resampled = Vec::with_capacity(count);
+        let mut values = Vec::with_capacity(BOOTSTRAP_RESAMPLES);
+        for _ in 0..BOOTSTRAP_RESAMPLES {
+            resampled.clear();
+            while resampled.len() < count {
+                let start = rng.gen_range(0..=count - block_length);
+                resampled.extend_from_slice(&samples[start..start + block_length]);
+            }
+            resampled.truncate(count);
+
+            if let Some(value) = statistic(&resampled) {
+                values.push(value);
+            }
+        }
+
+        let error = (values.len() >= 2).then(|| {
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let spread = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>();
+            (spread / (values.len() - 1) as f64).sqrt() as f32
+        });
+
+        Some(Estimate { value, error })
+    }
+
+    /// Integrated autocorrelation time τ = 1/2 + Σ_t ρ(t) of the magnetisation over the
+    /// fluctuation window, in samples, where ρ(t) is the autocorrelation at lag t. The sum stops
+    /// at the first lag past 6τ (Sokal's windowing), beyond which ρ is mostly noise. None if the
+    /// window is too short to tell.
+    pub fn autocorrelation_time(&self) -> Option<f32> {
+        self.integrated_autocorrelation().map(|(tau, _)| tau as f32)
+    }
+
+    /// autocorrelation_time() in sweeps rather than samples, with Sokal's error estimate
+    /// τ sqrt(2 (2W + 1) / n) for a window of W lags over n samples
+    pub fn autocorrelation_sweeps(&self) -> Option<Estimate> {
+        let (tau, window) = self.integrated_autocorrelation()?;
+        let error = tau * (2.0 * (2 * window + 1) as f64 / self.recent_samples.len() as f64).sqrt();
+        let interval = self.sample_interval as f64;
+
+        Some(Estimate { value: (tau * interval) as f32, error: Some((error * interval) as f32) })
+    }
+
+    /// τ and the lag the sum stopped at, see autocorrelation_time()
+    fn integrated_autocorrelation(&self) -> Option<(f64, usize)> {
+        let samples: Vec<(f32, f32)> = self.recent_samples.iter().copied().collect();
+        let series: Vec<f64> = samples.iter().map(|(_, magnetisation)| *magnetisation as f64).collect();
+        let variance = variance(&samples, |(_, magnetisation)| magnetisation)?;
+        if variance <= 0.0 {
+            return None;
+        }
+
+        let count = series.len();
+        let mean = series.iter().sum::<f64>() / count as f64;
+        let autocorrelation = |lag: usize| {
+            let covariance = series.iter().zip(&series[lag..])
+                .map(|(a, b)| (a - mean) * (b - mean))
+                .sum::<f64>() / (count - lag) as f64;
+            covariance / variance
+        };
+
+        let mut tau = 0.5;
+        for lag in 1..count / 2 {
+            tau += autocorrelation(lag);
+            if lag as f64 >= 6.0 * tau {
+                return Some((tau.max(0.5), lag));
+            }
+        }
+
+        None
+    }
+
+    /// Number of independent samples the averages are worth, count() / 2τ. None until τ is known.
+    pub fn effective_sample_count(&self) -> Option<u64> {
+        let tau = self.autocorrelation_time()?;
+        Some((self.count() as f64 / (2.0 * tau as f64)) as u64)
+    }
+
+    /// Number of independent samples in the fluctuation window, as effective_sample_count()
+    pub fn effective_window_count(&self) -> Option<u64> {
+        let tau = self.autocorrelation_time()?;
+        Some((self.recent_samples.len() as f64 / (2.0 * tau as f64)) as u64)
+    }
+
+    /// Sweeps since the last parameter change at which the energy stopped drifting, if it has
+    pub fn equilibrated_at(&self) -> Option<u64> {
+        self.equilibrated_at
+    }
+
+    /// Compares the mean energy of the last two windows of samples: once they agree to within
+    /// two standard errors the lattice is considered equilibrated (until the parameters change).
+    fn detect_equilibration(&mut self, energy: f32) {
+        let window = self.equilibration_window.max(2);
+
+        self.recent_energies.push_back(energy);
+        while self.recent_energies.len() > 2 * window {
+            self.recent_energies.pop_front();
+        }
+
+        if self.equilibrated_at.is_some() || self.recent_energies.len() < 2 * window {
+            return;
+        }
+
+        let (older, newer) = self.recent_energies.as_slices();
+        let energies: Vec<f64> = older.iter().chain(newer).map(|energy| *energy as f64).collect();
+        let (first, second) = energies.split_at(window);
+
+        let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+        let variance = |values: &[f64], mean: f64| {
+            values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+        };
+
+        let (first_mean, second_mean) = (mean(first), mean(second));
+        let standard_error = ((variance(first, first_mean) + variance(second, second_mean)) / window as f64).sqrt();
+
+        // successive samples are correlated, so this errs on the side of waiting longer
+        if (first_mean - second_mean).abs() <= 2.0 * standard_error {
+            self.equilibrated_at = Some(self.sweeps_since_change);
+        }
+    }
+}
+
+/// Mean of a measurement with its standard error, if there are enough samples to tell.
+/// Formats as "value ± error", with the precision given applying to both.
+#[derive(Debug, Clone, Copy)]
+pub struct Estimate {
+    pub value: f32,
+    pub error: Option<f32>,
+}
+
+impl Display for Estimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let precision = f.precision().unwrap_or(4);
+
+        match self.error {
+            Some(error) => write!(f, "{:.*} ± {:.*}", precision, self.value, precision, error),
+            None => write!(f, "{:.*}", precision, self.value),
+        }
+    }
+}
+
+/// Sums of N observables over blocks of consecutive samples. Successive samples are correlated,
+/// but once blocks are much longer than the autocorrelation time their means are independent, so
+/// the spread between blocks gives honest error bars. Whenever MAX_BLOCKS blocks are complete,
+/// neighbours are merged and the block length doubles, so a long run takes no more memory.
+struct Blocks<const N: usize> {
+    block_length: u64,
+    // sums over each complete block, then over the block being filled
+    complete: Vec<[f64; N]>,
+    partial: [f64; N],
+    partial_count: u64,
+}
+
+impl<const N: usize> Blocks<N> {
+    fn new() -> Blocks<N> {
+        Blocks {
+            block_length: 1,
+            complete: Vec::new(),
+            partial: [0.0; N],
+            partial_count: 0,
+        }
+    }
+
+    fn add(&mut self, values: [f64; N]) {
+        self.partial = sum(self.partial, values);
+        self.partial_count += 1;
+
+        if self.partial_count < self.block_length {
+            return;
+        }
+
+        self.complete.push(std::mem::replace(&mut self.partial, [0.0; N]));
+        self.partial_count = 0;
+
+        if self.complete.len() == MAX_BLOCKS {
+            self.complete = self.complete.chunks_exact(2).map(|pair| sum(pair[0], pair[1])).collect();
+            self.block_length *= 2;
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = Blocks::new();
+    }
+
+    /// Samples added since the blocks were last cleared
+    fn count(&self) -> u64 {
+        self.complete.len() as u64 * self.block_length + self.partial_count
+    }
+
+    /// Some function of the means of the observables over every sample, with a jackknife error
+    /// bar: the function is evaluated on the means leaving out each complete block in turn, and
+    /// the spread of those values gives the error. For a plain mean this is the usual error from
+    /// the spread of the block means, but unlike that it also works for ratios and the like.
+    fn estimate(&self, function: impl Fn([f64; N]) -> Option<f64>) -> Option<Estimate> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+
+        let total = self.complete.iter().fold([0.0; N], |total, block| sum(total, *block));
+        let value = function(sum(total, self.partial).map(|sum| sum / count as f64))?;
+
+        let blocks = self.complete.len();
+        let error = if blocks >= MIN_BLOCKS {
+            let length = ((blocks - 1) as u64 * self.block_length) as f64;
+            let values: Option<Vec<f64>> = self.complete.iter()
+                .map(|block| function(std::array::from_fn(|index| (total[index] - block[index]) / length)))
+                .collect();
+
+            values.map(|values| {
+                let mean = values.iter().sum::<f64>() / blocks as f64;
+                let spread = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>();
+                ((blocks - 1) as f64 / blocks as f64 * spread).sqrt() as f32
+            })
+        } else {
+            None
+        };
+
+        Some(Estimate { value: value as f32, error })
+    }
+}
+
+/// Variance of one observable over some (energy, magnetisation) samples, None unless there are at
+/// least two
+pub fn variance(samples: &[(f32, f32)], observable: impl Fn((f32, f32)) -> f32) -> Option<f64> {
+    if samples.len() < 2 {
+        return None;
+    }
+
+    let count = samples.len() as f64;
+    let values = samples.iter().map(|sample| observable(*sample) as f64);
+    let mean = values.clone().sum::<f64>() / count;
+
+    Some(values.map(|value| (value - mean).powi(2)).sum::<f64>() / count)
+}
+
+fn sum<const N: usize>(a: [f64; N], b: [f64; N]) -> [f64; N] {
+    std::array::from_fn(|index| a[index] + b[index])
+}
+
+/// Counts of values falling in equal bins between `min` and `max`, values outside go in the
+/// first or last bin.
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    counts: Vec<u64>,
+}
+
+impl Histogram {
+    pub fn new(min: f32, max: f32, bins: usize) -> Histogram {
+        Histogram {
+            min,
+            max,
+            counts: vec![0; bins.max(1)],
+        }
+    }
+
+    pub fn add(&mut self, value: f32) {
+        let bins = self.counts.len();
+        let bin = ((value - self.min) / (self.max - self.min) * bins as f32).floor();
+        self.counts[(bin.max(0.0) as usize).min(bins - 1)] += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.fill(0);
+    }
+
+    /// Values added since the histogram was last cleared
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Centre and count of each bin
+    pub fn bins(&self) -> impl Iterator<Item = (f32, u64)> + '_ {
+        let width = self.bin_width();
+        self.counts.iter().enumerate().map(move |(bin, count)| (self.min + (bin as f32 + 0.5) * width, *count))
+    }
+
+    pub fn bin_width(&self) -> f32 {
+        (self.max - self.min) / self.counts.len() as f32
+    }
+}
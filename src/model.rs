@@ -0,0 +1,276 @@
+use std::fmt::Display;
+
+use rand::Rng;
+
+use crate::spin::Spin;
+
+/// Colours of the states of a discrete model, the first two matching up and down spins
+const PALETTE: [[u8; 3]; 10] = [
+    [0, 0, 255],
+    [255, 0, 0],
+    [0, 170, 0],
+    [255, 200, 0],
+    [160, 0, 200],
+    [0, 200, 200],
+    [255, 120, 0],
+    [255, 0, 160],
+    [120, 80, 40],
+    [130, 170, 255],
+];
+
+/// What sits on each site and how neighbours interact.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Model {
+    /// Spins up or down, H = -Σ J s_i s_j - B Σ s_i
+    Ising,
+    /// One of q states, H = -Σ J δ(σ_i, σ_j) - B Σ δ(σ_i, 0). In two dimensions the transition
+    /// is continuous up to q = 4 and first order above.
+    Potts { q: usize },
+    /// Spins pointing at one of q evenly spaced angles θ_k = 2πk/q in the plane, with
+    /// H = -Σ J cos(θ_i - θ_j) - B Σ cos θ_i. Ising for q = 2, tending to XY as q grows.
+    Clock { q: usize },
+    /// Unit spins at any angle in the plane, H = -Σ J s_i · s_j - B Σ s_i · x. In two dimensions
+    /// there is no ordered phase, instead vortex-antivortex pairs unbind at the
+    /// Kosterlitz-Thouless transition.
+    XY,
+    /// Unit spins pointing anywhere in three dimensions, H = -Σ J s_i · s_j - B Σ s_i · z
+    Heisenberg,
+    /// Spin 1, s = +1, -1 or 0 (states 0, 1 and 2), with H = -Σ J s_i s_j + Δ Σ s_i² - B Σ s_i.
+    /// A large crystal field Δ fills the lattice with zeros, and the transition turns first order
+    /// at a tricritical point.
+    BlumeCapel { crystal_field: f32 },
+}
+
+impl Model {
+    /// Most states of a Potts model, one per colour of the palette
+    pub const MAX_STATES: usize = PALETTE.len();
+    /// Most angles of a clock model
+    pub const MAX_ANGLES: usize = 64;
+    /// Largest change of angle proposed for an XY spin, in radians
+    const XY_STEP: f32 = 1.0;
+
+    /// Whether sites hold vectors rather than one of a few states
+    pub fn is_vector(&self) -> bool {
+        matches!(self, Model::XY | Model::Heisenberg)
+    }
+
+    /// Number of states of a site, 0 for vector spins
+    pub fn state_count(&self) -> usize {
+        match self {
+            Model::Ising => 2,
+            Model::Potts { q } | Model::Clock { q } => *q,
+            Model::XY | Model::Heisenberg => 0,
+            Model::BlumeCapel { .. } => 3,
+        }
+    }
+
+    /// State an "all down" lattice starts in, the one most opposed to state 0
+    pub fn down_state(&self) -> u8 {
+        match self {
+            Model::Ising | Model::Potts { .. } | Model::XY | Model::Heisenberg | Model::BlumeCapel { .. } => 1,
+            Model::Clock { q } => (q / 2) as u8,
+        }
+    }
+
+    /// Spin drawn and counted by code which only knows about Ising spins, up if state a is
+    /// favoured by a positive field
+    pub fn spin(&self, a: u8) -> Spin {
+        if self.field(a) > 0.0 { Spin::Up } else { Spin::Down }
+    }
+
+    /// As spin(), for a vector spin
+    pub fn vector_spin(&self, vector: [f32; 3]) -> Spin {
+        if self.vector_field(vector) > 0.0 { Spin::Up } else { Spin::Down }
+    }
+
+    /// Spin of state a of the Blume-Capel model
+    fn spin_one(a: u8) -> f32 {
+        [1.0, -1.0, 0.0][a as usize]
+    }
+
+    /// Angle of state a of a clock model, in radians
+    fn angle(&self, a: u8) -> f32 {
+        std::f32::consts::TAU * a as f32 / self.state_count() as f32
+    }
+
+    /// A bond with coupling J between sites in states a and b has energy -J pair(a, b)
+    pub fn pair(&self, a: u8, b: u8) -> f32 {
+        match self {
+            Model::Ising => if a == b { 1.0 } else { -1.0 },
+            Model::Potts { .. } => if a == b { 1.0 } else { 0.0 },
+            Model::Clock { .. } => (self.angle(a) - self.angle(b)).cos(),
+            Model::BlumeCapel { .. } => Self::spin_one(a) * Self::spin_one(b),
+            Model::XY | Model::Heisenberg => 0.0,
+        }
+    }
+
+    /// A site in state a has energy -h field(a) in a field h. State 0 is the one favoured by a
+    /// positive field, like an up spin.
+    pub fn field(&self, a: u8) -> f32 {
+        match self {
+            Model::Ising => if a == 0 { 1.0 } else { -1.0 },
+            Model::Potts { .. } => if a == 0 { 1.0 } else { 0.0 },
+            Model::Clock { .. } => self.angle(a).cos(),
+            Model::BlumeCapel { .. } => Self::spin_one(a),
+            Model::XY | Model::Heisenberg => 0.0,
+        }
+    }
+
+    /// Energy of a site in state a which doesn't depend on its neighbours or the field
+    pub fn onsite(&self, a: u8) -> f32 {
+        match self {
+            Model::BlumeCapel { crystal_field } => crystal_field * Self::spin_one(a).powi(2),
+            _ => 0.0,
+        }
+    }
+
+    /// Order parameter given the number of sites in each state, 0 with every state equally
+    /// common and 1 with every site in the same state. Signed for the Blume-Capel model.
+    pub fn order_parameter(&self, counts: &[i64]) -> f32 {
+        let sites = counts.iter().sum::<i64>().max(1) as f32;
+
+        match self {
+            Model::Ising | Model::Potts { .. } => {
+                let q = self.state_count() as f32;
+                let most = counts.iter().copied().max().unwrap_or_default();
+
+                (q * most as f32 / sites - 1.0) / (q - 1.0)
+            },
+            // length of the mean spin vector
+            Model::Clock { .. } => {
+                let (x, y) = counts.iter().enumerate()
+                    .map(|(a, count)| {
+                        let (sin, cos) = self.angle(a as u8).sin_cos();
+                        (*count as f32 * cos, *count as f32 * sin)
+                    })
+                    .fold((0.0, 0.0), |(x, y), (dx, dy)| (x + dx, y + dy));
+
+                x.hypot(y) / sites
+            },
+            // magnetisation, like the Ising model
+            Model::BlumeCapel { .. } => (counts[0] - counts[1]) as f32 / sites,
+            Model::XY | Model::Heisenberg => 0.0,
+        }
+    }
+
+    pub fn colour(&self, a: u8) -> [u8; 3] {
+        match self {
+            Model::Ising | Model::Potts { .. } | Model::BlumeCapel { .. } => PALETTE[a as usize % PALETTE.len()],
+            Model::Clock { q } => hue(a as f32 / *q as f32),
+            Model::XY | Model::Heisenberg => [0, 0, 0],
+        }
+    }
+
+    /// Vector spin along the field, or against it
+    pub fn up_vector(&self, up: bool) -> [f32; 3] {
+        let sign = if up { 1.0 } else { -1.0 };
+
+        match self {
+            Model::Heisenberg => [0.0, 0.0, sign],
+            _ => [sign, 0.0, 0.0],
+        }
+    }
+
+    /// Vector spin pointing in a uniformly random direction
+    pub fn random_vector(&self, rng: &mut impl Rng) -> [f32; 3] {
+        let (sin, cos) = rng.gen_range(0.0..std::f32::consts::TAU).sin_cos();
+
+        match self {
+            // uniform in z is uniform on the sphere (Archimedes)
+            Model::Heisenberg => {
+                let z: f32 = rng.gen_range(-1.0..=1.0);
+                let r = (1.0 - z * z).sqrt();
+                [r * cos, r * sin, z]
+            },
+            _ => [cos, sin, 0.0],
+        }
+    }
+
+    /// Vector spin to propose as a Metropolis move from `vector`. XY spins turn by a random
+    /// angle, Heisenberg spins jump to a random point on the sphere.
+    pub fn propose_vector(&self, vector: [f32; 3], rng: &mut impl Rng) -> [f32; 3] {
+        match self {
+            Model::Heisenberg => self.random_vector(rng),
+            _ => {
+                let angle = vector[1].atan2(vector[0]) + rng.gen_range(-Self::XY_STEP..Self::XY_STEP);
+                let (sin, cos) = angle.sin_cos();
+                [cos, sin, 0.0]
+            },
+        }
+    }
+
+    /// Component of a vector spin along the field
+    pub fn vector_field(&self, vector: [f32; 3]) -> f32 {
+        match self {
+            Model::Heisenberg => vector[2],
+            _ => vector[0],
+        }
+    }
+
+    /// XY spins are coloured by angle, Heisenberg spins by z from blue (up) through white to red
+    pub fn vector_colour(&self, vector: [f32; 3]) -> [u8; 3] {
+        match self {
+            Model::Heisenberg => {
+                let z = vector[2].clamp(-1.0, 1.0);
+                let fade = |amount: f32| (255.0 * (1.0 - amount)) as u8;
+                if z >= 0.0 { [fade(z), fade(z), 255] } else { [255, fade(-z), fade(-z)] }
+            },
+            _ => hue(vector[1].atan2(vector[0]) / std::f32::consts::TAU),
+        }
+    }
+}
+
+impl Display for Model {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Model::Ising => f.write_str("Ising"),
+            Model::Potts { q } => write!(f, "{}-state Potts", q),
+            Model::Clock { q } => write!(f, "{}-state Clock", q),
+            Model::XY => f.write_str("XY"),
+            Model::Heisenberg => f.write_str("Heisenberg"),
+            Model::BlumeCapel { crystal_field } => write!(f, "Blume-Capel (Δ = {})", crystal_field),
+        }
+    }
+}
+
+/// Fully saturated colour with the given hue, as a fraction of a turn starting at blue (so
+/// opposite angles are blue and yellow)
+pub fn hue(turns: f32) -> [u8; 3] {
+    // hue measured from red, in sixths of a turn
+    let h = (turns + 2.0 / 3.0).rem_euclid(1.0) * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+
+    let (r, g, b) = match h as usize {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+
+    [(255.0 * r) as u8, (255.0 * g) as u8, (255.0 * b) as u8]
+}
+
+/// Exact critical temperature of the square q-state Potts ferromagnet with couplings jx and jy,
+/// which solves (exp(Jx/T) - 1)(exp(Jy/T) - 1) = q (self-duality). None unless both couplings
+/// are positive.
+pub fn potts_critical_temperature(jx: f32, jy: f32, q: usize) -> Option<f32> {
+    if jx <= 0.0 || jy <= 0.0 {
+        return None;
+    }
+
+    // the left hand side falls monotonically with T, from infinity to zero
+    let f = |t: f64| (jx as f64 / t).exp_m1() * (jy as f64 / t).exp_m1() - q as f64;
+    let (mut low, mut high) = (1e-3, (jx + jy) as f64 / f64::ln(1.0 + f64::sqrt(q as f64)));
+    for _ in 0..100 {
+        let middle = 0.5 * (low + high);
+        if f(middle) > 0.0 {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    Some((0.5 * (low + high)) as f32)
+}
@@ -0,0 +1,237 @@
+use rand::RngCore;
+
+use crate::spin::Spin;
+
+/// Number of random words combined to build an acceptance mask, i.e. probabilities are
+/// resolved to 2^-PRECISION.
+const PRECISION: u32 = 16;
+
+/// Bits at even x positions (64 is even, so the pattern is the same for every word).
+const EVEN_BITS: u64 = 0x5555_5555_5555_5555;
+
+/// Square lattice (width × height) with one spin per bit (set = up), for word-parallel Metropolis sweeps of
+/// uniform ferromagnetic/antiferromagnetic couplings ("multispin coding").
+///
+/// Each row is stored in `words_per_row` words, spin x lives in bit x % 64 of word x / 64.
+/// Unused bits at the end of a row are always zero.
+pub struct Multispin {
+    width: usize,
+    height: usize,
+    words_per_row: usize,
+    bits: Vec<u64>,
+}
+
+impl Multispin {
+    pub fn pack(state: &[Spin], width: usize, height: usize) -> Multispin {
+        let words_per_row = width.div_ceil(64);
+        let mut bits = vec![0; words_per_row * height];
+
+        for y in 0..height {
+            for x in 0..width {
+                if let Spin::Up = state[x + y * width] {
+                    bits[y * words_per_row + x / 64] |= 1 << (x % 64);
+                }
+            }
+        }
+
+        Multispin {
+            width,
+            height,
+            words_per_row,
+            bits,
+        }
+    }
+
+    pub fn unpack(&self, state: &mut [Spin]) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                state[x + y * self.width] = if self.get(x, y) {
+                    Spin::Up
+                } else {
+                    Spin::Down
+                };
+            }
+        }
+    }
+
+    /// One checkerboard sweep. `factors[s][c]` is the acceptance probability for flipping a site
+    /// with spin s (0 = up, 1 = down) and c unsatisfied bonds.
+    /// Only valid for even widths and heights, otherwise the periodic wraparound mixes the sublattices.
+    /// Returns the number of accepted flips.
+    pub fn sweep(&mut self, antiferromagnetic: bool, factors: &[[f32; 5]; 2], rng: &mut impl RngCore) -> u64 {
+        let w = self.words_per_row;
+        let mut accepted = 0;
+
+        let mut left = vec![0; w];
+        let mut right = vec![0; w];
+
+        for parity in 0..2 {
+            for y in 0..self.height {
+                let up_row = (y + self.height - 1) % self.height;
+                let down_row = (y + 1) % self.height;
+
+                self.shift_left(y, &mut left);
+                self.shift_right(y, &mut right);
+
+                let colour = if (y + parity) % 2 == 0 { EVEN_BITS } else { !EVEN_BITS };
+
+                for i in 0..w {
+                    let centre = self.bits[y * w + i];
+                    let sublattice = colour & self.valid_bits(i);
+
+                    // a bond is unsatisfied if the spins are antiparallel (ferro) or parallel (antiferro)
+                    let unsatisfied = |other: u64| if antiferromagnetic { !(centre ^ other) } else { centre ^ other };
+
+                    let count = BitCount::new(
+                        unsatisfied(left[i]),
+                        unsatisfied(right[i]),
+                        unsatisfied(self.bits[up_row * w + i]),
+                        unsatisfied(self.bits[down_row * w + i]),
+                    );
+
+                    let mut flip = 0;
+
+                    for (s, spin_bits) in [centre, !centre].into_iter().enumerate() {
+                        for (c, factor) in factors[s].iter().enumerate() {
+                            let level = count.equals(c) & spin_bits & sublattice;
+
+                            if level != 0 {
+                                flip |= level & random_mask(*factor, rng);
+                            }
+                        }
+                    }
+
+                    self.bits[y * w + i] ^= flip;
+                    accepted += flip.count_ones() as u64;
+                }
+            }
+        }
+
+        accepted
+    }
+
+    /// Sum of the spins and exchange energy of the whole lattice, counted from the bits
+    pub fn totals(&self, antiferromagnetic: bool) -> (i64, f64) {
+        let w = self.words_per_row;
+        let mut right = vec![0; w];
+
+        let mut up_spins = 0;
+        let mut unsatisfied = 0;
+
+        for y in 0..self.height {
+            let down_row = (y + 1) % self.height;
+            self.shift_right(y, &mut right);
+
+            for (i, right) in right.iter().enumerate() {
+                let centre = self.bits[y * w + i];
+                let valid = self.valid_bits(i);
+                let unsatisfied_bits = |other: u64| if antiferromagnetic { !(centre ^ other) } else { centre ^ other } & valid;
+
+                up_spins += centre.count_ones() as i64;
+                unsatisfied += unsatisfied_bits(*right).count_ones() as i64;
+                unsatisfied += unsatisfied_bits(self.bits[down_row * w + i]).count_ones() as i64;
+            }
+        }
+
+        let sites = (self.width * self.height) as i64;
+        // two bonds per site, each satisfied bond contributes -1 and each unsatisfied one +1
+        let exchange_energy = 2 * unsatisfied - 2 * sites;
+
+        (2 * up_spins - sites, exchange_energy as f64)
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.bits[y * self.words_per_row + x / 64] >> (x % 64) & 1 == 1
+    }
+
+    /// Mask of the bits in word i of a row which correspond to actual sites
+    fn valid_bits(&self, i: usize) -> u64 {
+        let used = self.width - i * 64;
+
+        if used >= 64 {
+            u64::MAX
+        } else {
+            (1 << used) - 1
+        }
+    }
+
+    /// Row y, shifted so bit x holds the spin at x - 1 (periodic)
+    fn shift_left(&self, y: usize, out: &mut [u64]) {
+        let w = self.words_per_row;
+        let row = &self.bits[y * w..(y + 1) * w];
+
+        for i in 0..w {
+            let carry = if i > 0 { row[i - 1] >> 63 } else { 0 };
+            out[i] = ((row[i] << 1) | carry) & self.valid_bits(i);
+        }
+
+        out[0] |= self.get(self.width - 1, y) as u64;
+    }
+
+    /// Row y, shifted so bit x holds the spin at x + 1 (periodic)
+    fn shift_right(&self, y: usize, out: &mut [u64]) {
+        let w = self.words_per_row;
+        let row = &self.bits[y * w..(y + 1) * w];
+
+        for i in 0..w {
+            let carry = if i + 1 < w { row[i + 1] << 63 } else { 0 };
+            out[i] = (row[i] >> 1) | carry;
+        }
+
+        let last = self.width - 1;
+        out[last / 64] = (out[last / 64] & !(1 << (last % 64))) | ((self.get(0, y) as u64) << (last % 64));
+    }
+}
+
+/// Bit-sliced sum of four one-bit numbers, 0..=4 in every bit position
+struct BitCount {
+    low: u64,
+    mid: u64,
+    high: u64,
+}
+
+impl BitCount {
+    fn new(a: u64, b: u64, c: u64, d: u64) -> BitCount {
+        // two half adders, then add the two-bit results
+        let (sum_ab, carry_ab) = (a ^ b, a & b);
+        let (sum_cd, carry_cd) = (c ^ d, c & d);
+
+        let low = sum_ab ^ sum_cd;
+        let carry = sum_ab & sum_cd;
+
+        BitCount {
+            low,
+            mid: carry_ab ^ carry_cd ^ carry,
+            high: (carry_ab & carry_cd) | (carry & (carry_ab ^ carry_cd)),
+        }
+    }
+
+    /// Bits which are equal to n
+    fn equals(&self, n: usize) -> u64 {
+        let bit = |value: u64, set: bool| if set { value } else { !value };
+
+        bit(self.low, n & 1 != 0) & bit(self.mid, n & 2 != 0) & bit(self.high, n & 4 != 0)
+    }
+}
+
+/// Random word where each bit is independently set with probability p (to within 2^-PRECISION),
+/// built from the binary expansion of p.
+fn random_mask(p: f32, rng: &mut impl RngCore) -> u64 {
+    if p >= 1.0 {
+        return u64::MAX;
+    }
+    if p <= 0.0 {
+        return 0;
+    }
+
+    let threshold = (p as f64 * (1u64 << PRECISION) as f64) as u64;
+    let mut mask = 0;
+
+    // least significant bit first, each step halves the probability and optionally adds 1/2
+    for j in 0..PRECISION {
+        let r = rng.next_u64();
+        mask = if threshold >> j & 1 == 1 { mask | r } else { mask & r };
+    }
+
+    mask
+}
@@ -0,0 +1,220 @@
+use std::fmt::Display;
+
+use rand::{RngCore, SeedableRng, rngs::StdRng};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RngAlgorithm {
+    Xoshiro256PlusPlus,
+    Pcg64,
+    /// rand's StdRng, ChaCha with 12 rounds
+    ChaCha12,
+}
+
+impl RngAlgorithm {
+    pub const ALL: [RngAlgorithm; 3] = [RngAlgorithm::Xoshiro256PlusPlus, RngAlgorithm::Pcg64, RngAlgorithm::ChaCha12];
+}
+
+impl Display for RngAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RngAlgorithm::Xoshiro256PlusPlus => f.write_str("Xoshiro256++"),
+            RngAlgorithm::Pcg64 => f.write_str("PCG64"),
+            RngAlgorithm::ChaCha12 => f.write_str("ChaCha12"),
+        }
+    }
+}
+
+/// Random number generator of a lattice, one of several algorithms.
+#[derive(Debug, Clone)]
+pub enum LatticeRng {
+    Xoshiro256PlusPlus(Xoshiro256PlusPlus),
+    Pcg64(Pcg64),
+    // boxed, its buffered state is much larger than the others
+    ChaCha12(Box<StdRng>),
+}
+
+impl LatticeRng {
+    pub fn new(algorithm: RngAlgorithm, seed: u64) -> LatticeRng {
+        match algorithm {
+            RngAlgorithm::Xoshiro256PlusPlus => LatticeRng::Xoshiro256PlusPlus(Xoshiro256PlusPlus::new(seed)),
+            RngAlgorithm::Pcg64 => LatticeRng::Pcg64(Pcg64::new(seed)),
+            RngAlgorithm::ChaCha12 => LatticeRng::ChaCha12(Box::new(StdRng::seed_from_u64(seed))),
+        }
+    }
+
+    pub fn algorithm(&self) -> RngAlgorithm {
+        match self {
+            LatticeRng::Xoshiro256PlusPlus(_) => RngAlgorithm::Xoshiro256PlusPlus,
+            LatticeRng::Pcg64(_) => RngAlgorithm::Pcg64,
+            LatticeRng::ChaCha12(_) => RngAlgorithm::ChaCha12,
+        }
+    }
+}
+
+impl RngCore for LatticeRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            LatticeRng::Xoshiro256PlusPlus(rng) => (rng.next() >> 32) as u32,
+            LatticeRng::Pcg64(rng) => (rng.next() >> 32) as u32,
+            LatticeRng::ChaCha12(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            LatticeRng::Xoshiro256PlusPlus(rng) => rng.next(),
+            LatticeRng::Pcg64(rng) => rng.next(),
+            LatticeRng::ChaCha12(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            LatticeRng::ChaCha12(rng) => rng.fill_bytes(dest),
+            _ => {
+                for chunk in dest.chunks_mut(8) {
+                    let bytes = self.next_u64().to_le_bytes();
+                    chunk.copy_from_slice(&bytes[..chunk.len()]);
+                }
+            },
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Expands a 64 bit seed into a larger state, as recommended for seeding Xoshiro/PCG.
+fn split_mix_64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Xoshiro256++ by Blackman and Vigna, fast with good statistical quality.
+#[derive(Debug, Clone)]
+pub struct Xoshiro256PlusPlus {
+    state: [u64; 4],
+}
+
+impl Xoshiro256PlusPlus {
+    fn new(seed: u64) -> Xoshiro256PlusPlus {
+        let mut seed = seed;
+        let state = [(); 4].map(|_| split_mix_64(&mut seed));
+
+        Xoshiro256PlusPlus { state }
+    }
+
+    fn next(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[0].wrapping_add(s[3]).rotate_left(23).wrapping_add(s[0]);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+}
+
+/// PCG64 (XSL RR 128/64) by O'Neill, a 128 bit LCG with a permuted output.
+#[derive(Debug, Clone)]
+pub struct Pcg64 {
+    state: u128,
+    increment: u128,
+}
+
+impl Pcg64 {
+    const MULTIPLIER: u128 = 0x2360_ED05_1FC6_5DA4_4385_DF64_9FCC_F645;
+    const INCREMENT: u128 = 0x5851_F42D_4C95_7F2D_1405_7B7E_F767_814F;
+
+    fn new(seed: u64) -> Pcg64 {
+        let mut seed = seed;
+        let state = (split_mix_64(&mut seed) as u128) << 64 | split_mix_64(&mut seed) as u128;
+
+        Pcg64::from_state(state, Pcg64::INCREMENT)
+    }
+
+    /// Seeded as by the reference implementation, the increment selects the stream
+    fn from_state(state: u128, increment: u128) -> Pcg64 {
+        let mut pcg = Pcg64 {
+            state: 0,
+            increment: increment | 1,
+        };
+        pcg.state = state.wrapping_add(pcg.increment);
+        pcg.step();
+
+        pcg
+    }
+
+    fn step(&mut self) {
+        self.state = self.state.wrapping_mul(Pcg64::MULTIPLIER).wrapping_add(self.increment);
+    }
+
+    fn next(&mut self) -> u64 {
+        self.step();
+
+        let rotation = (self.state >> 122) as u32;
+        let xsl = (self.state >> 64) as u64 ^ self.state as u64;
+        xsl.rotate_right(rotation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_mix_64_matches_reference() {
+        // outputs of splitmix64.c seeded with 0
+        let mut state = 0;
+        let outputs = [(); 4].map(|_| split_mix_64(&mut state));
+        assert_eq!(outputs, [0xE220_A839_7B1D_CDAF, 0x6E78_9E6A_A1B9_65F4, 0x06C4_5D18_8009_454F, 0xF88B_B8A8_724C_81EC]);
+    }
+
+    #[test]
+    fn xoshiro_matches_reference() {
+        // outputs of xoshiro256plusplus.c from the state [1, 2, 3, 4]
+        let mut rng = Xoshiro256PlusPlus { state: [1, 2, 3, 4] };
+        let outputs = [(); 10].map(|_| rng.next());
+        assert_eq!(outputs, [
+            41943041, 58720359, 3588806011781223, 3591011842654386, 9228616714210784205,
+            9973669472204895162, 14011001112246962877, 12406186145184390807, 15849039046786891736,
+            10450023813501588000,
+        ]);
+    }
+
+    #[test]
+    fn xoshiro_seeded_with_split_mix() {
+        let rng = Xoshiro256PlusPlus::new(0);
+        assert_eq!(rng.state, [0xE220_A839_7B1D_CDAF, 0x6E78_9E6A_A1B9_65F4, 0x06C4_5D18_8009_454F, 0xF88B_B8A8_724C_81EC]);
+    }
+
+    #[test]
+    fn pcg_matches_reference() {
+        // outputs of the reference pcg64 seeded with state 42 on stream 54
+        let mut rng = Pcg64::from_state(42, 54 << 1);
+        let outputs = [(); 6].map(|_| rng.next());
+        assert_eq!(outputs, [
+            0x86B1_DA1D_7206_2B68, 0x1304_AA46_C985_3D39, 0xA367_0E9E_0DD5_0358,
+            0xF909_0E52_9A7D_AE00, 0xC85B_9FD8_3799_6F2C, 0x6061_21F8_E391_9196,
+        ]);
+    }
+
+    #[test]
+    fn pcg_seeded_with_split_mix() {
+        let mut rng = Pcg64::new(0);
+        let mut expected = Pcg64::from_state(0xE220_A839_7B1D_CDAF_6E78_9E6A_A1B9_65F4, Pcg64::INCREMENT);
+        assert_eq!([(); 4].map(|_| rng.next()), [(); 4].map(|_| expected.next()));
+    }
+}
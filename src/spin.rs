@@ -1,6 +1,6 @@
 use std::{ops::{Mul, Neg}, fmt::Display};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Spin {
     Up, // z: +1
     Down, // z: -1
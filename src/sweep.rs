@@ -0,0 +1,864 @@
+use std::{fmt::Display, io::Write, path::Path};
+
+use crate::{measurement::{self, Estimate, Measurements, Sample}, fit::{self, LinearFit}};
+
+/// Measurements at one temperature of a sweep
+#[derive(Debug, Clone, Copy)]
+pub struct SweepPoint {
+    pub temperature: f32,
+    pub heat_capacity: Option<Estimate>,
+    pub susceptibility: Option<Estimate>,
+    pub binder_cumulant: Option<Estimate>,
+    pub abs_magnetisation: Option<Estimate>,
+    pub magnetisation_squared: Option<Estimate>,
+    pub magnetisation_fourth: Option<Estimate>,
+    /// Mean energy per spin
+    pub energy: Option<Estimate>,
+    pub correlation_length: Option<Estimate>,
+    /// Integrated autocorrelation time of the magnetisation, in sweeps
+    pub autocorrelation_time: Option<Estimate>,
+}
+
+/// Quantity of a sweep point to plot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SweepObservable {
+    AbsMagnetisation,
+    Energy,
+    Susceptibility,
+    HeatCapacity,
+    BinderCumulant,
+    AutocorrelationTime,
+}
+
+impl SweepObservable {
+    pub const ALL: [SweepObservable; 6] = [
+        SweepObservable::AbsMagnetisation,
+        SweepObservable::Energy,
+        SweepObservable::Susceptibility,
+        SweepObservable::HeatCapacity,
+        SweepObservable::BinderCumulant,
+        SweepObservable::AutocorrelationTime,
+    ];
+
+    pub fn of(&self, point: &SweepPoint) -> Option<Estimate> {
+        match self {
+            SweepObservable::AbsMagnetisation => point.abs_magnetisation,
+            SweepObservable::Energy => point.energy,
+            SweepObservable::Susceptibility => point.susceptibility,
+            SweepObservable::HeatCapacity => point.heat_capacity,
+            SweepObservable::BinderCumulant => point.binder_cumulant,
+            SweepObservable::AutocorrelationTime => point.autocorrelation_time,
+        }
+    }
+}
+
+impl Display for SweepObservable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SweepObservable::AbsMagnetisation => f.write_str("<|m|>"),
+            SweepObservable::Energy => f.write_str("<E>"),
+            SweepObservable::Susceptibility => f.write_str("χ"),
+            SweepObservable::HeatCapacity => f.write_str("C"),
+            SweepObservable::BinderCumulant => f.write_str("U4"),
+            SweepObservable::AutocorrelationTime => f.write_str("τ"),
+        }
+    }
+}
+
+/// Steps the temperature evenly from `start` to `end`, moving on once `samples` samples have been
+/// recorded at each temperature after the burn-in.
+pub struct TemperatureSweep {
+    pub start: f32,
+    pub end: f32,
+    /// Number of temperatures, at least 2
+    pub steps: usize,
+    pub samples: u64,
+    points: Vec<SweepPoint>,
+    // index of the temperature being measured, None unless running
+    current: Option<usize>,
+    // generation of the measurements before the temperature last changed, whose samples still
+    // arriving from the simulation thread belong to the previous temperature
+    stale_generation: u64,
+}
+
+impl TemperatureSweep {
+    pub fn new(start: f32, end: f32, steps: usize, samples: u64) -> TemperatureSweep {
+        TemperatureSweep {
+            start,
+            end,
+            steps,
+            samples,
+            points: Vec::new(),
+            current: None,
+            stale_generation: 0,
+        }
+    }
+
+    /// Temperature of step `step`
+    pub fn temperature(&self, step: usize) -> f32 {
+        let steps = self.steps.max(2);
+        self.start + (self.end - self.start) * step as f32 / (steps - 1) as f32
+    }
+
+    /// Throw away any previous points and begin, returning the first temperature to set
+    pub fn begin(&mut self, measurements: &Measurements) -> f32 {
+        self.points.clear();
+        self.current = Some(0);
+        self.stale_generation = measurements.generation();
+        self.temperature(0)
+    }
+
+    pub fn stop(&mut self) {
+        self.current = None;
+    }
+
+    /// Index of the temperature being measured, if running
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// Record a point once there are enough samples at the current temperature, returning the
+    /// next temperature to set, if there is one. `sites` is the number of sites of the lattice and
+    /// `side` its width, for the correlation length.
+    pub fn update(&mut self, measurements: &Measurements, sites: usize, side: usize) -> Option<f32> {
+        let step = self.current?;
+        if measurements.generation() == self.stale_generation || measurements.count() < self.samples {
+            return None;
+        }
+
+        let temperature = self.temperature(step);
+        self.points.push(SweepPoint {
+            temperature,
+            heat_capacity: measurements.heat_capacity(sites, temperature),
+            susceptibility: measurements.susceptibility(sites, temperature),
+            binder_cumulant: measurements.binder_cumulant(),
+            abs_magnetisation: measurements.mean_abs_magnetisation(),
+            magnetisation_squared: measurements.mean_magnetisation_squared(),
+            magnetisation_fourth: measurements.mean_magnetisation_fourth(),
+            energy: measurements.mean_energy(),
+            correlation_length: measurements.correlation_length(side),
+            autocorrelation_time: measurements.autocorrelation_sweeps(),
+        });
+
+        if step + 1 < self.steps.max(2) {
+            self.current = Some(step + 1);
+            self.stale_generation = measurements.generation();
+            Some(self.temperature(step + 1))
+        } else {
+            self.current = None;
+            None
+        }
+    }
+
+    /// Points measured so far, in order of temperature stepped through
+    pub fn points(&self) -> &[SweepPoint] {
+        &self.points
+    }
+}
+
+/// Samples from one run kept for histogram reweighting, see MultiHistogram
+pub struct HistogramRun {
+    pub temperature: f32,
+    sites: usize,
+    // energy per spin and magnetisation of each sample
+    samples: Vec<(f32, f32)>,
+}
+
+impl HistogramRun {
+    /// The fluctuation window of the measurements at `temperature`, None with too few samples
+    pub fn new(measurements: &Measurements, sites: usize, temperature: f32) -> Option<HistogramRun> {
+        let samples = measurements.fluctuation_samples();
+        (samples.len() >= 100 && temperature > 0.0).then_some(HistogramRun { temperature, sites, samples })
+    }
+
+    /// Temperatures the reweighting can be trusted over, where the mean energy moves by less
+    /// than twice the spread of the energies sampled, δT = 2 T₀² / σ_E
+    pub fn range(&self) -> (f32, f32) {
+        let spread = self.sites as f64 * measurement::variance(&self.samples, |(energy, _)| energy).unwrap_or(0.0).sqrt();
+        let half_width = if spread > 0.0 { 2.0 * (self.temperature as f64).powi(2) / spread } else { 0.0 };
+        let half_width = half_width.min(0.5 * self.temperature as f64) as f32;
+
+        (self.temperature - half_width, self.temperature + half_width)
+    }
+}
+
+/// Most iterations of the multiple histogram equations
+const MULTI_HISTOGRAM_ITERATIONS: usize = 5000;
+/// Bins of the combined energy histogram the multiple histogram equations are solved over
+const MULTI_HISTOGRAM_BINS: usize = 512;
+/// Temperatures the combined averages are worked out at across the range
+const MULTI_HISTOGRAM_POINTS: usize = 100;
+
+/// Runs at several temperatures combined by multiple histogram reweighting (Ferrenberg and
+/// Swendsen, the same equations as WHAM). Every sample of every run counts towards the averages
+/// at a temperature T, weighted by exp(-E/T) / Σ_k n_k exp(-E/T_k) / Z_k for runs k of n_k
+/// samples, where the partition functions Z_k are themselves solved for self-consistently. Where
+/// the runs' energies overlap this gives one smooth curve across all of them. With a single run
+/// it is single histogram reweighting, each sample weighted by exp(-(1/T - 1/T₀) E).
+pub struct MultiHistogram {
+    runs: Vec<HistogramRun>,
+    // every sample of every run, with ln Σ_k n_k exp(-E/T_k) / Z_k for its energy
+    samples: Vec<(f32, f32)>,
+    log_denominators: Vec<f64>,
+    // the combined averages over range(), worked out once per run added
+    curve: Vec<SweepPoint>,
+}
+
+impl MultiHistogram {
+    pub fn new() -> MultiHistogram {
+        MultiHistogram {
+            runs: Vec::new(),
+            samples: Vec::new(),
+            log_denominators: Vec::new(),
+            curve: Vec::new(),
+        }
+    }
+
+    /// Add a run, replacing any earlier one at the same temperature. Runs of a lattice with a
+    /// different number of sites start the combination again.
+    pub fn add(&mut self, run: HistogramRun) {
+        if self.runs.first().is_some_and(|first| first.sites != run.sites) {
+            self.runs.clear();
+        }
+        self.runs.retain(|other| (other.temperature - run.temperature).abs() >= 1e-4);
+        self.runs.push(run);
+        self.runs.sort_by(|a, b| a.temperature.total_cmp(&b.temperature));
+
+        self.solve();
+        self.curve = match self.range() {
+            Some((low, high)) => (0..=MULTI_HISTOGRAM_POINTS)
+                .filter_map(|step| self.point(low + (high - low) * step as f32 / MULTI_HISTOGRAM_POINTS as f32))
+                .collect(),
+            None => Vec::new(),
+        };
+    }
+
+    pub fn clear(&mut self) {
+        *self = MultiHistogram::new();
+    }
+
+    pub fn runs(&self) -> &[HistogramRun] {
+        &self.runs
+    }
+
+    /// Iterate ln Z_k = ln Σ_E h(E) exp(-E/T_k) / Σ_j n_j exp(-E/T_j - ln Z_j) until it settles,
+    /// with h the histogram of the energies of all the runs together
+    fn solve(&mut self) {
+        let sites = self.runs.first().map_or(0, |run| run.sites) as f64;
+        self.samples = self.runs.iter().flat_map(|run| run.samples.iter().copied()).collect();
+        let energies: Vec<f64> = self.samples.iter().map(|(energy, _)| sites * *energy as f64).collect();
+        let betas: Vec<f64> = self.runs.iter().map(|run| 1.0 / run.temperature as f64).collect();
+        let log_counts: Vec<f64> = self.runs.iter().map(|run| (run.samples.len() as f64).ln()).collect();
+
+        // the mean energy and ln of the number of samples in each occupied bin
+        let lowest = energies.iter().copied().fold(f64::INFINITY, f64::min);
+        let highest = energies.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let width = ((highest - lowest) / MULTI_HISTOGRAM_BINS as f64).max(f64::EPSILON);
+        let mut bins = vec![(0.0, 0usize); MULTI_HISTOGRAM_BINS];
+        for energy in &energies {
+            let bin = &mut bins[(((energy - lowest) / width) as usize).min(MULTI_HISTOGRAM_BINS - 1)];
+            bin.0 += energy;
+            bin.1 += 1;
+        }
+        let histogram: Vec<(f64, f64)> = bins.into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(total, count)| (total / count as f64, (count as f64).ln()))
+            .collect();
+
+        let log_denominator = |energy: f64, log_partition_functions: &[f64]| {
+            log_sum_exp((0..betas.len()).map(|k| log_counts[k] - betas[k] * energy - log_partition_functions[k]))
+        };
+
+        let mut log_partition_functions = vec![0.0; self.runs.len()];
+        for _ in 0..MULTI_HISTOGRAM_ITERATIONS {
+            let denominators: Vec<f64> = histogram.iter().map(|(energy, _)| log_denominator(*energy, &log_partition_functions)).collect();
+            let mut next: Vec<f64> = betas.iter()
+                .map(|beta| log_sum_exp(histogram.iter().zip(&denominators).map(|((energy, log_count), denominator)| log_count - beta * energy - denominator)))
+                .collect();
+            // only ratios of the partition functions matter
+            let reference = next[0];
+            for value in &mut next {
+                *value -= reference;
+            }
+
+            let change = next.iter().zip(&log_partition_functions).map(|(a, b)| (a - b).abs()).fold(0.0, f64::max);
+            log_partition_functions = next;
+            if change < 1e-8 {
+                break;
+            }
+        }
+
+        self.log_denominators = energies.iter().map(|energy| log_denominator(*energy, &log_partition_functions)).collect();
+    }
+
+    /// From the lowest to the highest temperature any run can be trusted at
+    pub fn range(&self) -> Option<(f32, f32)> {
+        let low = self.runs.iter().map(|run| run.range().0).reduce(f32::min)?;
+        let high = self.runs.iter().map(|run| run.range().1).reduce(f32::max)?;
+        Some((low, high))
+    }
+
+    /// Averages at `temperature` predicted from all the runs, without error bars
+    pub fn point(&self, temperature: f32) -> Option<SweepPoint> {
+        let sites = self.runs.first()?.sites;
+        if temperature <= 0.0 {
+            return None;
+        }
+
+        let beta = sites as f64 / temperature as f64;
+        let log_weights: Vec<f64> = self.samples.iter().zip(&self.log_denominators)
+            .map(|((energy, _), denominator)| -beta * *energy as f64 - denominator)
+            .collect();
+
+        weighted_point(&self.samples, &log_weights, sites, temperature)
+    }
+
+    /// Points evenly spaced over range()
+    pub fn curve(&self) -> &[SweepPoint] {
+        &self.curve
+    }
+}
+
+/// ln Σ exp(x), without overflowing
+fn log_sum_exp(values: impl Iterator<Item = f64> + Clone) -> f64 {
+    let largest = values.clone().fold(f64::NEG_INFINITY, f64::max);
+    if !largest.is_finite() {
+        return largest;
+    }
+    largest + values.map(|value| (value - largest).exp()).sum::<f64>().ln()
+}
+
+/// Averages of (energy per spin, magnetisation) samples with the given log weights, as a sweep
+/// point at `temperature` of a lattice of `sites` spins
+fn weighted_point(samples: &[(f32, f32)], log_weights: &[f64], sites: usize, temperature: f32) -> Option<SweepPoint> {
+    let largest = log_weights.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let weights: Vec<f64> = log_weights.iter().map(|log_weight| (log_weight - largest).exp()).collect();
+    let total: f64 = weights.iter().sum();
+    if !total.is_finite() || total <= 0.0 {
+        return None;
+    }
+
+    let mean = |observable: &dyn Fn((f32, f32)) -> f64| {
+        samples.iter().zip(&weights).map(|(sample, weight)| weight * observable(*sample)).sum::<f64>() / total
+    };
+    let energy = mean(&|(energy, _)| energy as f64);
+    let energy_squared = mean(&|(energy, _)| (energy as f64).powi(2));
+    let magnetisation = mean(&|(_, magnetisation)| magnetisation as f64);
+    let abs_magnetisation = mean(&|(_, magnetisation)| (magnetisation as f64).abs());
+    let squared = mean(&|(_, magnetisation)| (magnetisation as f64).powi(2));
+    let fourth = mean(&|(_, magnetisation)| (magnetisation as f64).powi(4));
+
+    let sites = sites as f64;
+    let estimate = |value: f64| Some(Estimate { value: value as f32, error: None });
+    Some(SweepPoint {
+        temperature,
+        heat_capacity: estimate(sites * (energy_squared - energy * energy) / (temperature as f64).powi(2)),
+        susceptibility: estimate(sites * (squared - magnetisation * magnetisation) / temperature as f64),
+        binder_cumulant: (squared > 0.0).then(|| 1.0 - fourth / (3.0 * squared * squared)).and_then(estimate),
+        abs_magnetisation: estimate(abs_magnetisation),
+        magnetisation_squared: estimate(squared),
+        magnetisation_fourth: estimate(fourth),
+        energy: estimate(energy),
+        correlation_length: None,
+        autocorrelation_time: None,
+    })
+}
+
+/// Write the points of sweeps at each lattice size as comma separated values, one row per point
+/// with a value and an error column for each observable. Missing values are left empty.
+/// `description` is written as comments at the top, so the run can be reproduced.
+pub fn save_csv(path: &Path, description: &str, curves: &[(usize, &[SweepPoint])]) -> std::io::Result<()> {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+    writeln!(file, "# ising-gui sweep")?;
+    for line in description.lines() {
+        writeln!(file, "# {}", line)?;
+    }
+
+    let columns = ["abs_m", "m2", "m4", "energy", "heat_capacity", "susceptibility", "binder", "xi", "tau"];
+    write!(file, "size,temperature")?;
+    for column in columns {
+        write!(file, ",{},{}_error", column, column)?;
+    }
+    writeln!(file)?;
+
+    for (size, points) in curves {
+        for point in *points {
+            write!(file, "{},{}", size, point.temperature)?;
+            for estimate in [
+                point.abs_magnetisation,
+                point.magnetisation_squared,
+                point.magnetisation_fourth,
+                point.energy,
+                point.heat_capacity,
+                point.susceptibility,
+                point.binder_cumulant,
+                point.correlation_length,
+                point.autocorrelation_time,
+            ] {
+                let value = estimate.map(|estimate| estimate.value.to_string()).unwrap_or_default();
+                let error = estimate.and_then(|estimate| estimate.error).map(|error| error.to_string()).unwrap_or_default();
+                write!(file, ",{},{}", value, error)?;
+            }
+            writeln!(file)?;
+        }
+    }
+
+    file.flush()
+}
+
+/// Samples needed at a temperature before the scan records its mean |m|
+const SCAN_SAMPLES: u64 = 50;
+
+/// ⟨|m|⟩ at each temperature the simulation has been left at, however it got there. The point at
+/// the current temperature is refined as samples come in.
+pub struct TemperatureScan {
+    // temperature and ⟨|m|⟩ of each point
+    points: Vec<(f32, Estimate)>,
+    temperature: f32,
+    // as for TemperatureSweep
+    stale_generation: u64,
+}
+
+impl TemperatureScan {
+    pub fn new() -> TemperatureScan {
+        TemperatureScan {
+            points: Vec::new(),
+            temperature: f32::NAN,
+            stale_generation: 0,
+        }
+    }
+
+    /// Record the measurements at `temperature`, replacing any earlier point there
+    pub fn update(&mut self, measurements: &Measurements, temperature: f32) {
+        if temperature != self.temperature {
+            self.temperature = temperature;
+            self.stale_generation = measurements.generation();
+            return;
+        }
+        if measurements.generation() == self.stale_generation || measurements.count() < SCAN_SAMPLES {
+            return;
+        }
+        let Some(magnetisation) = measurements.mean_abs_magnetisation() else {
+            return;
+        };
+
+        match self.points.iter_mut().find(|(t, _)| (t - temperature).abs() < 1e-4) {
+            Some(point) => point.1 = magnetisation,
+            None => self.points.push((temperature, magnetisation)),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Points recorded so far, in the order the temperatures were first visited
+    pub fn points(&self) -> &[(f32, Estimate)] {
+        &self.points
+    }
+}
+
+/// Ramps the magnetic field from -`max_field` up to `max_field` and back down in `steps` steps
+/// each way, recording the magnetisation `sweeps` sweeps after each step. The lattice is never
+/// left to equilibrate, so below Tc the magnetisation lags behind the field and traces out a
+/// hysteresis loop.
+pub struct FieldSweep {
+    pub max_field: f32,
+    pub steps: usize,
+    pub sweeps: u64,
+    // field and magnetisation at each step so far
+    points: Vec<[f32; 2]>,
+    current: Option<usize>,
+    // as for TemperatureSweep
+    stale_generation: u64,
+}
+
+impl FieldSweep {
+    pub fn new(max_field: f32, steps: usize, sweeps: u64) -> FieldSweep {
+        FieldSweep {
+            max_field,
+            steps,
+            sweeps,
+            points: Vec::new(),
+            current: None,
+            stale_generation: 0,
+        }
+    }
+
+    /// Field of step `step`, up the first branch and back down the second
+    pub fn field(&self, step: usize) -> f32 {
+        let steps = self.steps.max(1);
+        let up = if step <= steps { step } else { 2 * steps - step };
+        self.max_field * (2.0 * up as f32 / steps as f32 - 1.0)
+    }
+
+    /// Throw away any previous loop and begin, returning the first field to set. `generation`
+    /// is that of the latest sample.
+    pub fn begin(&mut self, generation: u64) -> f32 {
+        self.points.clear();
+        self.current = Some(0);
+        self.stale_generation = generation;
+        self.field(0)
+    }
+
+    pub fn stop(&mut self) {
+        self.current = None;
+    }
+
+    /// Index of the step running, if running
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// Record the magnetisation once a sample comes in `sweeps` sweeps after the field changed,
+    /// returning the next field to set, if there is one
+    pub fn record(&mut self, sample: &Sample) -> Option<f32> {
+        let step = self.current?;
+        if sample.generation == self.stale_generation || sample.sweeps_since_change < self.sweeps {
+            return None;
+        }
+
+        self.points.push([self.field(step), sample.magnetisation]);
+        self.stale_generation = sample.generation;
+
+        if step < 2 * self.steps.max(1) {
+            self.current = Some(step + 1);
+            Some(self.field(step + 1))
+        } else {
+            self.current = None;
+            None
+        }
+    }
+
+    /// Field and magnetisation of every step so far
+    pub fn points(&self) -> &[[f32; 2]] {
+        &self.points
+    }
+
+    /// Fields at which the magnetisation changes sign on the way up and on the way down
+    pub fn coercive_fields(&self) -> [Option<f32>; 2] {
+        let steps = self.steps.max(1);
+        let crossing = |branch: &[[f32; 2]]| branch.windows(2).find_map(|pair| {
+            let ([b0, m0], [b1, m1]) = (pair[0], pair[1]);
+            ((m0 < 0.0) != (m1 < 0.0)).then(|| b0 + (b1 - b0) * m0 / (m0 - m1))
+        });
+
+        let up = &self.points[..self.points.len().min(steps + 1)];
+        let down = self.points.get(steps..).unwrap_or_default();
+        [crossing(up), crossing(down)]
+    }
+
+    /// Area ∮ m dB enclosed by the loop, the energy per spin dissipated in one cycle. None until
+    /// the loop is complete.
+    pub fn loop_area(&self) -> Option<f32> {
+        if self.points.len() < 2 * self.steps.max(1) + 1 {
+            return None;
+        }
+
+        let area: f32 = self.points.windows(2)
+            .map(|pair| 0.5 * (pair[0][1] + pair[1][1]) * (pair[1][0] - pair[0][0]))
+            .sum();
+
+        Some(area.abs())
+    }
+}
+
+/// Free energy and entropy per site at one temperature of a sweep
+#[derive(Debug, Clone, Copy)]
+pub struct Thermodynamics {
+    pub temperature: f32,
+    pub free_energy: f32,
+    pub entropy: f32,
+}
+
+/// Free energy and entropy at the temperatures of a sweep by thermodynamic integration of the
+/// energy over β = 1/T, βf(β) = βf(0) + ∫ e dβ', starting from the energy and entropy per site
+/// at infinite temperature (βf(0) = -s). The energy is interpolated linearly between β = 0 and
+/// the highest temperature, so the sweep should reach well above Tc.
+pub fn thermodynamic_integration(points: &[SweepPoint], infinite_temperature: (f32, f32)) -> Vec<Thermodynamics> {
+    let (infinite_energy, infinite_entropy) = infinite_temperature;
+
+    let mut energies: Vec<[f64; 2]> = points.iter()
+        .filter(|point| point.temperature > 0.0)
+        .filter_map(|point| Some([1.0 / point.temperature as f64, point.energy?.value as f64]))
+        .collect();
+    energies.sort_by(|a, b| a[0].total_cmp(&b[0]));
+    energies.insert(0, [0.0, infinite_energy as f64]);
+
+    let integrals = cumulative_trapezoid(&energies);
+
+    energies.iter().zip(integrals).skip(1)
+        .map(|([beta, energy], integral)| {
+            let free_energy = (integral - infinite_entropy as f64) / beta;
+            Thermodynamics {
+                temperature: (1.0 / beta) as f32,
+                free_energy: free_energy as f32,
+                entropy: (beta * (energy - free_energy)) as f32,
+            }
+        })
+        .collect()
+}
+
+/// Integral of y over x from the first point up to each point, by the trapezoidal rule
+pub fn cumulative_trapezoid(points: &[[f64; 2]]) -> Vec<f64> {
+    let mut integral = 0.0;
+    let mut integrals = Vec::with_capacity(points.len());
+
+    for (index, [x, y]) in points.iter().enumerate() {
+        if index > 0 {
+            let [previous_x, previous_y] = points[index - 1];
+            integral += 0.5 * (y + previous_y) * (x - previous_x);
+        }
+        integrals.push(integral);
+    }
+
+    integrals
+}
+
+/// The same temperature sweep run at several lattice sizes in turn, for finite-size scaling
+pub struct SizeSweep {
+    /// Side lengths to run, in order
+    pub sizes: Vec<usize>,
+    // points of every finished sweep, by side length
+    curves: Vec<(usize, Vec<SweepPoint>)>,
+    // index into sizes of the sweep running, None unless running
+    current: Option<usize>,
+}
+
+impl SizeSweep {
+    pub fn new(sizes: Vec<usize>) -> SizeSweep {
+        SizeSweep {
+            sizes,
+            curves: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Throw away any previous curves and begin, returning the first size to run
+    pub fn begin(&mut self) -> Option<usize> {
+        self.curves.clear();
+        self.current = (!self.sizes.is_empty()).then_some(0);
+        self.sizes.first().copied()
+    }
+
+    /// Keep the points of a finished sweep at the current size, returning the next size to run,
+    /// if there is one
+    pub fn finish(&mut self, points: &[SweepPoint]) -> Option<usize> {
+        let index = self.current?;
+        self.curves.push((self.sizes[index], points.to_vec()));
+
+        self.current = (index + 1 < self.sizes.len()).then_some(index + 1);
+        self.current.map(|index| self.sizes[index])
+    }
+
+    pub fn stop(&mut self) {
+        self.current = None;
+    }
+
+    /// Forget the finished curves
+    pub fn clear(&mut self) {
+        self.curves.clear();
+        self.current = None;
+    }
+
+    /// Side length being run, if running
+    pub fn current(&self) -> Option<usize> {
+        self.current.map(|index| self.sizes[index])
+    }
+
+    /// Side length and points of every finished sweep
+    pub fn curves(&self) -> &[(usize, Vec<SweepPoint>)] {
+        &self.curves
+    }
+}
+
+/// Temperatures at which an observable of two sweeps over the same temperatures crosses,
+/// interpolating linearly between the points either side. For the Binder cumulant of two sizes
+/// these estimate Tc with hardly any finite-size shift.
+pub fn crossings(a: &[SweepPoint], b: &[SweepPoint], observable: SweepObservable) -> Vec<f32> {
+    let differences: Vec<Option<(f32, f32)>> = a.iter().zip(b)
+        .map(|(a, b)| Some((a.temperature, observable.of(a)?.value - observable.of(b)?.value)))
+        .collect();
+
+    differences.windows(2)
+        .filter_map(|pair| {
+            let ((t0, d0), (t1, d1)) = (pair[0]?, pair[1]?);
+            ((d0 < 0.0) != (d1 < 0.0)).then(|| t0 + (t1 - t0) * d0 / (d0 - d1))
+        })
+        .collect()
+}
+
+/// Rescaled axes of a finite-size scaling plot. Near Tc an observable O of a lattice of side L
+/// scales as O = L^κ f((T - Tc) L^(1/ν)), so plotting O L^-κ against (T - Tc) L^(1/ν) collapses
+/// the curves of every size onto the one function f when Tc, ν and κ are right.
+#[derive(Debug, Clone, Copy)]
+pub struct DataCollapse {
+    pub critical_temperature: f32,
+    pub nu: f32,
+    /// κ, γ/ν for χ, -β/ν for |m|, 0 for U4
+    pub exponent: f32,
+}
+
+impl DataCollapse {
+    /// κ of the observable for the two-dimensional Ising model, None for the energy, which has
+    /// a regular part as well as the scaling one
+    pub fn ising_2d_exponent(observable: SweepObservable) -> Option<f32> {
+        match observable {
+            SweepObservable::AbsMagnetisation => Some(-0.125),
+            SweepObservable::Susceptibility => Some(1.75),
+            // α = 0, the peak only grows as ln L
+            SweepObservable::HeatCapacity => Some(0.0),
+            SweepObservable::BinderCumulant => Some(0.0),
+            // the dynamic exponent z of Metropolis
+            SweepObservable::AutocorrelationTime => Some(2.17),
+            SweepObservable::Energy => None,
+        }
+    }
+
+    /// Points of a sweep at side `size` on the rescaled axes
+    pub fn points(&self, size: usize, points: &[SweepPoint], observable: SweepObservable) -> Vec<[f64; 2]> {
+        let size = size as f64;
+        let x_scale = size.powf(1.0 / self.nu.max(0.01) as f64);
+        let y_scale = size.powf(-self.exponent as f64);
+
+        points.iter()
+            .filter_map(|point| Some([
+                (point.temperature - self.critical_temperature) as f64 * x_scale,
+                observable.of(point)?.value as f64 * y_scale,
+            ]))
+            .collect()
+    }
+
+    /// How far apart the rescaled curves are: the mean squared difference between each point and
+    /// the curve of the next size interpolated at it, relative to the spread of the values. Zero
+    /// for a perfect collapse, None without two curves which overlap.
+    pub fn residual(&self, curves: &[(usize, Vec<SweepPoint>)], observable: SweepObservable) -> Option<f64> {
+        let rescaled: Vec<Vec<[f64; 2]>> = curves.iter()
+            .map(|(size, points)| {
+                let mut points = self.points(*size, points, observable);
+                points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+                points
+            })
+            .collect();
+
+        let mut differences = Vec::new();
+        for pair in rescaled.windows(2) {
+            for [x, y] in &pair[0] {
+                let Some(index) = pair[1].windows(2).position(|segment| (segment[0][0]..=segment[1][0]).contains(x)) else {
+                    continue;
+                };
+                let ([x0, y0], [x1, y1]) = (pair[1][index], pair[1][index + 1]);
+                let interpolated = if x1 > x0 { y0 + (y1 - y0) * (x - x0) / (x1 - x0) } else { y0 };
+                differences.push((y - interpolated).powi(2));
+            }
+        }
+        if differences.is_empty() {
+            return None;
+        }
+
+        let values: Vec<f64> = rescaled.iter().flatten().map(|[_, y]| *y).collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let spread = values.iter().map(|y| (y - mean).powi(2)).sum::<f64>() / values.len() as f64;
+
+        Some(differences.iter().sum::<f64>() / differences.len() as f64 / spread.max(f64::EPSILON))
+    }
+}
+
+/// Exponent of a power law followed by an observable close to Tc, in terms of the reduced
+/// temperature t = (T - Tc) / Tc
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CriticalExponent {
+    /// |m| ∝ (-t)^β, below Tc
+    Beta,
+    /// χ ∝ t^-γ, above Tc
+    Gamma,
+    /// ξ ∝ t^-ν, above Tc
+    Nu,
+}
+
+impl CriticalExponent {
+    pub const ALL: [CriticalExponent; 3] = [CriticalExponent::Beta, CriticalExponent::Gamma, CriticalExponent::Nu];
+
+    /// Exact value for the two-dimensional Ising model
+    pub fn ising_2d(&self) -> f32 {
+        match self {
+            CriticalExponent::Beta => 0.125,
+            CriticalExponent::Gamma => 1.75,
+            CriticalExponent::Nu => 1.0,
+        }
+    }
+
+    fn observable(&self, point: &SweepPoint) -> Option<Estimate> {
+        match self {
+            CriticalExponent::Beta => point.abs_magnetisation,
+            CriticalExponent::Gamma => point.susceptibility,
+            CriticalExponent::Nu => point.correlation_length,
+        }
+    }
+
+    /// Points (ln |t|, ln y) of the sweep on the side of Tc the power law holds, with |t| inside
+    /// the window
+    pub fn log_points(&self, points: &[SweepPoint], critical_temperature: f32, window: [f32; 2]) -> Vec<[f64; 2]> {
+        points.iter()
+            .filter_map(|point| {
+                let reduced = (point.temperature - critical_temperature) / critical_temperature;
+                let below = *self == CriticalExponent::Beta;
+                let value = self.observable(point)?.value;
+
+                let inside = (reduced < 0.0) == below && (window[0]..=window[1]).contains(&reduced.abs());
+                (inside && value > 0.0).then(|| [(reduced.abs() as f64).ln(), (value as f64).ln()])
+            })
+            .collect()
+    }
+
+    /// Fit of a line to log_points(), and the exponent it gives with its error
+    pub fn fit(&self, points: &[SweepPoint], critical_temperature: f32, window: [f32; 2]) -> Option<(LinearFit, Estimate)> {
+        let line = fit::linear_fit(&self.log_points(points, critical_temperature, window))?;
+        let sign = if *self == CriticalExponent::Beta { 1.0 } else { -1.0 };
+
+        Some((line, Estimate { value: (sign * line.slope) as f32, error: line.slope_error.map(|error| error as f32) }))
+    }
+}
+
+impl Display for CriticalExponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CriticalExponent::Beta => f.write_str("β"),
+            CriticalExponent::Gamma => f.write_str("γ"),
+            CriticalExponent::Nu => f.write_str("ν"),
+        }
+    }
+}
+
+/// Temperature at which an observable of the points peaks, the vertex of the parabola through the
+/// highest point and its two neighbours (which are assumed evenly spaced). The error propagates
+/// the errors of those three values, if they all have one. None unless the highest point has a
+/// neighbour on each side, otherwise the peak may lie outside the sweep.
+pub fn peak_temperature(points: &[SweepPoint], observable: impl Fn(&SweepPoint) -> Option<Estimate>) -> Option<Estimate> {
+    let values: Vec<Estimate> = points.iter().map(&observable).collect::<Option<_>>()?;
+
+    let highest = (0..values.len()).max_by(|a, b| values[*a].value.total_cmp(&values[*b].value))?;
+    if highest == 0 || highest + 1 >= values.len() {
+        return None;
+    }
+
+    let [a, b, c] = [highest - 1, highest, highest + 1].map(|index| values[index].value as f64);
+    let spacing = (points[highest + 1].temperature - points[highest].temperature) as f64;
+    let curvature = a - 2.0 * b + c;
+    if curvature >= 0.0 {
+        return None;
+    }
+
+    let offset = (0.5 * spacing * (a - c) / curvature).clamp(-spacing.abs(), spacing.abs());
+    let temperature = points[highest].temperature as f64 + offset;
+
+    // derivatives of the vertex with respect to a, b and c
+    let derivatives = [c - b, a - c, b - a].map(|derivative| spacing * derivative / curvature.powi(2));
+    let error = [highest - 1, highest, highest + 1].iter().zip(derivatives)
+        .map(|(index, derivative)| values[*index].error.map(|error| (derivative * error as f64).powi(2)))
+        .sum::<Option<f64>>()
+        .map(|variance| variance.sqrt() as f32);
+
+    Some(Estimate { value: temperature as f32, error })
+}
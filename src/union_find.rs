@@ -0,0 +1,47 @@
+/// Disjoint sets of the numbers 0..n, for labelling clusters of sites.
+pub struct UnionFind {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl UnionFind {
+    /// Every element in a set of its own
+    pub fn new(n: usize) -> UnionFind {
+        UnionFind {
+            parents: (0..n).collect(),
+            sizes: vec![1; n],
+        }
+    }
+
+    /// Representative of the set containing `a`
+    pub fn find(&mut self, mut a: usize) -> usize {
+        while self.parents[a] != a {
+            // path halving
+            self.parents[a] = self.parents[self.parents[a]];
+            a = self.parents[a];
+        }
+        a
+    }
+
+    /// Merge the sets containing `a` and `b`, the smaller under the larger
+    pub fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.sizes[a] < self.sizes[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+
+        self.parents[b] = a;
+        self.sizes[a] += self.sizes[b];
+    }
+
+    /// Size of each set, in no particular order
+    pub fn set_sizes(&self) -> Vec<usize> {
+        (0..self.parents.len())
+            .filter(|a| self.parents[*a] == *a)
+            .map(|root| self.sizes[root])
+            .collect()
+    }
+}
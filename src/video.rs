@@ -0,0 +1,136 @@
+use std::{fmt::Display, io::{self, Write}, path::Path, process::{Command, Stdio}, sync::mpsc, thread::JoinHandle};
+
+/// Frames waiting for ffmpeg at most, more are dropped until it catches up
+const QUEUED_FRAMES: usize = 8;
+
+/// Container and codec of a recorded video
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+    /// H.264 in an MP4, which plays almost anywhere
+    Mp4,
+    /// VP9 in a WebM, for the web
+    WebM,
+}
+
+impl VideoFormat {
+    pub const ALL: [VideoFormat; 2] = [VideoFormat::Mp4, VideoFormat::WebM];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VideoFormat::Mp4 => "mp4",
+            VideoFormat::WebM => "webm",
+        }
+    }
+
+    fn codec_arguments(&self) -> &'static [&'static str] {
+        match self {
+            VideoFormat::Mp4 => &["-c:v", "libx264", "-crf", "18", "-pix_fmt", "yuv420p"],
+            VideoFormat::WebM => &["-c:v", "libvpx-vp9", "-crf", "30", "-b:v", "0", "-pix_fmt", "yuv420p"],
+        }
+    }
+}
+
+impl Display for VideoFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            VideoFormat::Mp4 => "MP4",
+            VideoFormat::WebM => "WebM",
+        })
+    }
+}
+
+/// Video being encoded by ffmpeg, which has to be installed and on the path. Frames are piped to
+/// it raw as they're captured, from a thread of their own, so a long run never has to fit in
+/// memory the way a GIF does. Only a few frames are queued, if ffmpeg falls behind the rest are
+/// dropped.
+pub struct VideoRecorder {
+    frames: mpsc::SyncSender<Vec<u8>>,
+    writer: JoinHandle<io::Result<()>>,
+    width: usize,
+    height: usize,
+    count: usize,
+    dropped: usize,
+}
+
+impl VideoRecorder {
+    /// Start encoding a video of RGB frames `width` × `height` pixels to `path`, played back at
+    /// `fps` frames per second. Frames are scaled up without smoothing so their longer side is
+    /// `size` pixels.
+    pub fn start(path: &Path, format: VideoFormat, width: usize, height: usize, size: usize, fps: u32) -> io::Result<VideoRecorder> {
+        // the shorter side is kept even, as yuv420p needs
+        let scale = format!("scale=w='if(gte(iw,ih),{size},-2)':h='if(gte(iw,ih),-2,{size})':flags=neighbor");
+
+        let mut child = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pixel_format", "rgb24"])
+            .args(["-video_size", &format!("{}x{}", width, height)])
+            .args(["-framerate", &fps.to_string()])
+            .args(["-i", "-", "-vf", &scale])
+            .args(format.codec_arguments())
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::NotFound => io::Error::new(err.kind(), "ffmpeg wasn't found, it needs to be installed to record videos"),
+                _ => err,
+            })?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+
+        let (frames, receiver) = mpsc::sync_channel::<Vec<u8>>(QUEUED_FRAMES);
+        let writer = std::thread::spawn(move || {
+            // runs until the recorder is finished, or stops early if ffmpeg does
+            let written = receiver.iter().try_for_each(|frame| stdin.write_all(&frame));
+            drop(stdin);
+
+            let output = child.wait_with_output()?;
+            if !output.status.success() {
+                return Err(io::Error::other(format!("ffmpeg failed: {}", String::from_utf8_lossy(&output.stderr).trim())));
+            }
+            written
+        });
+
+        Ok(VideoRecorder {
+            frames,
+            writer,
+            width,
+            height,
+            count: 0,
+            dropped: 0,
+        })
+    }
+
+    /// Queue a frame to be encoded, or drop it if the queue is full. False if it isn't the size of
+    /// the video.
+    pub fn push(&mut self, rgb: Vec<u8>, width: usize, height: usize) -> bool {
+        if (width, height) != (self.width, self.height) {
+            return false;
+        }
+
+        match self.frames.try_send(rgb) {
+            Ok(()) => self.count += 1,
+            Err(mpsc::TrySendError::Full(_)) => self.dropped += 1,
+            // the writer has stopped, which finish() reports
+            Err(mpsc::TrySendError::Disconnected(_)) => {},
+        }
+        true
+    }
+
+    /// Frames queued so far
+    pub fn frame_count(&self) -> usize {
+        self.count
+    }
+
+    /// Frames dropped so far because ffmpeg hadn't caught up
+    pub fn dropped_count(&self) -> usize {
+        self.dropped
+    }
+
+    /// Stop taking frames. The thread returned finishes encoding the ones queued and closes the
+    /// file.
+    pub fn finish(self) -> JoinHandle<io::Result<()>> {
+        drop(self.frames);
+        self.writer
+    }
+}
@@ -0,0 +1,379 @@
+use std::{sync::{mpsc, Arc, Mutex}, time::{Duration, Instant}};
+
+use crate::{lattice::{Lattice, SweepOrder, FieldProfile}, measurement::Sample};
+
+/// Longest time a batch of sweeps runs without checking for new commands.
+const MAX_CHUNK_TIME: f32 = 0.02;
+
+/// Parameter updates sent to the simulation thread.
+pub enum Command {
+    /// Replace the simulated lattice
+    Replace(Box<Lattice>),
+    Temperature(f32),
+    /// Difference in temperature between the right and left edges
+    TemperatureGradient(f32),
+    MagneticField(f32),
+    /// Transverse field Γ, for a lattice with Trotter slices
+    TransverseField(f32),
+    /// Multiple of the magnetic field at each site
+    FieldProfile(FieldProfile),
+    SweepOrder(SweepOrder),
+    /// Batches per second and sweeps per batch
+    Rate { fps: f32, sweeps_per_frame: usize },
+    Paused(bool),
+    /// Run this many sweeps now, even while paused
+    Step(usize),
+    /// Sweeps between samples, starting a new generation of them
+    MeasureInterval(u64),
+    /// Run a replica of the lattice alongside it, for the overlap between the two
+    Replica(bool),
+    /// Keep track of how often each site flips, see Lattice::set_track_activity
+    TrackActivity(bool),
+    #[cfg(feature = "gpu")]
+    Gpu(Option<crate::gpu::GpuBackend>),
+}
+
+/// Copy of the lattice after a batch of sweeps.
+pub struct Snapshot {
+    pub lattice: Lattice,
+    /// Total sweeps performed on this lattice
+    pub sweeps: u64,
+    /// Fraction of Metropolis moves accepted since the previous snapshot
+    pub acceptance_rate: Option<f32>,
+    /// Measurements since the previous snapshot the GUI took
+    pub samples: Vec<Sample>,
+}
+
+/// Handle to a Monte Carlo loop running on its own thread, so slow sweeps don't block the GUI.
+/// Commands go in through a channel, snapshots come out through a slot which always holds the
+/// most recent one. The thread exits when the handle is dropped.
+pub struct Simulation {
+    commands: mpsc::Sender<Command>,
+    snapshot: Arc<Mutex<Option<Snapshot>>>,
+}
+
+impl Simulation {
+    pub fn spawn(lattice: Lattice, fps: f32, sweeps_per_frame: usize) -> Simulation {
+        let (command_sender, command_receiver) = mpsc::channel();
+        let snapshot = Arc::new(Mutex::new(None));
+        let worker_snapshot = snapshot.clone();
+
+        std::thread::spawn(move || {
+            Worker {
+                lattice,
+                replica: None,
+                track_activity: false,
+                sweeps: 0,
+                fps,
+                sweeps_per_frame,
+                paused: false,
+                sweep_time: 0.0,
+                published_flips: (0, 0),
+                generation: 0,
+                sweeps_since_change: 0,
+                measure_interval: 1,
+                samples: Vec::new(),
+                commands: command_receiver,
+                snapshot: worker_snapshot,
+                #[cfg(feature = "gpu")]
+                gpu: None,
+                #[cfg(feature = "gpu")]
+                gpu_stale: false,
+            }.run();
+        });
+
+        Simulation {
+            commands: command_sender,
+            snapshot,
+        }
+    }
+
+    pub fn send(&self, command: Command) {
+        // only fails if the simulation thread has panicked
+        let _ = self.commands.send(command);
+    }
+
+    /// Most recent snapshot since the last call, if any
+    pub fn latest(&self) -> Option<Snapshot> {
+        self.snapshot.lock().ok()?.take()
+    }
+}
+
+struct Worker {
+    lattice: Lattice,
+    // second copy of the lattice with the same couplings, updated independently
+    replica: Option<Lattice>,
+    // applies to every lattice simulated
+    track_activity: bool,
+    sweeps: u64,
+    fps: f32,
+    sweeps_per_frame: usize,
+    paused: bool,
+    // running estimate of the time per sweep, in seconds
+    sweep_time: f32,
+    // attempted and accepted flip counts at the last snapshot
+    published_flips: (u64, u64),
+    // see Sample
+    generation: u64,
+    sweeps_since_change: u64,
+    // sweeps between samples
+    measure_interval: u64,
+    // samples not yet published
+    samples: Vec<Sample>,
+    commands: mpsc::Receiver<Command>,
+    snapshot: Arc<Mutex<Option<Snapshot>>>,
+    #[cfg(feature = "gpu")]
+    gpu: Option<crate::gpu::GpuBackend>,
+    // whether the lattice has been swept on the CPU since its spins were last uploaded to the GPU
+    #[cfg(feature = "gpu")]
+    gpu_stale: bool,
+}
+
+impl Worker {
+    fn run(mut self) {
+        let mut next_batch = Instant::now();
+
+        loop {
+            // wait for the next batch, handling commands in the meantime
+            let command = if self.paused {
+                self.commands.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected)
+            } else {
+                self.commands.recv_timeout(next_batch.saturating_duration_since(Instant::now()))
+            };
+
+            match command {
+                Ok(command) => {
+                    self.handle(command);
+                    continue;
+                },
+                Err(mpsc::RecvTimeoutError::Timeout) => {},
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+
+            next_batch = Instant::now() + Duration::from_secs_f32(1.0 / self.fps);
+
+            let mut remaining = self.sweeps_per_frame;
+
+            while remaining > 0 && !self.paused {
+                // split long batches so commands (e.g. pause) are still handled promptly
+                let chunk = if self.sweep_time > 0.0 {
+                    ((MAX_CHUNK_TIME / self.sweep_time) as usize).clamp(1, remaining)
+                } else {
+                    1
+                };
+
+                let start = Instant::now();
+                self.sweep(chunk);
+                self.sweep_time = start.elapsed().as_secs_f32() / chunk as f32;
+
+                remaining -= chunk;
+
+                while let Ok(command) = self.commands.try_recv() {
+                    self.handle(command);
+                }
+            }
+
+            self.publish();
+        }
+    }
+
+    fn handle(&mut self, command: Command) {
+        match command {
+            Command::Replace(lattice) => {
+                self.lattice = *lattice;
+                self.lattice.set_track_activity(self.track_activity);
+                if self.replica.is_some() {
+                    self.replica = Some(self.new_replica());
+                }
+                self.sweeps = 0;
+                self.published_flips = (0, 0);
+                self.parameters_changed();
+
+                #[cfg(feature = "gpu")]
+                if let Some(gpu) = &mut self.gpu {
+                    gpu.upload(&self.lattice);
+                    self.gpu_stale = false;
+                }
+
+                self.publish();
+            },
+            Command::Temperature(temperature) => {
+                for lattice in self.lattices() {
+                    lattice.temperature = temperature;
+                }
+                self.parameters_changed();
+            },
+            Command::TemperatureGradient(temperature_gradient) => {
+                for lattice in self.lattices() {
+                    lattice.temperature_gradient = temperature_gradient;
+                }
+                self.parameters_changed();
+            },
+            Command::MagneticField(magnetic_field) => {
+                for lattice in self.lattices() {
+                    lattice.magnetic_field = magnetic_field;
+                }
+                self.parameters_changed();
+            },
+            Command::TransverseField(transverse_field) => {
+                for lattice in self.lattices() {
+                    lattice.transverse_field = transverse_field;
+                }
+                self.parameters_changed();
+            },
+            Command::FieldProfile(profile) => {
+                for lattice in self.lattices() {
+                    lattice.set_field_profile(&profile);
+                }
+                self.parameters_changed();
+            },
+            Command::SweepOrder(sweep_order) => {
+                for lattice in self.lattices() {
+                    lattice.sweep_order = sweep_order;
+                }
+            },
+            Command::Rate { fps, sweeps_per_frame } => {
+                self.fps = fps;
+                self.sweeps_per_frame = sweeps_per_frame;
+            },
+            Command::Paused(paused) => self.paused = paused,
+            Command::Step(count) => {
+                self.sweep(count);
+                self.publish();
+            },
+            Command::MeasureInterval(interval) => {
+                self.measure_interval = interval.max(1);
+                self.parameters_changed();
+            },
+            Command::TrackActivity(track) => {
+                self.download();
+                self.track_activity = track;
+                self.lattice.set_track_activity(track);
+            },
+            Command::Replica(enabled) => {
+                self.download();
+                self.replica = enabled.then(|| self.new_replica());
+                self.parameters_changed();
+            },
+            #[cfg(feature = "gpu")]
+            Command::Gpu(mut gpu) => {
+                self.download();
+                if let Some(gpu) = &mut gpu {
+                    gpu.upload(&self.lattice);
+                }
+                self.gpu = gpu;
+                self.gpu_stale = false;
+            },
+        }
+    }
+
+    /// The lattice and its replica, if any
+    fn lattices(&mut self) -> impl Iterator<Item = &mut Lattice> {
+        std::iter::once(&mut self.lattice).chain(self.replica.as_mut())
+    }
+
+    /// Replica of the current lattice, seeded from it so runs stay reproducible
+    fn new_replica(&self) -> Lattice {
+        self.lattice.replica(self.lattice.seed().wrapping_add(1))
+    }
+
+    /// Start a new generation of samples
+    fn parameters_changed(&mut self) {
+        self.generation += 1;
+        self.sweeps_since_change = 0;
+    }
+
+    /// Run sweeps, stopping to take a sample every `measure_interval` sweeps
+    fn sweep(&mut self, count: usize) {
+        let mut remaining = count as u64;
+
+        while remaining > 0 {
+            let until_sample = self.measure_interval - self.sweeps_since_change % self.measure_interval;
+            let sweeps = remaining.min(until_sample);
+
+            self.run_sweeps(sweeps as usize);
+            self.sweeps_since_change += sweeps;
+            remaining -= sweeps;
+
+            if sweeps == until_sample {
+                self.download();
+                self.samples.push(Sample {
+                    generation: self.generation,
+                    sweeps_since_change: self.sweeps_since_change,
+                    energy: self.lattice.internal_energy() / self.lattice.spin_count() as f32,
+                    magnetisation: self.lattice.magnetisation(),
+                    structure_factor: self.lattice.structure_factor(),
+                    domain_wall_length: self.lattice.domain_wall_length(),
+                    overlap: self.replica.as_ref().map(|replica| self.lattice.overlap(replica)),
+                });
+            }
+        }
+    }
+
+    fn run_sweeps(&mut self, count: usize) {
+        // the replica always runs on the CPU
+        if let Some(replica) = &mut self.replica {
+            replica.epochs(count);
+        }
+
+        #[cfg(feature = "gpu")]
+        if let Some(gpu) = &mut self.gpu {
+            // the GPU kernel doesn't track activity
+            if gpu.size() == self.lattice.size() && self.lattice.uniform_coupling().is_some() && !self.lattice.tracks_activity() {
+                // picking up where the CPU left off
+                if self.gpu_stale {
+                    gpu.upload(&self.lattice);
+                    self.gpu_stale = false;
+                }
+                gpu.run(count, &mut self.lattice);
+                self.sweeps += count as u64;
+                return;
+            }
+        }
+
+        self.download();
+        self.lattice.epochs(count);
+        self.sweeps += count as u64;
+
+        #[cfg(feature = "gpu")]
+        {
+            self.gpu_stale = self.gpu.is_some();
+        }
+    }
+
+    /// Copy spins the GPU has changed back into the lattice, before anything reads them
+    fn download(&mut self) {
+        #[cfg(feature = "gpu")]
+        if let Some(gpu) = &mut self.gpu {
+            gpu.download(&mut self.lattice);
+        }
+    }
+
+    fn publish(&mut self) {
+        self.download();
+        let flips = (self.lattice.attempted_flips(), self.lattice.accepted_flips());
+        let attempted = flips.0 - self.published_flips.0;
+        let accepted = flips.1 - self.published_flips.1;
+
+        let acceptance_rate = if attempted > 0 {
+            Some(accepted as f32 / attempted as f32)
+        } else {
+            None
+        };
+
+        self.published_flips = flips;
+
+        // replaces the previous snapshot if the GUI hasn't taken it yet, keeping its samples
+        if let Ok(mut snapshot) = self.snapshot.lock() {
+            let mut samples = snapshot.take().map(|snapshot| snapshot.samples).unwrap_or_default();
+            samples.append(&mut self.samples);
+
+            *snapshot = Some(Snapshot {
+                lattice: self.lattice.clone(),
+                sweeps: self.sweeps,
+                acceptance_rate,
+                samples,
+            });
+        }
+    }
+}